@@ -1,39 +1,161 @@
 use std::io::Write;
+use std::path::PathBuf;
 
 use anyhow::{Result, bail};
 use serde_json::Value;
 use tokio::io;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 use vex_cli::proto::{
-    AgentEntry, ClientMessage, Frame, ServerMessage, read_frame, send_client_message,
+    AgentEntry, ClientMessage, ExitedAgent, Frame, ServerMessage, read_frame, send_client_message,
 };
 
 use super::client::{connect, request};
+use super::table::{Template, check_output_flags, print_json, truncate};
 
-fn print_agent_table(agents: &[AgentEntry]) {
+/// Placeholders for `vex agent list --format`.
+const FORMAT_FIELDS: &[&str] = &[
+    "session",
+    "claude_session",
+    "pid",
+    "cwd",
+    "detected",
+    "needs_intervention",
+    "prompts",
+    "last_prompt",
+    "prompt",
+];
+
+fn format_field(a: &AgentEntry, field: &str) -> String {
+    match field {
+        "session" => a.vex_session_id.to_string(),
+        "claude_session" => a.claude_session_id.clone(),
+        "pid" => a.claude_pid.to_string(),
+        "cwd" => a.cwd.display().to_string(),
+        "detected" => a.detected_at.to_rfc3339(),
+        "needs_intervention" => a.needs_intervention.to_string(),
+        "prompts" => a.prompt_count.to_string(),
+        "last_prompt" => a.last_prompt_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+        "prompt" => prompt_text(a),
+        _ => unreachable!("checked by Template::parse"),
+    }
+}
+
+/// The last prompt sent through vex, on one line.
+fn prompt_text(a: &AgentEntry) -> String {
+    a.last_prompt
+        .as_deref()
+        .unwrap_or_default()
+        .replace('\n', " ")
+}
+
+/// Agents whose last prompt contains `pattern`, ignoring case.
+fn filter_by_prompt(agents: Vec<AgentEntry>, pattern: &str) -> Vec<AgentEntry> {
+    let pattern = pattern.to_lowercase();
+    agents
+        .into_iter()
+        .filter(|a| {
+            a.last_prompt
+                .as_ref()
+                .is_some_and(|p| p.to_lowercase().contains(&pattern))
+        })
+        .collect()
+}
+
+fn print_agent_table(agents: &[AgentEntry], full_prompt: bool) {
     println!(
-        "{:<36}  {:<12}  {:<6}  CWD",
-        "VEX SESSION", "CLAUDE ID", "PID"
+        "{:<36}  {:<12}  {:<6}  {:<7}  {:<30}  CWD",
+        "VEX SESSION", "CLAUDE ID", "PID", "PROMPTS", "LAST PROMPT"
     );
     for a in agents {
+        let prompt = prompt_text(a);
         println!(
-            "{:<36}  {:<12}  {:<6}  {}",
+            "{:<36}  {:<12}  {:<6}  {:<7}  {:<30}  {}",
             a.vex_session_id,
-            &a.claude_session_id[..a.claude_session_id.len().min(12)],
+            a.claude_session_id.chars().take(12).collect::<String>(),
             a.claude_pid,
+            a.prompt_count,
+            if full_prompt {
+                prompt
+            } else {
+                truncate(&prompt, 30)
+            },
             a.cwd.display(),
         );
     }
 }
 
-pub async fn agent_list(port: u16) -> Result<()> {
-    let resp = request(port, &ClientMessage::AgentList).await?;
+fn print_exited_table(exited: &[ExitedAgent]) {
+    println!(
+        "{:<36}  {:<14}  {:<19}  COMMAND",
+        "VEX SESSION", "STATUS", "ENDED"
+    );
+    for e in exited {
+        println!(
+            "{:<36}  {:<14}  {:<19}  {}",
+            e.vex_session_id,
+            exit_status(e.exit_code),
+            e.ended_at.format("%Y-%m-%d %H:%M:%S"),
+            truncate(&e.command, 40),
+        );
+    }
+}
+
+fn exit_status(code: Option<i32>) -> String {
+    match code {
+        Some(0) => "completed".to_string(),
+        Some(code) => format!("failed ({})", code),
+        None => "killed".to_string(),
+    }
+}
+
+pub async fn agent_list(
+    port: u16,
+    format: Option<&str>,
+    grep: Option<&str>,
+    full_prompt: bool,
+    json: bool,
+) -> Result<()> {
+    check_output_flags(json, format)?;
+    let template = format
+        .map(|f| Template::parse(f, FORMAT_FIELDS))
+        .transpose()?;
+    // The daemon sends a preview of each prompt unless the full text is
+    // printed or searched
+    let resp = request(
+        port,
+        &ClientMessage::AgentList {
+            full_prompt: full_prompt || grep.is_some(),
+        },
+    )
+    .await?;
     match resp {
-        ServerMessage::AgentListResponse { agents } => {
-            if agents.is_empty() {
+        ServerMessage::AgentListResponse { agents, exited } => {
+            // Exited agents have no prompt to match, so a search leaves them out
+            let (agents, exited) = match grep {
+                Some(pattern) => (filter_by_prompt(agents, pattern), Vec::new()),
+                None => (agents, exited),
+            };
+            if json {
+                return print_json(&serde_json::json!({ "agents": agents, "exited": exited }));
+            }
+            if let Some(template) = template {
+                // Only live agents, so every row has every field
+                for a in &agents {
+                    println!("{}", template.render(|f| format_field(a, f)));
+                }
+                return Ok(());
+            }
+            if agents.is_empty() && grep.is_some() {
+                println!("no agents with a matching prompt");
+            } else if agents.is_empty() {
                 println!("no agents detected");
             } else {
-                print_agent_table(&agents);
+                print_agent_table(&agents, full_prompt);
+            }
+            if !exited.is_empty() {
+                println!();
+                print_exited_table(&exited);
             }
             Ok(())
         }
@@ -42,14 +164,16 @@ pub async fn agent_list(port: u16) -> Result<()> {
     }
 }
 
-pub async fn agent_notifications(port: u16) -> Result<()> {
+pub async fn agent_notifications(port: u16, json: bool) -> Result<()> {
     let resp = request(port, &ClientMessage::AgentNotifications).await?;
     match resp {
-        ServerMessage::AgentListResponse { agents } => {
-            if agents.is_empty() {
+        ServerMessage::AgentListResponse { agents, .. } => {
+            if json {
+                print_json(&agents)?;
+            } else if agents.is_empty() {
                 println!("no agents need intervention");
             } else {
-                print_agent_table(&agents);
+                print_agent_table(&agents, false);
             }
             Ok(())
         }
@@ -60,7 +184,128 @@ pub async fn agent_notifications(port: u16) -> Result<()> {
 
 pub async fn agent_watch(port: u16, session_id_prefix: &str, show_thinking: bool) -> Result<()> {
     let session_id = resolve_agent_session(port, session_id_prefix).await?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(stream_conversation(port, session_id, 0, show_thinking, tx));
+    while let Some((_, event)) = rx.recv().await {
+        match event {
+            WatchEvent::Line(text) => {
+                println!("{}", text);
+                let _ = std::io::stdout().flush();
+            }
+            WatchEvent::Ended(reason) => {
+                eprintln!("[{}]", reason);
+                break;
+            }
+            WatchEvent::Failed(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Follow several agents at once, each line tagged with the agent's
+/// directory name and short session id. Agents that end are reported and
+/// the rest are followed until none are left.
+pub async fn agent_watch_many(
+    port: u16,
+    session_id_prefixes: &[String],
+    all: bool,
+    show_thinking: bool,
+) -> Result<()> {
+    let agents = match request(port, &ClientMessage::AgentList { full_prompt: false }).await? {
+        ServerMessage::AgentListResponse { agents, .. } => agents,
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    };
+    let mut watched = Vec::new();
+    if all {
+        watched.extend(agents.iter().map(|a| a.vex_session_id));
+    } else {
+        for prefix in session_id_prefixes {
+            let id = match_agent(&agents, prefix)?;
+            if !watched.contains(&id) {
+                watched.push(id);
+            }
+        }
+    }
+    if watched.is_empty() {
+        bail!("no agents to watch");
+    }
+
+    let tags: Vec<String> = watched
+        .iter()
+        .map(|&id| watch_tag(id, agents.iter().find(|a| a.vex_session_id == id)))
+        .collect();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    for (key, &id) in watched.iter().enumerate() {
+        tokio::spawn(stream_conversation(
+            port,
+            id,
+            key,
+            show_thinking,
+            tx.clone(),
+        ));
+    }
+    drop(tx);
+
+    let mut remaining = watched.len();
+    while let Some((key, event)) = rx.recv().await {
+        let tag = &tags[key];
+        match event {
+            WatchEvent::Line(text) => {
+                for line in text.lines() {
+                    println!("[{}] {}", tag, line);
+                }
+                let _ = std::io::stdout().flush();
+            }
+            WatchEvent::Ended(reason) => {
+                eprintln!("[{}] {}", tag, reason);
+                remaining -= 1;
+            }
+            WatchEvent::Failed(e) => {
+                eprintln!("[{}] error: {}", tag, e);
+                remaining -= 1;
+            }
+        }
+        if remaining == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+enum WatchEvent {
+    Line(String),
+    /// The stream closed normally; the text says why.
+    Ended(&'static str),
+    Failed(anyhow::Error),
+}
+
+type WatchSender = mpsc::UnboundedSender<(usize, WatchEvent)>;
+
+/// Send an agent's conversation to `tx`, tagged with `key`, until it ends.
+/// Exactly one `Ended` or `Failed` is sent last, unless the receiver has
+/// gone away.
+async fn stream_conversation(
+    port: u16,
+    session_id: Uuid,
+    key: usize,
+    show_thinking: bool,
+    tx: WatchSender,
+) {
+    let last = match follow_conversation(port, session_id, key, show_thinking, &tx).await {
+        Ok(reason) => WatchEvent::Ended(reason),
+        Err(e) => WatchEvent::Failed(e),
+    };
+    let _ = tx.send((key, last));
+}
 
+async fn follow_conversation(
+    port: u16,
+    session_id: Uuid,
+    key: usize,
+    show_thinking: bool,
+    tx: &WatchSender,
+) -> Result<&'static str> {
     let stream = connect(port).await?;
     let (mut reader, mut writer) = io::split(stream);
 
@@ -72,35 +317,63 @@ pub async fn agent_watch(port: u16, session_id_prefix: &str, show_thinking: bool
                 let msg: ServerMessage = serde_json::from_slice(&data)?;
                 match msg {
                     ServerMessage::AgentConversationLine { line, .. } => {
-                        format_conversation_line(&line, show_thinking);
-                    }
-                    ServerMessage::AgentWatchEnd { .. } => {
-                        eprintln!("[agent ended]");
-                        break;
-                    }
-                    ServerMessage::Error { message } => {
-                        bail!("{}", message);
+                        for text in format_conversation_line(&line, show_thinking) {
+                            if tx.send((key, WatchEvent::Line(text))).is_err() {
+                                return Ok("watch stopped");
+                            }
+                        }
                     }
+                    ServerMessage::AgentWatchEnd { .. } => return Ok("agent ended"),
+                    ServerMessage::Error { message } => bail!("{}", message),
                     _ => {}
                 }
             }
             Some(Frame::Data(_)) => {}
-            None => {
-                eprintln!("[server disconnected]");
-                break;
-            }
+            None => return Ok("server disconnected"),
         }
     }
+}
 
-    Ok(())
+/// `<dir>/<short id>`, where the directory is usually the workstream name.
+fn watch_tag(id: Uuid, agent: Option<&AgentEntry>) -> String {
+    let short: String = id.to_string().chars().take(8).collect();
+    match agent
+        .and_then(|a| a.cwd.file_name())
+        .map(|name| name.to_string_lossy())
+    {
+        Some(dir) => format!("{}/{}", dir, short),
+        None => short,
+    }
+}
+
+/// Parse a `KEY=VALUE` pair for `--env`. Only the first `=` separates the
+/// key; the value is passed through verbatim (no shell is involved).
+pub fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))?;
+    if key.is_empty() {
+        return Err(format!("missing variable name in '{}'", s));
+    }
+    Ok((key.to_string(), value.to_string()))
 }
 
-pub async fn agent_spawn(port: u16, repo: &str, workstream: Option<&str>) -> Result<String> {
+pub async fn agent_spawn(
+    port: u16,
+    repo: &str,
+    workstream: Option<&str>,
+    env: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+    profile: Option<String>,
+) -> Result<String> {
     let resp = request(
         port,
         &ClientMessage::AgentSpawn {
             repo: repo.to_string(),
             workstream: workstream.map(String::from),
+            env,
+            cwd,
+            profile,
         },
     )
     .await?;
@@ -115,6 +388,31 @@ pub async fn agent_spawn(port: u16, repo: &str, workstream: Option<&str>) -> Res
     }
 }
 
+/// Start an ended agent again; `session_id_prefix` is matched against
+/// ended agents. Prints and returns the new session's ID.
+pub async fn agent_restart(port: u16, session_id_prefix: &str) -> Result<String> {
+    let session_id = match session_id_prefix.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => match request(port, &ClientMessage::AgentList { full_prompt: false }).await? {
+            ServerMessage::AgentListResponse { exited, .. } => {
+                match_exited(&exited, session_id_prefix)?
+            }
+            ServerMessage::Error { message } => bail!("{}", message),
+            other => bail!("unexpected response: {:?}", other),
+        },
+    };
+    let resp = request(port, &ClientMessage::AgentRestart { session_id }).await?;
+    match resp {
+        ServerMessage::SessionCreated { id } => {
+            let id_str = id.to_string();
+            println!("{}", id_str);
+            Ok(id_str)
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
 pub async fn agent_prompt(
     port: u16,
     session_id_prefix: &str,
@@ -144,6 +442,20 @@ pub async fn agent_prompt(
     Ok(())
 }
 
+pub async fn agent_logs(port: u16, session_id_prefix: &str, lines: Option<u32>) -> Result<()> {
+    let session_id = resolve_agent_session(port, session_id_prefix).await?;
+    let resp = request(port, &ClientMessage::AgentLogs { session_id, lines }).await?;
+    match resp {
+        ServerMessage::AgentLogs { content, .. } => {
+            print!("{}", content);
+            std::io::stdout().flush()?;
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
 async fn resolve_agent_session(port: u16, prefix: &str) -> Result<Uuid> {
     // Try parsing as a full UUID first
     if let Ok(id) = prefix.parse::<Uuid>() {
@@ -151,28 +463,48 @@ async fn resolve_agent_session(port: u16, prefix: &str) -> Result<Uuid> {
     }
 
     // Otherwise, list agents and match by prefix
-    let resp = request(port, &ClientMessage::AgentList).await?;
+    let resp = request(port, &ClientMessage::AgentList { full_prompt: false }).await?;
     match resp {
-        ServerMessage::AgentListResponse { agents } => {
-            let matches: Vec<_> = agents
-                .iter()
-                .filter(|a| a.vex_session_id.to_string().starts_with(prefix))
-                .collect();
-            match matches.len() {
-                0 => bail!("no agent matching prefix '{}'", prefix),
-                1 => Ok(matches[0].vex_session_id),
-                n => bail!("ambiguous prefix '{}' matches {} agents", prefix, n),
-            }
-        }
+        ServerMessage::AgentListResponse { agents, .. } => match_agent(&agents, prefix),
         ServerMessage::Error { message } => bail!("{}", message),
         other => bail!("unexpected response: {:?}", other),
     }
 }
 
-fn format_conversation_line(line: &str, show_thinking: bool) {
+fn match_agent(agents: &[AgentEntry], prefix: &str) -> Result<Uuid> {
+    if let Ok(id) = prefix.parse::<Uuid>() {
+        return Ok(id);
+    }
+    let matches: Vec<_> = agents
+        .iter()
+        .filter(|a| a.vex_session_id.to_string().starts_with(prefix))
+        .collect();
+    match matches.len() {
+        0 => bail!("no agent matching prefix '{}'", prefix),
+        1 => Ok(matches[0].vex_session_id),
+        n => bail!("ambiguous prefix '{}' matches {} agents", prefix, n),
+    }
+}
+
+fn match_exited(exited: &[ExitedAgent], prefix: &str) -> Result<Uuid> {
+    let matches: Vec<Uuid> = exited
+        .iter()
+        .map(|e| e.vex_session_id)
+        .filter(|id| id.to_string().starts_with(prefix))
+        .collect();
+    match matches.len() {
+        0 => bail!("no ended agent matching prefix '{}'", prefix),
+        1 => Ok(matches[0]),
+        n => bail!("ambiguous prefix '{}' matches {} ended agents", prefix, n),
+    }
+}
+
+/// The text to show for one line of an agent's conversation log, if any.
+fn format_conversation_line(line: &str, show_thinking: bool) -> Vec<String> {
+    let mut out = Vec::new();
     let parsed: Result<Value, _> = serde_json::from_str(line);
     let Ok(v) = parsed else {
-        return;
+        return out;
     };
 
     let msg_type = v.get("type").and_then(|t| t.as_str()).unwrap_or("");
@@ -182,24 +514,23 @@ fn format_conversation_line(line: &str, show_thinking: bool) {
             if let Some(content) = v.get("message").and_then(|m| m.get("content"))
                 && let Some(text) = extract_text(content)
             {
-                println!("> {}", text);
+                out.push(format!("> {}", text));
             }
         }
         "assistant" => {
             if let Some(content) = v.get("message").and_then(|m| m.get("content")) {
-                format_assistant_content(content, show_thinking);
+                format_assistant_content(content, show_thinking, &mut out);
             }
         }
         _ => {}
     }
-
-    let _ = std::io::stdout().flush();
+    out
 }
 
-fn format_assistant_content(content: &Value, show_thinking: bool) {
+fn format_assistant_content(content: &Value, show_thinking: bool, out: &mut Vec<String>) {
     match content {
         Value::String(s) => {
-            println!("{}", s);
+            out.push(s.clone());
         }
         Value::Array(items) => {
             for item in items {
@@ -207,7 +538,7 @@ fn format_assistant_content(content: &Value, show_thinking: bool) {
                 match item_type {
                     "text" => {
                         if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
-                            println!("{}", text);
+                            out.push(text.to_string());
                         }
                     }
                     "tool_use" => {
@@ -216,14 +547,13 @@ fn format_assistant_content(content: &Value, show_thinking: bool) {
                             .and_then(|n| n.as_str())
                             .unwrap_or("unknown");
                         let input = item.get("input").cloned().unwrap_or(Value::Null);
-                        let summary = tool_summary(name, &input);
-                        println!("{}", summary);
+                        out.push(tool_summary(name, &input));
                     }
                     "thinking" => {
                         if show_thinking
                             && let Some(text) = item.get("thinking").and_then(|t| t.as_str())
                         {
-                            println!("[thinking] {}", text);
+                            out.push(format!("[thinking] {}", text));
                         }
                     }
                     _ => {}
@@ -245,8 +575,7 @@ fn tool_summary(name: &str, input: &Value) -> String {
         }
         "Bash" => {
             let cmd = input.get("command").and_then(|c| c.as_str()).unwrap_or("?");
-            let short = if cmd.len() > 60 { &cmd[..60] } else { cmd };
-            format!("[tool: Bash] {}", short)
+            format!("[tool: Bash] {}", truncate(cmd, 60))
         }
         "Grep" | "Glob" => {
             let pat = input.get("pattern").and_then(|p| p.as_str()).unwrap_or("?");
@@ -279,3 +608,93 @@ fn extract_text(content: &Value) -> Option<String> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_env_var_splits_on_first_equals() {
+        assert_eq!(
+            parse_env_var("OPENAI_BASE_URL=http://h:1/v1?a=b").unwrap(),
+            ("OPENAI_BASE_URL".into(), "http://h:1/v1?a=b".into())
+        );
+    }
+
+    #[test]
+    fn parse_env_var_keeps_special_chars_verbatim() {
+        let (_, value) = parse_env_var(r#"KEY=it's "quoted" $(rm -rf /) `x`; y"#).unwrap();
+        assert_eq!(value, r#"it's "quoted" $(rm -rf /) `x`; y"#);
+        assert_eq!(parse_env_var("EMPTY=").unwrap().1, "");
+    }
+
+    #[test]
+    fn conversation_lines_skip_thinking_unless_asked() {
+        let line = r#"{"type":"assistant","message":{"content":[
+            {"type":"thinking","thinking":"hmm"},
+            {"type":"text","text":"done"},
+            {"type":"tool_use","name":"Bash","input":{"command":"cargo test"}}]}}"#;
+        assert_eq!(
+            format_conversation_line(line, false),
+            ["done", "[tool: Bash] cargo test"]
+        );
+        assert_eq!(format_conversation_line(line, true)[0], "[thinking] hmm");
+        assert!(format_conversation_line("not json", false).is_empty());
+    }
+
+    #[test]
+    fn watch_tags_name_the_agents_directory() {
+        let id: Uuid = "0f1e2d3c-0000-0000-0000-000000000000".parse().unwrap();
+        let agent = AgentEntry {
+            vex_session_id: id,
+            claude_session_id: String::new(),
+            claude_pid: 1,
+            cwd: PathBuf::from("/home/me/.vex/worktrees/vex/fix-login"),
+            detected_at: chrono::Utc::now(),
+            needs_intervention: false,
+            prompt_count: 0,
+            last_prompt_at: None,
+            last_prompt: None,
+        };
+        assert_eq!(watch_tag(id, Some(&agent)), "fix-login/0f1e2d3c");
+        assert_eq!(watch_tag(id, None), "0f1e2d3c");
+    }
+
+    #[test]
+    fn grep_matches_prompts_ignoring_case() {
+        let agent = |prompt: Option<&str>| AgentEntry {
+            vex_session_id: Uuid::new_v4(),
+            claude_session_id: String::new(),
+            claude_pid: 1,
+            cwd: PathBuf::from("/tmp"),
+            detected_at: chrono::Utc::now(),
+            needs_intervention: false,
+            prompt_count: prompt.is_some() as u32,
+            last_prompt_at: None,
+            last_prompt: prompt.map(String::from),
+        };
+        let agents = vec![
+            agent(Some("Fix the AUTH bug in login")),
+            agent(Some("write docs for the cache")),
+            agent(None),
+        ];
+        let prompts = |pattern| {
+            filter_by_prompt(agents.clone(), pattern)
+                .into_iter()
+                .map(|a| a.last_prompt.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(prompts("auth bug"), ["Fix the AUTH bug in login"]);
+        assert_eq!(
+            prompts("THE"),
+            ["Fix the AUTH bug in login", "write docs for the cache"]
+        );
+        assert!(prompts("deploy").is_empty());
+    }
+
+    #[test]
+    fn parse_env_var_rejects_malformed() {
+        assert!(parse_env_var("NOVALUE").is_err());
+        assert!(parse_env_var("=value").is_err());
+    }
+}
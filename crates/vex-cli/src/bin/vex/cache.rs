@@ -0,0 +1,147 @@
+//! Short-lived, opt-in cache of read-only listings for `--fast`.
+//!
+//! Only the list commands consult the cache; prefix resolution and every
+//! mutating command always talk to the daemon, so stale entries can never
+//! drive a destructive action. Any request that is not read-only wipes the
+//! cache before it is sent.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use vex_cli::proto::{ClientMessage, ServerMessage};
+
+use super::client::request;
+
+const CACHE_TTL_MS: i64 = 2000;
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    stored_at: DateTime<Utc>,
+    response: ServerMessage,
+}
+
+fn cache_path(vex_dir: &Path) -> PathBuf {
+    vex_dir.join("cache.json")
+}
+
+fn cache_key(port: u16, msg: &ClientMessage) -> Result<String> {
+    Ok(format!("{}:{}", port, serde_json::to_string(msg)?))
+}
+
+fn is_fresh(stored_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    let age = now - stored_at;
+    age >= Duration::zero() && age < Duration::milliseconds(CACHE_TTL_MS)
+}
+
+/// Requests that never change daemon state and are safe to serve from cache.
+pub fn is_read_only(msg: &ClientMessage) -> bool {
+    matches!(
+        msg,
+        ClientMessage::ListSessions
+            | ClientMessage::AgentList { .. }
+            | ClientMessage::AgentNotifications
+            | ClientMessage::RepoList
+            | ClientMessage::WorkstreamList { .. }
+            | ClientMessage::RepoIntrospectPath { .. }
+            | ClientMessage::Ping
+    )
+}
+
+fn load(vex_dir: &Path) -> HashMap<String, CacheEntry> {
+    std::fs::read_to_string(cache_path(vex_dir))
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn invalidate(vex_dir: &Path) {
+    let _ = std::fs::remove_file(cache_path(vex_dir));
+}
+
+/// Send a request, answering from the cache when `cache_dir` is given and a
+/// fresh entry exists. Errors from the daemon are never cached.
+pub async fn request_cached(
+    port: u16,
+    msg: &ClientMessage,
+    cache_dir: Option<&Path>,
+) -> Result<ServerMessage> {
+    let Some(vex_dir) = cache_dir.filter(|_| is_read_only(msg)) else {
+        return request(port, msg).await;
+    };
+
+    let key = cache_key(port, msg)?;
+    let mut entries = load(vex_dir);
+    let now = Utc::now();
+    if let Some(entry) = entries.get(&key)
+        && is_fresh(entry.stored_at, now)
+    {
+        return Ok(entry.response.clone());
+    }
+
+    let resp = request(port, msg).await?;
+    if !matches!(resp, ServerMessage::Error { .. }) {
+        entries.retain(|_, e| is_fresh(e.stored_at, now));
+        entries.insert(
+            key,
+            CacheEntry {
+                stored_at: now,
+                response: resp.clone(),
+            },
+        );
+        if let Ok(data) = serde_json::to_string(&entries) {
+            let _ = std::fs::write(cache_path(vex_dir), data);
+        }
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freshness_respects_ttl() {
+        let now = Utc::now();
+        assert!(is_fresh(now, now));
+        assert!(is_fresh(
+            now - Duration::milliseconds(CACHE_TTL_MS - 1),
+            now
+        ));
+        assert!(!is_fresh(now - Duration::milliseconds(CACHE_TTL_MS), now));
+        // Clock skew into the future is treated as stale
+        assert!(!is_fresh(now + Duration::seconds(1), now));
+    }
+
+    #[test]
+    fn keys_distinguish_port_and_request() {
+        let a = cache_key(1, &ClientMessage::RepoList).unwrap();
+        let b = cache_key(2, &ClientMessage::RepoList).unwrap();
+        let c = cache_key(
+            1,
+            &ClientMessage::WorkstreamList {
+                repo: None,
+                sort: None,
+                git_status: false,
+            },
+        )
+        .unwrap();
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn mutating_requests_are_not_read_only() {
+        assert!(is_read_only(&ClientMessage::RepoList));
+        assert!(!is_read_only(&ClientMessage::RepoRemove {
+            name: "x".into(),
+            delete_workstreams: false,
+        }));
+        assert!(!is_read_only(&ClientMessage::WorkstreamCreate {
+            repo: "x".into(),
+            name: "y".into(),
+        }));
+    }
+}
@@ -1,19 +1,84 @@
+use std::time::{Duration, Instant};
+
 use anyhow::{Result, bail};
 use tokio::io;
 use tokio::net::TcpStream;
 use vex_cli::proto::{ClientMessage, Frame, ServerMessage, read_frame, send_client_message};
 
 pub async fn connect(port: u16) -> Result<TcpStream> {
-    TcpStream::connect(("127.0.0.1", port)).await.map_err(|e| {
-        anyhow::anyhow!(
-            "could not connect to daemon on port {}: {} (is the daemon running?)",
-            port,
-            e
-        )
-    })
+    TcpStream::connect(("127.0.0.1", port))
+        .await
+        .map_err(|e| anyhow::anyhow!("could not connect to daemon on port {}: {}", port, e))
+}
+
+/// Suggest a next step for common failures. Matches on the rendered error
+/// text, which covers both client-side errors and daemon `Error` messages.
+pub fn hint_for(message: &str) -> Option<&'static str> {
+    // Every needle in an entry must match; the first matching entry wins.
+    const HINTS: &[(&[&str], &str)] = &[
+        (
+            &["could not connect to daemon"],
+            "start it with `vex daemon start`, or check `vex remote list` if you expected a remote",
+        ),
+        (
+            &["failed to establish SSH tunnel"],
+            "check that `ssh <host>` works on its own and that vex is installed on the remote",
+        ),
+        (
+            &["workstream '", "' not found for repo '"],
+            "run `vex workstream list` to see existing workstreams",
+        ),
+        (
+            &["repo '", "' not found"],
+            "run `vex repo list` to see registered repos",
+        ),
+        (&["ambiguous prefix"], "use a longer prefix or the full ID"),
+        (
+            &["no session matching"],
+            "run `vex session list` to see active sessions",
+        ),
+        (
+            &["session not found"],
+            "run `vex session list` to see active sessions",
+        ),
+        (
+            &["unknown agent profile"],
+            "define it under agent_profiles in the daemon's config.yml",
+        ),
+        (
+            &["no agent matching prefix"],
+            "run `vex agent list` to see detected agents",
+        ),
+        (
+            &["no ended agent matching prefix"],
+            "run `vex agent list` to see detected agents",
+        ),
+        (
+            &["no agent running in session"],
+            "run `vex agent list` to see detected agents",
+        ),
+        (
+            &["no agent found for session"],
+            "run `vex agent list` to see detected agents",
+        ),
+        (
+            &["daemon failed to start"],
+            "inspect the log with `vex daemon logs`",
+        ),
+    ];
+    HINTS
+        .iter()
+        .find(|(needles, _)| needles.iter().all(|n| message.contains(n)))
+        .map(|(_, hint)| *hint)
 }
 
 pub async fn request(port: u16, msg: &ClientMessage) -> Result<ServerMessage> {
+    if !super::cache::is_read_only(msg)
+        && let Ok(vex_dir) = super::vex_dir()
+    {
+        super::cache::invalidate(&vex_dir);
+    }
+
     let stream = connect(port).await?;
     let (mut reader, mut writer) = io::split(stream);
 
@@ -28,3 +93,116 @@ pub async fn request(port: u16, msg: &ClientMessage) -> Result<ServerMessage> {
         None => bail!("server closed connection"),
     }
 }
+
+/// Send several requests over one connection without waiting for each reply.
+/// Replies come back in request order, whatever order the daemon finished
+/// them in. Only the first reply to each request is kept, so this is not for
+/// streaming requests like `AgentWatch`.
+pub async fn request_all(port: u16, msgs: Vec<ClientMessage>) -> Result<Vec<ServerMessage>> {
+    if !msgs.iter().all(super::cache::is_read_only)
+        && let Ok(vex_dir) = super::vex_dir()
+    {
+        super::cache::invalidate(&vex_dir);
+    }
+
+    let stream = connect(port).await?;
+    let (mut reader, mut writer) = io::split(stream);
+
+    let mut replies: Vec<Option<ServerMessage>> = vec![None; msgs.len()];
+    for (request_id, message) in msgs.into_iter().enumerate() {
+        let tagged = ClientMessage::Tagged {
+            request_id: request_id as u64,
+            message: Box::new(message),
+        };
+        send_client_message(&mut writer, &tagged).await?;
+    }
+
+    while replies.iter().any(Option::is_none) {
+        match read_frame(&mut reader).await? {
+            Some(Frame::Control(data)) => match serde_json::from_slice(&data)? {
+                ServerMessage::Tagged {
+                    request_id,
+                    message,
+                } => {
+                    if let Some(slot) = replies.get_mut(request_id as usize) {
+                        slot.get_or_insert(*message);
+                    }
+                }
+                other => bail!("unexpected response: {:?}", other),
+            },
+            Some(Frame::Data(_)) => bail!("unexpected data frame"),
+            None => bail!("server closed connection"),
+        }
+    }
+    Ok(replies.into_iter().flatten().collect())
+}
+
+/// Round-trip a `Ping`, including the connect, and return how long it took.
+pub async fn ping(port: u16) -> Result<Duration> {
+    let start = Instant::now();
+    match request(port, &ClientMessage::Ping).await? {
+        ServerMessage::Pong => Ok(start.elapsed()),
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+pub fn format_rtt(rtt: Duration) -> String {
+    format!("{:.1}ms", rtt.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_for_common_errors() {
+        assert!(
+            hint_for("could not connect to daemon on port 6969: Connection refused")
+                .unwrap()
+                .contains("vex daemon start")
+        );
+        assert!(
+            hint_for("repo 'nope' not found")
+                .unwrap()
+                .contains("vex repo list")
+        );
+        assert!(
+            hint_for("workstream 'x' not found for repo 'y'")
+                .unwrap()
+                .contains("vex workstream list")
+        );
+        assert!(
+            hint_for("unknown agent profile 'x' (no agent_profiles in config.yml)")
+                .unwrap()
+                .contains("agent_profiles")
+        );
+        assert!(
+            hint_for("no agent running in session 5f1c2a9e-0000-0000-0000-000000000000")
+                .unwrap()
+                .contains("vex agent list")
+        );
+        assert!(
+            hint_for("ambiguous prefix 'a' matches 2 sessions")
+                .unwrap()
+                .contains("longer prefix")
+        );
+    }
+
+    #[test]
+    fn rtt_is_rendered_in_milliseconds() {
+        assert_eq!(format_rtt(Duration::from_micros(43_250)), "43.2ms");
+        assert_eq!(format_rtt(Duration::ZERO), "0.0ms");
+    }
+
+    #[test]
+    fn no_hint_for_unknown_errors() {
+        assert_eq!(hint_for("something unexpected"), None);
+        assert_eq!(
+            hint_for("path '/tmp/x' is already registered as repo 'x'"),
+            None
+        );
+        assert_eq!(hint_for("no agent_profiles in config.yml"), None);
+        assert_eq!(hint_for("no agents to watch"), None);
+    }
+}
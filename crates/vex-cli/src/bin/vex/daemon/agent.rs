@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -9,6 +11,7 @@ use tracing::debug;
 use uuid::Uuid;
 use vex_cli::proto::AgentEntry;
 
+use super::log_throttle::LogThrottle;
 use super::session::SessionManager;
 
 #[derive(Debug, Clone)]
@@ -20,10 +23,17 @@ pub struct AgentInfo {
     pub jsonl_path: PathBuf,
     pub detected_at: DateTime<Utc>,
     pub needs_intervention: bool,
+    pub prompt_count: u32,
+    pub last_prompt_at: Option<DateTime<Utc>>,
+    pub last_prompt: Option<String>,
 }
 
+/// How much of the last prompt an agent entry carries unless the full
+/// text is asked for; prompts can run to tens of KiB each.
+const PROMPT_PREVIEW_CHARS: usize = 200;
+
 impl AgentInfo {
-    pub fn to_entry(&self) -> AgentEntry {
+    pub fn to_entry(&self, full_prompt: bool) -> AgentEntry {
         AgentEntry {
             vex_session_id: self.vex_session_id,
             claude_session_id: self.claude_session_id.clone(),
@@ -31,7 +41,33 @@ impl AgentInfo {
             cwd: self.cwd.clone(),
             detected_at: self.detected_at,
             needs_intervention: self.needs_intervention,
+            prompt_count: self.prompt_count,
+            last_prompt_at: self.last_prompt_at,
+            last_prompt: self.last_prompt.as_deref().map(|p| {
+                if full_prompt {
+                    p.to_string()
+                } else {
+                    prompt_preview(p)
+                }
+            }),
+        }
+    }
+
+    pub fn record_prompt(&mut self, text: &str) {
+        self.prompt_count += 1;
+        self.last_prompt_at = Some(Utc::now());
+        self.last_prompt = Some(text.to_string());
+    }
+}
+
+/// The first `PROMPT_PREVIEW_CHARS` characters of `prompt`, with an
+/// ellipsis marking the cut.
+fn prompt_preview(prompt: &str) -> String {
+    match prompt.char_indices().nth(PROMPT_PREVIEW_CHARS - 1) {
+        Some((end, _)) if prompt.chars().count() > PROMPT_PREVIEW_CHARS => {
+            format!("{}…", &prompt[..end])
         }
+        _ => prompt.to_string(),
     }
 }
 
@@ -54,10 +90,17 @@ struct ClaudeSessionFile {
 pub fn spawn_detection_task(manager: Arc<SessionManager>, store: AgentStore) {
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(2));
+        let mut throttle = LogThrottle::new(std::time::Duration::from_secs(300));
         loop {
             interval.tick().await;
-            if let Err(e) = detect_agents(&manager, &store).await {
-                debug!("agent detection error: {}", e);
+            if let Err(e) = detect_agents(&manager, &store).await
+                && let Some(n) = throttle.check("detect", std::time::Instant::now())
+            {
+                if n == 0 {
+                    debug!("agent detection error: {}", e);
+                } else {
+                    debug!("agent detection error: {} ({} repeats suppressed)", e, n);
+                }
             }
         }
     });
@@ -122,12 +165,15 @@ async fn detect_agents(manager: &SessionManager, store: &AgentStore) -> anyhow::
                     jsonl_path,
                     detected_at: Utc::now(),
                     needs_intervention,
+                    prompt_count: 0,
+                    last_prompt_at: None,
+                    last_prompt: None,
                 },
             );
         }
     }
 
-    // Update store — preserve detected_at for existing entries
+    // Update store — preserve detected_at and prompt history for existing entries
     let mut agents = store.lock().await;
     for (id, mut info) in found {
         if let Some(existing) = agents.get(&id)
@@ -135,6 +181,9 @@ async fn detect_agents(manager: &SessionManager, store: &AgentStore) -> anyhow::
             && existing.claude_session_id == info.claude_session_id
         {
             info.detected_at = existing.detected_at;
+            info.prompt_count = existing.prompt_count;
+            info.last_prompt_at = existing.last_prompt_at;
+            info.last_prompt = existing.last_prompt.clone();
         }
         agents.insert(id, info);
     }
@@ -147,6 +196,41 @@ async fn detect_agents(manager: &SessionManager, store: &AgentStore) -> anyhow::
     Ok(())
 }
 
+/// Turn prompt text into PTY input for an agent's TUI. Line breaks become
+/// `\n` (a soft newline) so only the trailing `\r` submits, tabs become
+/// spaces, and other control characters are dropped so a prompt can't
+/// interrupt (Ctrl-C) or otherwise drive the agent.
+pub fn prompt_input(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut input: String = text
+        .chars()
+        .filter_map(|c| match c {
+            '\n' => Some('\n'),
+            '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect();
+    input.push('\r');
+    input
+}
+
+/// Resolve an agent command's program the way exec would: a name containing
+/// `/` is taken relative to `cwd`, anything else is searched for in
+/// `path_var`. Only executable files count.
+pub fn find_program(program: &str, path_var: Option<&OsStr>, cwd: &Path) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        let path = cwd.join(program);
+        return is_executable(&path).then_some(path);
+    }
+    std::env::split_paths(path_var?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
 /// Walk /proc/{pid}/stat parent chain upward to find a matching vex shell PID.
 /// Also checks if pid itself matches, for agent spawn sessions where the
 /// Claude process IS the session command (no intermediate shell).
@@ -231,3 +315,59 @@ fn derive_jsonl_path(home: &Path, cwd: &Path, session_id: &str) -> PathBuf {
         .join(&encoded_cwd)
         .join(format!("{}.jsonl", session_id))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_input_submits_once() {
+        assert_eq!(prompt_input("fix the tests"), "fix the tests\r");
+        assert_eq!(
+            prompt_input("line one\r\nline two\rthree"),
+            "line one\nline two\nthree\r"
+        );
+    }
+
+    #[test]
+    fn find_program_needs_an_executable_file() {
+        let dir = std::env::temp_dir().join(format!("vex-agent-test-{}", Uuid::new_v4()));
+        let bin = dir.join("bin");
+        std::fs::create_dir_all(bin.join("subdir")).unwrap();
+        std::fs::write(bin.join("agent"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(bin.join("agent"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        std::fs::write(bin.join("notes"), "").unwrap();
+        let path = std::env::join_paths([dir.join("missing"), bin.clone()]).unwrap();
+
+        let found = |program| find_program(program, Some(&path), &dir);
+        assert_eq!(found("agent"), Some(bin.join("agent")));
+        assert_eq!(found("bin/agent"), Some(dir.join("bin/agent")));
+        assert_eq!(found("nosuch-agent"), None);
+        assert_eq!(found("notes"), None);
+        assert_eq!(found("subdir"), None);
+        assert_eq!(find_program("agent", None, &dir), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prompt_preview_cuts_long_prompts_on_char_boundaries() {
+        assert_eq!(prompt_preview("fix the tests"), "fix the tests");
+        let exact = "é".repeat(PROMPT_PREVIEW_CHARS);
+        assert_eq!(prompt_preview(&exact), exact);
+
+        let preview = prompt_preview(&"é".repeat(32 * 1024));
+        assert_eq!(preview.chars().count(), PROMPT_PREVIEW_CHARS);
+        assert!(preview.ends_with('…'));
+    }
+
+    #[test]
+    fn prompt_input_drops_control_characters() {
+        // Ctrl-C, ESC sequences and backspace must not reach the agent
+        assert_eq!(prompt_input("stop\x03 now"), "stop now\r");
+        assert_eq!(prompt_input("\x1b[2Jclear"), "[2Jclear\r");
+        assert_eq!(prompt_input("a\x08b\tc"), "ab c\r");
+        assert_eq!(prompt_input("ünïcode ✓"), "ünïcode ✓\r");
+    }
+}
@@ -0,0 +1,202 @@
+//! `vex daemon check`: read-only validation of the daemon's state files.
+//!
+//! The stores silently fall back to empty state when a file doesn't parse,
+//! so hand-edited or corrupted files otherwise show up only as missing repos
+//! or workstreams. Nothing here mutates state or needs a running daemon.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::config::VexConfig;
+use super::{repo, workstream};
+
+pub struct Problem {
+    pub message: String,
+    pub fix: String,
+}
+
+fn problem(message: String, fix: impl Into<String>) -> Problem {
+    Problem {
+        message,
+        fix: fix.into(),
+    }
+}
+
+pub fn check(vex_dir: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if let Err(e) = VexConfig::load_strict(vex_dir) {
+        problems.push(problem(
+            format!("config.yml is invalid: {}", e),
+            "fix the YAML; until then the daemon ignores the whole file and uses defaults",
+        ));
+    }
+
+    let repos = match repo::read_persisted(vex_dir) {
+        Ok(repos) => repos,
+        Err(e) => {
+            problems.push(problem(
+                format!("repos.json is invalid: {}", e),
+                "fix or delete it; until then the daemon starts with no repos",
+            ));
+            HashMap::new()
+        }
+    };
+    check_repos(&repos, &mut problems);
+
+    match workstream::read_persisted(vex_dir) {
+        Ok(workstreams) => check_workstreams(&repos, &workstreams, &mut problems),
+        Err(e) => problems.push(problem(
+            format!("workstreams.json is invalid: {}", e),
+            "fix or delete it; until then the daemon starts with no workstreams",
+        )),
+    }
+
+    problems
+}
+
+fn check_repos(repos: &HashMap<String, PathBuf>, problems: &mut Vec<Problem>) {
+    let mut names: Vec<&String> = repos.keys().collect();
+    names.sort();
+
+    let mut by_path: HashMap<&PathBuf, &String> = HashMap::new();
+    for name in names {
+        let path = &repos[name];
+        if !path.is_dir() {
+            problems.push(problem(
+                format!(
+                    "repo '{}' points to a missing directory: {}",
+                    name,
+                    path.display()
+                ),
+                format!("restore the directory or run `vex repo remove {}`", name),
+            ));
+        }
+        if let Some(other) = by_path.insert(path, name) {
+            problems.push(problem(
+                format!(
+                    "repos '{}' and '{}' share the path {}",
+                    other,
+                    name,
+                    path.display()
+                ),
+                format!("remove one of them, e.g. `vex repo remove {}`", name),
+            ));
+        }
+    }
+}
+
+fn check_workstreams(
+    repos: &HashMap<String, PathBuf>,
+    workstreams: &[workstream::PersistedWorkstream],
+    problems: &mut Vec<Problem>,
+) {
+    for ws in workstreams {
+        let id = format!("{}/{}", ws.repo, ws.name);
+        match repos.get(&ws.repo) {
+            None => problems.push(problem(
+                format!(
+                    "workstream '{}' belongs to unregistered repo '{}'",
+                    id, ws.repo
+                ),
+                format!(
+                    "re-register it with `vex repo add {} {}`",
+                    ws.repo,
+                    ws.repo_path.display()
+                ),
+            )),
+            Some(path) if *path != ws.repo_path => problems.push(problem(
+                format!(
+                    "workstream '{}' was created from {} but repo '{}' now points to {}",
+                    id,
+                    ws.repo_path.display(),
+                    ws.repo,
+                    path.display()
+                ),
+                format!("run `vex workstream remove -r {} {}`", ws.repo, ws.name),
+            )),
+            Some(_) => {}
+        }
+        if !ws.worktree_path.is_dir() {
+            problems.push(problem(
+                format!(
+                    "worktree for '{}' is missing: {}",
+                    id,
+                    ws.worktree_path.display()
+                ),
+                format!("run `vex workstream remove -r {} {}`", ws.repo, ws.name),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vex-check-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn messages(problems: &[Problem]) -> Vec<&str> {
+        problems.iter().map(|p| p.message.as_str()).collect()
+    }
+
+    #[test]
+    fn empty_vex_dir_is_clean() {
+        let dir = scratch();
+        assert!(check(&dir).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_unparseable_files() {
+        let dir = scratch();
+        std::fs::write(dir.join("repos.json"), "{not json").unwrap();
+        std::fs::write(dir.join("workstreams.json"), "[]").unwrap();
+        std::fs::write(dir.join("config.yml"), "repos: [").unwrap();
+
+        let problems = check(&dir);
+        let msgs = messages(&problems);
+        assert_eq!(msgs.len(), 3, "{:?}", msgs);
+        assert!(msgs[0].starts_with("config.yml is invalid"));
+        assert!(msgs[1].starts_with("repos.json is invalid"));
+        assert!(msgs[2].starts_with("workstreams.json is invalid"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_dangling_references() {
+        let dir = scratch();
+        let repo_dir = dir.join("api");
+        std::fs::create_dir_all(&repo_dir).unwrap();
+        let repos = serde_json::json!({
+            "api": repo_dir,
+            "api-copy": repo_dir,
+            "gone": dir.join("gone"),
+        });
+        std::fs::write(dir.join("repos.json"), repos.to_string()).unwrap();
+        let workstreams = serde_json::json!({
+            "orphan": {
+                "feat": {
+                    "worktree_path": dir.join("wt"),
+                    "repo_path": dir.join("orphan"),
+                    "branch": "feat",
+                    "created_at": "2026-01-01T00:00:00Z"
+                }
+            }
+        });
+        std::fs::write(dir.join("workstreams.json"), workstreams.to_string()).unwrap();
+
+        let problems = check(&dir);
+        let msgs = messages(&problems);
+        assert_eq!(msgs.len(), 4, "{:?}", msgs);
+        assert!(msgs[0].contains("repos 'api' and 'api-copy' share"));
+        assert!(msgs[1].contains("repo 'gone' points to a missing directory"));
+        assert!(msgs[2].contains("unregistered repo 'orphan'"));
+        assert!(msgs[3].contains("worktree for 'orphan/feat' is missing"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,15 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 const DEFAULT_AGENT_COMMAND: &str = "claude --dangerously-skip-permissions";
+const DEFAULT_MAX_PROMPT_LEN: usize = 32 * 1024;
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_PREFETCH_INTERVAL_SECS: u64 = 900;
+const DEFAULT_PREFETCH_SPACING_SECS: u64 = 5;
+const DEFAULT_SCROLLBACK_REPLAY_BYTES: u64 = 1024 * 1024;
+const DEFAULT_AUTOREGISTER_DEPTH: usize = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VexConfig {
@@ -11,8 +17,26 @@ pub struct VexConfig {
     pub default_agent_command: String,
     #[serde(default)]
     pub repos: HashMap<String, RepoConfig>,
+    /// Named agent commands, picked with `vex agent spawn --profile`.
+    #[serde(default)]
+    pub agent_profiles: HashMap<String, AgentProfile>,
     #[serde(default)]
     pub hooks: HooksConfig,
+    /// Editor launched by `vex workstream create --open-editor`, detached
+    /// (a GUI editor). Falls back to `$VISUAL`, then `$EDITOR`, which are
+    /// run in the foreground as terminal editors.
+    #[serde(default)]
+    pub editor_command: Option<String>,
+    /// Longest prompt, in bytes, accepted by `AgentPrompt`. Very long input
+    /// typed into an agent's PTY tends to be mangled or dropped.
+    #[serde(default = "default_max_prompt_len")]
+    pub max_prompt_len: usize,
+    #[serde(default)]
+    pub prefetch: PrefetchConfig,
+    #[serde(default)]
+    pub scrollback: ScrollbackConfig,
+    #[serde(default)]
+    pub autoregister: AutoregisterConfig,
 }
 
 impl Default for VexConfig {
@@ -20,7 +44,13 @@ impl Default for VexConfig {
         Self {
             default_agent_command: default_agent_command(),
             repos: HashMap::new(),
+            agent_profiles: HashMap::new(),
             hooks: HooksConfig::default(),
+            editor_command: None,
+            max_prompt_len: default_max_prompt_len(),
+            prefetch: PrefetchConfig::default(),
+            scrollback: ScrollbackConfig::default(),
+            autoregister: AutoregisterConfig::default(),
         }
     }
 }
@@ -30,9 +60,95 @@ pub struct RepoConfig {
     pub agent_command: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentProfile {
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HooksConfig {
     pub on_workstream_create: Option<HookDef>,
+    /// Run in the worktree just before it is removed. Failures are logged
+    /// but never block the removal.
+    #[serde(default)]
+    pub on_workstream_remove: Option<HookDef>,
+    /// Per-command limit; a hook still running after this is killed.
+    #[serde(default = "default_hook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_workstream_create: None,
+            on_workstream_remove: None,
+            timeout_secs: default_hook_timeout_secs(),
+        }
+    }
+}
+
+/// Background `git fetch` of every registered repo. Off by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefetchConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Minimum time between fetches of the same repo (at least 60).
+    #[serde(default = "default_prefetch_interval_secs")]
+    pub interval_secs: u64,
+    /// Pause between consecutive fetches, to avoid hammering the remote.
+    #[serde(default = "default_prefetch_spacing_secs")]
+    pub spacing_secs: u64,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: default_prefetch_interval_secs(),
+            spacing_secs: default_prefetch_spacing_secs(),
+        }
+    }
+}
+
+/// Session output kept on disk while the session runs, so attaching clients
+/// get more history than the 64 KiB held in memory. Off by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrollbackConfig {
+    #[serde(default)]
+    pub persist: bool,
+    /// Most history sent to a client when it attaches.
+    #[serde(default = "default_scrollback_replay_bytes")]
+    pub replay_bytes: u64,
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        Self {
+            persist: false,
+            replay_bytes: default_scrollback_replay_bytes(),
+        }
+    }
+}
+
+/// Directories scanned at daemon startup for git repos to register. Empty
+/// by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoregisterConfig {
+    /// A leading `~/` is the home directory.
+    #[serde(default)]
+    pub dirs: Vec<PathBuf>,
+    /// How many levels below each directory to look (at most 3).
+    #[serde(default = "default_autoregister_depth")]
+    pub depth: usize,
+}
+
+impl Default for AutoregisterConfig {
+    fn default() -> Self {
+        Self {
+            dirs: Vec::new(),
+            depth: default_autoregister_depth(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,24 +161,121 @@ fn default_agent_command() -> String {
     DEFAULT_AGENT_COMMAND.to_string()
 }
 
+fn default_hook_timeout_secs() -> u64 {
+    DEFAULT_HOOK_TIMEOUT_SECS
+}
+
+fn default_prefetch_interval_secs() -> u64 {
+    DEFAULT_PREFETCH_INTERVAL_SECS
+}
+
+fn default_prefetch_spacing_secs() -> u64 {
+    DEFAULT_PREFETCH_SPACING_SECS
+}
+
+fn default_scrollback_replay_bytes() -> u64 {
+    DEFAULT_SCROLLBACK_REPLAY_BYTES
+}
+
+fn default_autoregister_depth() -> usize {
+    DEFAULT_AUTOREGISTER_DEPTH
+}
+
+fn default_max_prompt_len() -> usize {
+    DEFAULT_MAX_PROMPT_LEN
+}
+
 impl VexConfig {
     pub fn load(vex_dir: &Path) -> Self {
-        let path = vex_dir.join("config.yml");
-        std::fs::read_to_string(&path)
-            .ok()
-            .and_then(|data| serde_yaml::from_str(&data).ok())
-            .unwrap_or_default()
+        Self::load_strict(vex_dir).unwrap_or_default()
+    }
+
+    /// Like `load`, but surfaces parse errors instead of falling back to the
+    /// defaults. A missing file still yields the defaults.
+    pub fn load_strict(vex_dir: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(vex_dir.join("config.yml")) {
+            Ok(data) => Ok(serde_yaml::from_str(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
     }
 
     /// Get the agent command for a repo, falling back to the global default.
-    pub fn agent_command_for(&self, repo_name: &str) -> Vec<String> {
+    /// A named profile takes precedence over both.
+    pub fn agent_command_for(
+        &self,
+        repo_name: &str,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Vec<String>> {
+        if let Some(profile) = profile {
+            let Some(p) = self.agent_profiles.get(profile) else {
+                let mut known: Vec<&str> = self.agent_profiles.keys().map(String::as_str).collect();
+                known.sort();
+                if known.is_empty() {
+                    anyhow::bail!(
+                        "unknown agent profile '{}' (no agent_profiles in config.yml)",
+                        profile
+                    );
+                }
+                anyhow::bail!(
+                    "unknown agent profile '{}' (known: {})",
+                    profile,
+                    known.join(", ")
+                );
+            };
+            return Ok(shell_split(&p.command));
+        }
         let cmd_str = self
             .repos
             .get(repo_name)
             .and_then(|r| r.agent_command.as_deref())
             .unwrap_or(&self.default_agent_command);
-        shell_split(cmd_str)
+        Ok(shell_split(cmd_str))
     }
+
+    /// Reject prompts longer than `max_prompt_len`.
+    pub fn check_prompt_len(&self, text: &str) -> anyhow::Result<()> {
+        if text.len() > self.max_prompt_len {
+            anyhow::bail!(
+                "prompt is {} bytes, over max_prompt_len ({}); write the details to a file in the worktree and prompt the agent to read it",
+                text.len(),
+                self.max_prompt_len
+            );
+        }
+        Ok(())
+    }
+
+    /// Get the editor command, if one is configured here or in the
+    /// environment.
+    pub fn editor_command(&self) -> Option<Editor> {
+        resolve_editor(
+            self.editor_command.as_deref(),
+            std::env::var("VISUAL").ok().as_deref(),
+            std::env::var("EDITOR").ok().as_deref(),
+        )
+    }
+}
+
+/// An editor to open a worktree in.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Editor {
+    /// Program and arguments.
+    pub command: Vec<String>,
+    /// Launch detached rather than in this terminal: true for
+    /// `editor_command`, false for `$VISUAL`/`$EDITOR`.
+    pub detach: bool,
+}
+
+fn resolve_editor(
+    configured: Option<&str>,
+    visual: Option<&str>,
+    editor: Option<&str>,
+) -> Option<Editor> {
+    [(configured, true), (visual, false), (editor, false)]
+        .into_iter()
+        .filter_map(|(cmd, detach)| Some((shell_split(cmd?), detach)))
+        .find(|(command, _)| !command.is_empty())
+        .map(|(command, detach)| Editor { command, detach })
 }
 
 /// Split a command string into program + args, respecting simple quoting.
@@ -95,3 +308,104 @@ fn shell_split(s: &str) -> Vec<String> {
     }
     parts
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agent_profiles_override_repo_and_default_commands() {
+        let config: VexConfig = serde_yaml::from_str(
+            r#"
+default_agent_command: claude
+repos:
+  vex:
+    agent_command: claude --model sonnet
+agent_profiles:
+  opus:
+    command: "claude --model opus --append-system-prompt 'be terse'"
+  fast:
+    command: claude --model haiku
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.agent_command_for("other", None).unwrap(), ["claude"]);
+        assert_eq!(
+            config.agent_command_for("vex", None).unwrap(),
+            ["claude", "--model", "sonnet"]
+        );
+        assert_eq!(
+            config.agent_command_for("vex", Some("opus")).unwrap(),
+            [
+                "claude",
+                "--model",
+                "opus",
+                "--append-system-prompt",
+                "be terse"
+            ]
+        );
+
+        let err = config
+            .agent_command_for("vex", Some("gpt"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "unknown agent profile 'gpt' (known: fast, opus)");
+        let err = VexConfig::default()
+            .agent_command_for("vex", Some("opus"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no agent_profiles"), "{}", err);
+    }
+
+    #[test]
+    fn prompt_length_limit_is_inclusive() {
+        let config = VexConfig {
+            max_prompt_len: 8,
+            ..VexConfig::default()
+        };
+        assert!(config.check_prompt_len("12345678").is_ok());
+        let err = config.check_prompt_len("123456789").unwrap_err();
+        assert!(err.to_string().contains("max_prompt_len (8)"));
+    }
+
+    #[test]
+    fn max_prompt_len_defaults_when_omitted() {
+        let config: VexConfig = serde_yaml::from_str("default_agent_command: sh").unwrap();
+        assert_eq!(config.max_prompt_len, DEFAULT_MAX_PROMPT_LEN);
+    }
+
+    #[test]
+    fn prefetch_is_off_unless_enabled() {
+        let config: VexConfig = serde_yaml::from_str("default_agent_command: sh").unwrap();
+        assert!(!config.prefetch.enabled);
+        let config: VexConfig = serde_yaml::from_str("prefetch:\n  enabled: true").unwrap();
+        assert!(config.prefetch.enabled);
+        assert_eq!(
+            config.prefetch.interval_secs,
+            DEFAULT_PREFETCH_INTERVAL_SECS
+        );
+    }
+
+    #[test]
+    fn editor_prefers_config_then_visual_then_editor() {
+        let editor = |command: &[&str], detach| {
+            Some(Editor {
+                command: command.iter().map(|s| s.to_string()).collect(),
+                detach,
+            })
+        };
+        assert_eq!(
+            resolve_editor(Some("code --new-window"), Some("nvim"), Some("vi")),
+            editor(&["code", "--new-window"], true)
+        );
+        assert_eq!(
+            resolve_editor(None, Some("nvim"), Some("vi")),
+            editor(&["nvim"], false)
+        );
+        assert_eq!(
+            resolve_editor(Some("  "), None, Some("vi")),
+            editor(&["vi"], false)
+        );
+        assert_eq!(resolve_editor(None, None, None), None);
+    }
+}
@@ -0,0 +1,150 @@
+//! One-off commands run in a workstream's worktree (`WorkstreamExec`).
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use nix::sys::signal::{Signal, killpg};
+use nix::unistd::Pid;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Per stream. The reply must fit in one 1 MiB protocol frame even when
+/// JSON escaping grows control characters sixfold.
+pub const MAX_OUTPUT: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` when the command was killed by a signal.
+    pub code: Option<i32>,
+    /// Whether either stream went over `MAX_OUTPUT` and was cut short.
+    pub truncated: bool,
+}
+
+/// Run `command` with `sh -c` in `dir`. On timeout the command's whole
+/// process group is killed.
+pub async fn run(command: &str, dir: &Path, timeout: Duration) -> Result<ExecOutput> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("'{}' could not start: {}", command, e))?;
+    let pgid = child.id();
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let run = async {
+        let (stdout, stderr, status) =
+            tokio::join!(read_capped(stdout), read_capped(stderr), child.wait());
+        Ok::<_, anyhow::Error>((stdout?, stderr?, status?))
+    };
+    let ((stdout, out_cut), (stderr, err_cut), status) =
+        match tokio::time::timeout(timeout, run).await {
+            Ok(result) => result?,
+            Err(_) => {
+                if let Some(pgid) = pgid {
+                    let _ = killpg(Pid::from_raw(pgid as i32), Signal::SIGKILL);
+                }
+                bail!("'{}' timed out after {:?}", command, timeout);
+            }
+        };
+
+    Ok(ExecOutput {
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        code: status.code(),
+        truncated: out_cut || err_cut,
+    })
+}
+
+/// Read a stream to the end, keeping at most `MAX_OUTPUT` bytes. The rest is
+/// drained so the command never blocks on a full pipe.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R) -> std::io::Result<(Vec<u8>, bool)> {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok((kept, truncated));
+        }
+        let room = MAX_OUTPUT - kept.len();
+        if n > room {
+            truncated = true;
+        }
+        kept.extend_from_slice(&buf[..n.min(room)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn captures_output_and_exit_code() {
+        let out = run(
+            "echo hello; echo oops >&2",
+            &std::env::temp_dir(),
+            DEFAULT_TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(out.stdout, "hello\n");
+        assert_eq!(out.stderr, "oops\n");
+        assert_eq!(out.code, Some(0));
+        assert!(!out.truncated);
+
+        let out = run("exit 7", &std::env::temp_dir(), DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(out.code, Some(7));
+    }
+
+    #[tokio::test]
+    async fn runs_in_the_given_dir() {
+        let dir = std::env::temp_dir().join(format!("vex-exec-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = run("pwd -P", &dir, DEFAULT_TIMEOUT).await.unwrap();
+        assert_eq!(
+            out.stdout.trim(),
+            dir.canonicalize().unwrap().to_string_lossy()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn large_output_is_truncated() {
+        let out = run(
+            "head -c 1000000 /dev/zero",
+            &std::env::temp_dir(),
+            DEFAULT_TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(out.stdout.len(), MAX_OUTPUT);
+        assert!(out.truncated);
+        assert_eq!(out.code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn slow_command_times_out() {
+        let err = run(
+            "sleep 30",
+            &std::env::temp_dir(),
+            Duration::from_millis(200),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("timed out"), "{}", err);
+    }
+}
@@ -1,26 +1,35 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream};
 use tokio::sync::{broadcast, mpsc};
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 use vex_cli::proto::{
-    ClientMessage, Frame, ServerMessage, read_frame, send_server_message, write_data,
+    BranchFailure, ClientMessage, Frame, ServerMessage, WorkstreamSort, read_frame,
+    send_server_message, write_data,
 };
 
 use std::path::Path;
 
-use super::agent::AgentStore;
+use super::agent::{self, AgentStore};
 use super::config::VexConfig;
+use super::exec;
+use super::hooks;
+use super::log_throttle::LogThrottle;
+use super::prefetch;
 use super::repo::RepoStore;
 use super::session::SessionManager;
-use super::workstream::WorkstreamStore;
+use super::workstream::{self, WorkstreamStore};
+
+/// Largest data frame used to replay scrollback on attach.
+const REPLAY_CHUNK: usize = 256 * 1024;
 
 struct AttachState {
     session_id: Uuid,
     output_rx: broadcast::Receiver<Vec<u8>>,
     event_rx: broadcast::Receiver<ServerMessage>,
+    lag_log: LogThrottle,
 }
 
 pub async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
@@ -47,11 +56,11 @@ pub async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'stati
 
 async fn handle_connection_inner<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
     stream: S,
-    manager: &SessionManager,
+    manager: &Arc<SessionManager>,
     agent_store: &AgentStore,
     repo_store: &RepoStore,
     workstream_store: &WorkstreamStore,
-    config: &VexConfig,
+    config: &Arc<VexConfig>,
 ) -> Result<()> {
     let client_id = Uuid::new_v4();
     let (reader, mut writer) = tokio::io::split(stream);
@@ -106,12 +115,23 @@ async fn connection_loop<W: AsyncWrite + Unpin>(
     frame_rx: &mut mpsc::Receiver<Result<Frame>>,
     writer: &mut W,
     attached: &mut Option<AttachState>,
-    manager: &SessionManager,
+    manager: &Arc<SessionManager>,
     agent_store: &AgentStore,
     repo_store: &RepoStore,
     workstream_store: &WorkstreamStore,
-    config: &VexConfig,
+    config: &Arc<VexConfig>,
 ) -> Result<()> {
+    // Replies from tagged requests running on their own tasks. Only this
+    // loop writes to the socket, so frames never interleave.
+    let (reply_tx, mut reply_rx) = mpsc::channel::<(u64, ServerMessage)>(64);
+    let tagged = TaggedContext {
+        manager: Arc::clone(manager),
+        agent_store: Arc::clone(agent_store),
+        repo_store: Arc::clone(repo_store),
+        workstream_store: Arc::clone(workstream_store),
+        config: Arc::clone(config),
+        replies: reply_tx,
+    };
     loop {
         if let Some(state) = attached {
             let session_id = state.session_id;
@@ -135,6 +155,9 @@ async fn connection_loop<W: AsyncWrite + Unpin>(
                         Some(Ok(Frame::Control(data))) => {
                             let msg: ClientMessage = serde_json::from_slice(&data)?;
                             match msg {
+                                ClientMessage::Tagged { request_id, message } => {
+                                    tagged.spawn(request_id, *message);
+                                }
                                 ClientMessage::DetachSession => {
                                     info!("client {} detaching from session {}", client_id, session_id);
                                     manager.client_detach(session_id, client_id).await;
@@ -192,10 +215,20 @@ async fn connection_loop<W: AsyncWrite + Unpin>(
                             *attached = None;
                         }
                         Err(broadcast::error::RecvError::Lagged(n)) => {
-                            warn!("output lagged by {} messages for session {}", n, session_id);
+                            if state.lag_log.check("lag", std::time::Instant::now()).is_some() {
+                                warn!("output lagged by {} messages for session {}", n, session_id);
+                            } else {
+                                debug!("output lagged by {} messages for session {}", n, session_id);
+                            }
                         }
                     }
                 }
+                Some((request_id, message)) = reply_rx.recv() => {
+                    send_server_message(writer, &ServerMessage::Tagged {
+                        request_id,
+                        message: Box::new(message),
+                    }).await?;
+                }
                 event = state.event_rx.recv() => {
                     match event {
                         Ok(msg) => {
@@ -207,24 +240,59 @@ async fn connection_loop<W: AsyncWrite + Unpin>(
                 }
             }
         } else {
-            // Idle state: only read client frames
-            match frame_rx.recv().await {
+            // Idle state: read client frames and forward tagged replies
+            let frame = tokio::select! {
+                frame = frame_rx.recv() => frame,
+                Some((request_id, message)) = reply_rx.recv() => {
+                    send_server_message(
+                        writer,
+                        &ServerMessage::Tagged {
+                            request_id,
+                            message: Box::new(message),
+                        },
+                    )
+                    .await?;
+                    continue;
+                }
+            };
+            match frame {
                 Some(Ok(Frame::Control(data))) => {
                     let msg: ClientMessage = serde_json::from_slice(&data)?;
-                    if let ClientMessage::AttachSession { id, cols, rows } = msg {
-                        match manager.attach_session(id).await {
-                            Ok((scrollback, output_rx)) => {
+                    if let ClientMessage::Tagged {
+                        request_id,
+                        message,
+                    } = msg
+                    {
+                        tagged.spawn(request_id, *message);
+                    } else if let ClientMessage::AttachSession {
+                        id,
+                        cols,
+                        rows,
+                        since,
+                    } = msg
+                    {
+                        match manager.attach_session(id, since).await {
+                            Ok((replay, output_rx)) => {
                                 let event_rx = manager.subscribe_events(id).await?;
                                 let _ = manager.client_attach(id, client_id, cols, rows).await;
-                                send_server_message(writer, &ServerMessage::Attached { id })
-                                    .await?;
-                                if !scrollback.is_empty() {
-                                    write_data(writer, &scrollback).await?;
+                                send_server_message(
+                                    writer,
+                                    &ServerMessage::Attached {
+                                        id,
+                                        offset: replay.start,
+                                        skipped: replay.skipped,
+                                    },
+                                )
+                                .await?;
+                                // Replay from an output log can exceed one frame
+                                for chunk in replay.bytes.chunks(REPLAY_CHUNK) {
+                                    write_data(writer, chunk).await?;
                                 }
                                 *attached = Some(AttachState {
                                     session_id: id,
                                     output_rx,
                                     event_rx,
+                                    lag_log: LogThrottle::new(std::time::Duration::from_secs(10)),
                                 });
                             }
                             Err(e) => {
@@ -261,7 +329,7 @@ async fn connection_loop<W: AsyncWrite + Unpin>(
                 }
                 Some(Err(e)) => return Err(e),
                 None => {
-                    info!("client {} disconnected", client_id);
+                    debug!("client {} disconnected", client_id);
                     break;
                 }
             }
@@ -271,6 +339,86 @@ async fn connection_loop<W: AsyncWrite + Unpin>(
     Ok(())
 }
 
+/// What a tagged request needs to run on its own task.
+struct TaggedContext {
+    manager: Arc<SessionManager>,
+    agent_store: AgentStore,
+    repo_store: RepoStore,
+    workstream_store: WorkstreamStore,
+    config: Arc<VexConfig>,
+    replies: mpsc::Sender<(u64, ServerMessage)>,
+}
+
+impl TaggedContext {
+    fn spawn(&self, request_id: u64, msg: ClientMessage) {
+        match msg {
+            ClientMessage::Tagged { .. }
+            | ClientMessage::AttachSession { .. }
+            | ClientMessage::DetachSession
+            | ClientMessage::ResizeSession { .. } => {
+                spawn_tagged(request_id, self.replies.clone(), |_| async {
+                    anyhow::bail!("attach and nested requests can't be tagged")
+                });
+            }
+            msg => {
+                let manager = Arc::clone(&self.manager);
+                let agent_store = Arc::clone(&self.agent_store);
+                let repo_store = Arc::clone(&self.repo_store);
+                let workstream_store = Arc::clone(&self.workstream_store);
+                let config = Arc::clone(&self.config);
+                spawn_tagged(request_id, self.replies.clone(), |mut pipe| async move {
+                    handle_control_idle(
+                        msg,
+                        &manager,
+                        &agent_store,
+                        &repo_store,
+                        &workstream_store,
+                        &config,
+                        &mut pipe,
+                    )
+                    .await
+                });
+            }
+        }
+    }
+}
+
+/// Run one tagged request on its own task. The handler writes its replies
+/// into a pipe, and each is forwarded with `request_id` as soon as it is
+/// written, so streaming requests work and quick requests overtake slow ones.
+fn spawn_tagged<F, Fut>(request_id: u64, replies: mpsc::Sender<(u64, ServerMessage)>, handler: F)
+where
+    F: FnOnce(DuplexStream) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let (pipe_w, mut pipe_r) = tokio::io::duplex(64 * 1024);
+        let forward_replies = replies.clone();
+        let forward = async move {
+            while let Ok(Some(frame)) = read_frame(&mut pipe_r).await {
+                if let Frame::Control(data) = frame
+                    && let Ok(msg) = serde_json::from_slice::<ServerMessage>(&data)
+                    && forward_replies.send((request_id, msg)).await.is_err()
+                {
+                    // Connection gone; dropping the pipe stops the handler
+                    break;
+                }
+            }
+        };
+        let (result, ()) = tokio::join!(handler(pipe_w), forward);
+        if let Err(e) = result {
+            let _ = replies
+                .send((
+                    request_id,
+                    ServerMessage::Error {
+                        message: e.to_string(),
+                    },
+                ))
+                .await;
+        }
+    });
+}
+
 async fn handle_control_idle<W: AsyncWrite + Unpin>(
     msg: ClientMessage,
     manager: &SessionManager,
@@ -353,6 +501,34 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
                 .await?;
             }
         }
+        ClientMessage::ResetShells { repo } => {
+            let within = match &repo {
+                Some(name) => match repo_store.lock().await.get(name) {
+                    Some(path) => Some(path),
+                    None => {
+                        send_server_message(
+                            writer,
+                            &ServerMessage::Error {
+                                message: format!("repo '{}' not found", name),
+                            },
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+            // A shell someone started an agent in counts as an agent session
+            let agents: std::collections::HashSet<Uuid> =
+                agent_store.lock().await.keys().copied().collect();
+            let ids = manager.reset_shells(within.as_deref(), &agents).await;
+            warn!(
+                "reset {} shell session(s) (scope: {})",
+                ids.len(),
+                repo.as_deref().unwrap_or("all")
+            );
+            send_server_message(writer, &ServerMessage::ShellsReset { ids }).await?;
+        }
         ClientMessage::DetachSession => {
             send_server_message(
                 writer,
@@ -362,15 +538,23 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
             )
             .await?;
         }
-        ClientMessage::AttachSession { .. } => {
+        ClientMessage::AttachSession { .. } | ClientMessage::Tagged { .. } => {
             // Handled in the main loop
         }
-        ClientMessage::AgentList => {
-            let agents = agent_store.lock().await;
-            let entries = agents.values().map(|a| a.to_entry()).collect();
+        ClientMessage::AgentList { full_prompt } => {
+            let entries = agent_store
+                .lock()
+                .await
+                .values()
+                .map(|a| a.to_entry(full_prompt))
+                .collect();
+            let exited = manager.exited_agents().await;
             send_server_message(
                 writer,
-                &ServerMessage::AgentListResponse { agents: entries },
+                &ServerMessage::AgentListResponse {
+                    agents: entries,
+                    exited,
+                },
             )
             .await?;
         }
@@ -379,11 +563,14 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
             let entries = agents
                 .values()
                 .filter(|a| a.needs_intervention)
-                .map(|a| a.to_entry())
+                .map(|a| a.to_entry(false))
                 .collect();
             send_server_message(
                 writer,
-                &ServerMessage::AgentListResponse { agents: entries },
+                &ServerMessage::AgentListResponse {
+                    agents: entries,
+                    exited: Vec::new(),
+                },
             )
             .await?;
         }
@@ -391,9 +578,28 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
             handle_agent_watch(session_id, agent_store, writer, false).await?;
         }
         ClientMessage::AgentPrompt { session_id, text } => {
-            // Write the prompt text + carriage return to the vex session's PTY
+            if let Err(e) = config.check_prompt_len(&text) {
+                send_server_message(
+                    writer,
+                    &ServerMessage::Error {
+                        message: e.to_string(),
+                    },
+                )
+                .await?;
+                return Ok(());
+            }
+            if !agent_store.lock().await.contains_key(&session_id) {
+                send_server_message(
+                    writer,
+                    &ServerMessage::Error {
+                        message: format!("no agent running in session {}", session_id),
+                    },
+                )
+                .await?;
+                return Ok(());
+            }
             // PTYs in raw mode expect \r, not \n, to submit input
-            let input = format!("{}\r", text);
+            let input = agent::prompt_input(&text);
             if let Err(e) = manager.write_input(session_id, input.as_bytes()).await {
                 send_server_message(
                     writer,
@@ -403,9 +609,35 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
                 )
                 .await?;
             } else {
+                if let Some(info) = agent_store.lock().await.get_mut(&session_id) {
+                    info.record_prompt(&text);
+                }
                 send_server_message(writer, &ServerMessage::AgentPromptSent { session_id }).await?;
             }
         }
+        ClientMessage::AgentLogs { session_id, lines } => {
+            match manager.output_tail(session_id, lines).await {
+                Ok(content) => {
+                    send_server_message(
+                        writer,
+                        &ServerMessage::AgentLogs {
+                            session_id,
+                            content,
+                        },
+                    )
+                    .await?;
+                }
+                Err(e) => {
+                    send_server_message(
+                        writer,
+                        &ServerMessage::Error {
+                            message: e.to_string(),
+                        },
+                    )
+                    .await?;
+                }
+            }
+        }
         ClientMessage::RepoAdd { name, path } => {
             let mut store = repo_store.lock().await;
             match store.add(name.clone(), path.clone()) {
@@ -431,8 +663,25 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
                 }
             }
         }
-        ClientMessage::RepoRemove { name } => {
+        ClientMessage::RepoRemove {
+            name,
+            delete_workstreams,
+        } => {
             let mut store = repo_store.lock().await;
+            if store.get(&name).is_some()
+                && let Err(e) =
+                    remove_repo_workstreams(workstream_store, config, &name, delete_workstreams)
+                        .await
+            {
+                send_server_message(
+                    writer,
+                    &ServerMessage::Error {
+                        message: e.to_string(),
+                    },
+                )
+                .await?;
+                return Ok(());
+            }
             match store.remove(&name) {
                 Ok(()) => {
                     send_server_message(writer, &ServerMessage::RepoRemoved { name }).await?;
@@ -448,6 +697,9 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
                 }
             }
         }
+        ClientMessage::Ping => {
+            send_server_message(writer, &ServerMessage::Pong).await?;
+        }
         ClientMessage::RepoList => {
             let store = repo_store.lock().await;
             let repos = store.list();
@@ -467,7 +719,45 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
             )
             .await?;
         }
-        ClientMessage::AgentSpawn { repo, workstream } => {
+        ClientMessage::AgentRestart { session_id } => {
+            match manager.restart_agent(session_id).await {
+                Ok(id) => {
+                    info!("restarted agent session {} as {}", session_id, id);
+                    send_server_message(writer, &ServerMessage::SessionCreated { id }).await?;
+                }
+                Err(e) => {
+                    send_server_message(
+                        writer,
+                        &ServerMessage::Error {
+                            message: e.to_string(),
+                        },
+                    )
+                    .await?;
+                }
+            }
+        }
+        ClientMessage::AgentSpawn {
+            repo,
+            workstream,
+            env,
+            cwd,
+            profile,
+        } => {
+            // Get agent command from config
+            let command = match config.agent_command_for(&repo, profile.as_deref()) {
+                Ok(command) => command,
+                Err(e) => {
+                    send_server_message(
+                        writer,
+                        &ServerMessage::Error {
+                            message: e.to_string(),
+                        },
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            };
+
             // Resolve repo → working directory
             let repo_path = {
                 let store = repo_store.lock().await;
@@ -508,15 +798,63 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
             } else {
                 repo_path
             };
+            let working_dir = match cwd {
+                Some(sub) => match resolve_subdir(&working_dir, &sub) {
+                    Ok(dir) => dir,
+                    Err(e) => {
+                        send_server_message(
+                            writer,
+                            &ServerMessage::Error {
+                                message: e.to_string(),
+                            },
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                },
+                None => working_dir,
+            };
+            // Fail here with the program's name rather than leave the spawn
+            // to report a bare "No such file or directory"
+            let path_var = env
+                .iter()
+                .find(|(k, _)| k == "PATH")
+                .map(|(_, v)| std::ffi::OsString::from(v))
+                .or_else(|| std::env::var_os("PATH"));
+            let missing = match command.first() {
+                None => Some("agent command is empty".to_string()),
+                Some(program)
+                    if agent::find_program(program, path_var.as_deref(), &working_dir)
+                        .is_none() =>
+                {
+                    let place = if program.contains('/') {
+                        ""
+                    } else {
+                        " in PATH"
+                    };
+                    Some(format!("agent binary '{}' not found{}", program, place))
+                }
+                Some(_) => None,
+            };
+            if let Some(message) = missing {
+                send_server_message(writer, &ServerMessage::Error { message }).await?;
+                return Ok(());
+            }
 
-            // Get agent command from config
-            let command = config.agent_command_for(&repo);
             match manager
-                .create_session_with_command(command, 80, 24, Some(working_dir))
+                .create_session_with_command(command, &env, 80, 24, Some(working_dir))
                 .await
             {
                 Ok(id) => {
-                    info!("spawned agent session {} for repo '{}'", id, repo);
+                    // Only log variable names: values are often secrets
+                    let env_keys: Vec<&str> = env.iter().map(|(k, _)| k.as_str()).collect();
+                    info!(
+                        "spawned agent session {} for repo '{}' (profile: {}, env: [{}])",
+                        id,
+                        repo,
+                        profile.as_deref().unwrap_or("default"),
+                        env_keys.join(", ")
+                    );
                     send_server_message(writer, &ServerMessage::SessionCreated { id }).await?;
                 }
                 Err(e) => {
@@ -547,31 +885,153 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
                     }
                 }
             };
-            let mut ws_store = workstream_store.lock().await;
-            match ws_store.create(&repo, &name, &repo_path) {
-                Ok(worktree_path) => {
-                    info!(
-                        "created workstream '{}' for repo '{}' at {}",
-                        name,
-                        repo,
-                        worktree_path.display()
-                    );
-                    // Run on_workstream_create hooks if configured
-                    if let Some(hook_def) = &config.hooks.on_workstream_create
-                        && let Err(e) =
-                            run_workstream_hooks(manager, &worktree_path, &hook_def.commands).await
-                    {
-                        warn!("hook error: {}", e);
-                    }
+            // Don't hold the store lock while hooks run; they can take minutes
+            let created = workstream_store
+                .lock()
+                .await
+                .create(&repo, &name, &repo_path);
+            let worktree_path = match created {
+                Ok(path) => path,
+                Err(e) => {
                     send_server_message(
                         writer,
-                        &ServerMessage::WorkstreamCreated {
-                            repo,
-                            name,
-                            worktree_path,
+                        &ServerMessage::Error {
+                            message: e.to_string(),
                         },
                     )
                     .await?;
+                    return Ok(());
+                }
+            };
+            info!(
+                "created workstream '{}' for repo '{}' at {}",
+                name,
+                repo,
+                worktree_path.display()
+            );
+            if let Err(e) =
+                run_create_hooks(config, workstream_store, &repo, &name, &worktree_path).await
+            {
+                send_server_message(
+                    writer,
+                    &ServerMessage::Error {
+                        message: e.to_string(),
+                    },
+                )
+                .await?;
+                return Ok(());
+            }
+            send_server_message(
+                writer,
+                &ServerMessage::WorkstreamCreated {
+                    repo,
+                    name,
+                    worktree_path,
+                },
+            )
+            .await?;
+        }
+        ClientMessage::WorkstreamCreateBatch {
+            repo,
+            branches,
+            fetch_latest,
+        } => {
+            let Some(repo_path) = repo_store.lock().await.get(&repo) else {
+                send_server_message(
+                    writer,
+                    &ServerMessage::Error {
+                        message: format!("repo '{}' not found", repo),
+                    },
+                )
+                .await?;
+                return Ok(());
+            };
+            let mut names = Vec::new();
+            let mut failed = Vec::new();
+            for branch in branches {
+                if let Err(e) = workstream::validate_branch(&branch) {
+                    failed.push(BranchFailure {
+                        branch,
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+                // A failed fetch isn't fatal: the branch may be local-only,
+                // and one that exists nowhere fails below as not found
+                if fetch_latest && let Err(e) = prefetch::fetch_branch(&repo_path, &branch).await {
+                    warn!(
+                        "fetching branch '{}' of repo '{}' failed: {}",
+                        branch, repo, e
+                    );
+                }
+                // Each branch is created and persisted on its own, so one
+                // failing rolls back only itself
+                let created = workstream_store
+                    .lock()
+                    .await
+                    .create_from_branch(&repo, &branch, &repo_path);
+                let result = match created {
+                    Ok((name, path)) => {
+                        info!(
+                            "created workstream '{}' for branch '{}' of repo '{}' at {}",
+                            name,
+                            branch,
+                            repo,
+                            path.display()
+                        );
+                        run_create_hooks(config, workstream_store, &repo, &name, &path)
+                            .await
+                            .map(|()| name)
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(name) => names.push(name),
+                    Err(e) => failed.push(BranchFailure {
+                        branch,
+                        message: e.to_string(),
+                    }),
+                }
+            }
+            let mut created = workstream_store
+                .lock()
+                .await
+                .list(Some(&repo), WorkstreamSort::Name);
+            created.retain(|ws| names.contains(&ws.name));
+            send_server_message(
+                writer,
+                &ServerMessage::WorkstreamsCreated { created, failed },
+            )
+            .await?;
+        }
+        ClientMessage::WorkstreamList {
+            repo,
+            sort,
+            git_status,
+        } => {
+            let mut workstreams = workstream_store
+                .lock()
+                .await
+                .list(repo.as_deref(), sort.unwrap_or_default());
+            if git_status {
+                workstream::add_git_status(&mut workstreams).await;
+            }
+            send_server_message(writer, &ServerMessage::Workstreams { workstreams }).await?;
+        }
+        ClientMessage::WorkstreamRemove { repo, name } => {
+            let worktree_path = workstream_store
+                .lock()
+                .await
+                .get_worktree_path(&repo, &name);
+            if let Some(path) = &worktree_path {
+                run_remove_hooks(config, path).await;
+            }
+            let mut ws_store = workstream_store.lock().await;
+            match ws_store.remove(&repo, &name) {
+                Ok(()) => {
+                    info!("removed workstream '{}' from repo '{}'", name, repo);
+                    send_server_message(writer, &ServerMessage::WorkstreamRemoved { repo, name })
+                        .await?;
                 }
                 Err(e) => {
                     send_server_message(
@@ -584,17 +1044,77 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
                 }
             }
         }
-        ClientMessage::WorkstreamList { repo } => {
-            let ws_store = workstream_store.lock().await;
-            let workstreams = ws_store.list(repo.as_deref());
-            send_server_message(writer, &ServerMessage::Workstreams { workstreams }).await?;
+        ClientMessage::WorkstreamPrune { repo, dry_run } => {
+            let dirs = manager.working_dirs().await;
+            let mut workstreams = workstream_store
+                .lock()
+                .await
+                .list(repo.as_deref(), WorkstreamSort::Name);
+            // A shell whose directory is unknown may be in any worktree
+            let mut busy = dirs.unwrap_or_else(|| {
+                workstreams
+                    .iter()
+                    .map(|ws| ws.worktree_path.clone())
+                    .collect()
+            });
+            busy.extend(agent_store.lock().await.values().map(|a| a.cwd.clone()));
+            workstream::add_git_status(&mut workstreams).await;
+            let total = workstreams.len();
+            let (prunable, kept_dirty) = workstream::prune_candidates(workstreams, &busy);
+            let in_use = total - prunable.len() - kept_dirty.len();
+            let mut removed = Vec::new();
+            let mut kept_branches = Vec::new();
+            for ws in prunable {
+                // Pruning is unattended, so a branch holding commits that
+                // exist nowhere else outlives its worktree
+                let owned = workstream_store
+                    .lock()
+                    .await
+                    .owned_branch(&ws.repo, &ws.name);
+                let keep_branch = match owned {
+                    Some((repo_path, branch)) => !tokio::task::spawn_blocking(move || {
+                        workstream::branch_is_merged_or_pushed(&repo_path, &branch)
+                    })
+                    .await
+                    .unwrap_or(false),
+                    None => false,
+                };
+                if !dry_run {
+                    run_remove_hooks(config, &ws.worktree_path).await;
+                    let mut store = workstream_store.lock().await;
+                    let result = if keep_branch {
+                        store.remove_keeping_branch(&ws.repo, &ws.name)
+                    } else {
+                        store.remove(&ws.repo, &ws.name)
+                    };
+                    if let Err(e) = result {
+                        warn!("pruning workstream {}/{} failed: {}", ws.repo, ws.name, e);
+                        continue;
+                    }
+                    info!("pruned workstream '{}' from repo '{}'", ws.name, ws.repo);
+                }
+                if keep_branch {
+                    kept_branches.push(ws.clone());
+                }
+                removed.push(ws);
+            }
+            send_server_message(
+                writer,
+                &ServerMessage::WorkstreamsPruned {
+                    removed,
+                    kept_dirty,
+                    kept_branches,
+                    in_use,
+                    dry_run,
+                },
+            )
+            .await?;
         }
-        ClientMessage::WorkstreamRemove { repo, name } => {
+        ClientMessage::WorkstreamSetNotes { repo, name, notes } => {
             let mut ws_store = workstream_store.lock().await;
-            match ws_store.remove(&repo, &name) {
+            match ws_store.set_notes(&repo, &name, notes) {
                 Ok(()) => {
-                    info!("removed workstream '{}' from repo '{}'", name, repo);
-                    send_server_message(writer, &ServerMessage::WorkstreamRemoved { repo, name })
+                    send_server_message(writer, &ServerMessage::WorkstreamNotesSet { repo, name })
                         .await?;
                 }
                 Err(e) => {
@@ -608,32 +1128,219 @@ async fn handle_control_idle<W: AsyncWrite + Unpin>(
                 }
             }
         }
+        ClientMessage::WorkstreamRename {
+            repo,
+            name,
+            new_name,
+        } => {
+            let renamed = workstream_store
+                .lock()
+                .await
+                .rename(&repo, &name, &new_name);
+            let msg = match renamed {
+                Ok(workstream) => {
+                    info!("renamed workstream {}/{} to {}", repo, name, new_name);
+                    ServerMessage::WorkstreamRenamed { workstream }
+                }
+                Err(e) => ServerMessage::Error {
+                    message: e.to_string(),
+                },
+            };
+            send_server_message(writer, &msg).await?;
+        }
+        ClientMessage::WorkstreamStatus { repo, name } => {
+            let worktree_path = workstream_store
+                .lock()
+                .await
+                .get_worktree_path(&repo, &name);
+            let msg = match worktree_path {
+                None => ServerMessage::Error {
+                    message: format!("workstream '{}' not found for repo '{}'", name, repo),
+                },
+                Some(path) => match workstream::read_git_status(path).await {
+                    Ok(status) => ServerMessage::WorkstreamGitStatus { repo, name, status },
+                    Err(e) => ServerMessage::Error {
+                        message: e.to_string(),
+                    },
+                },
+            };
+            send_server_message(writer, &msg).await?;
+        }
+        ClientMessage::WorkstreamPath { repo, name } => {
+            let worktree_path = workstream_store
+                .lock()
+                .await
+                .get_worktree_path(&repo, &name);
+            let msg = match worktree_path {
+                None => ServerMessage::Error {
+                    message: format!("workstream '{}' not found for repo '{}'", name, repo),
+                },
+                Some(path) if !path.is_dir() => ServerMessage::Error {
+                    message: format!(
+                        "worktree of workstream '{}' in repo '{}' no longer exists at {}",
+                        name,
+                        repo,
+                        path.display()
+                    ),
+                },
+                Some(path) => ServerMessage::WorkstreamPath { path },
+            };
+            send_server_message(writer, &msg).await?;
+        }
+        ClientMessage::WorkstreamExec {
+            repo,
+            name,
+            command,
+            timeout_secs,
+        } => {
+            let worktree_path = workstream_store
+                .lock()
+                .await
+                .get_worktree_path(&repo, &name);
+            let Some(worktree_path) = worktree_path else {
+                send_server_message(
+                    writer,
+                    &ServerMessage::Error {
+                        message: format!("workstream '{}' not found for repo '{}'", name, repo),
+                    },
+                )
+                .await?;
+                return Ok(());
+            };
+            // Anyone who can reach the daemon can already open a shell;
+            // still leave a trail of what was run where.
+            info!("exec in workstream {}/{}: {}", repo, name, command);
+            let timeout = timeout_secs
+                .map(std::time::Duration::from_secs)
+                .unwrap_or(exec::DEFAULT_TIMEOUT);
+            let msg = match exec::run(&command, &worktree_path, timeout).await {
+                Ok(out) => {
+                    info!(
+                        "exec in workstream {}/{} exited with {:?}",
+                        repo, name, out.code
+                    );
+                    ServerMessage::ExecResult {
+                        stdout: out.stdout,
+                        stderr: out.stderr,
+                        code: out.code,
+                        truncated: out.truncated,
+                    }
+                }
+                Err(e) => ServerMessage::Error {
+                    message: e.to_string(),
+                },
+            };
+            send_server_message(writer, &msg).await?;
+        }
     }
     Ok(())
 }
 
-async fn run_workstream_hooks(
-    manager: &SessionManager,
-    worktree_path: &Path,
-    commands: &[String],
+/// Tear down a repo's workstreams ahead of unregistering it. Without
+/// `delete`, refuse instead so worktrees are never silently orphaned.
+async fn remove_repo_workstreams(
+    workstream_store: &WorkstreamStore,
+    config: &VexConfig,
+    repo: &str,
+    delete: bool,
 ) -> Result<()> {
-    let session_id = manager
-        .create_session(None, 80, 24, Some(worktree_path.to_path_buf()))
-        .await?;
+    let names = workstream_store.lock().await.names_for_repo(repo);
+    if names.is_empty() {
+        return Ok(());
+    }
+    if !delete {
+        anyhow::bail!(
+            "repo '{}' still has workstreams: {} (remove them first or pass --delete-workstreams)",
+            repo,
+            names.join(", ")
+        );
+    }
+    for ws in &names {
+        let worktree_path = workstream_store.lock().await.get_worktree_path(repo, ws);
+        if let Some(path) = &worktree_path {
+            run_remove_hooks(config, path).await;
+        }
+        workstream_store.lock().await.remove(repo, ws)?;
+        info!("removed workstream '{}' from repo '{}'", ws, repo);
+    }
+    Ok(())
+}
 
-    // Wait for shell to initialize
-    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+/// Run the `on_workstream_create` hooks in a new worktree. A failing hook
+/// rolls the workstream back so nothing half-initialised is left behind.
+async fn run_create_hooks(
+    config: &VexConfig,
+    workstream_store: &WorkstreamStore,
+    repo: &str,
+    name: &str,
+    worktree_path: &Path,
+) -> Result<()> {
+    if let Some(hook_def) = &config.hooks.on_workstream_create
+        && let Err(e) = hooks::run_hooks(
+            &hook_def.commands,
+            worktree_path,
+            std::time::Duration::from_secs(config.hooks.timeout_secs),
+        )
+        .await
+    {
+        warn!(
+            "rolling back workstream '{}' for repo '{}': {}",
+            name, repo, e
+        );
+        if let Err(rollback) = workstream_store.lock().await.remove(repo, name) {
+            warn!("rollback failed: {}", rollback);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
 
-    for cmd in commands {
-        let input = format!("{}\r", cmd);
-        manager.write_input(session_id, input.as_bytes()).await?;
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+/// Run on_workstream_remove hooks ahead of removing a worktree. The user asked
+/// for the removal, so a failing hook is logged rather than blocking it.
+async fn run_remove_hooks(config: &VexConfig, worktree_path: &Path) {
+    if let Some(hook_def) = &config.hooks.on_workstream_remove
+        && worktree_path.is_dir()
+        && let Err(e) = hooks::run_hooks(
+            &hook_def.commands,
+            worktree_path,
+            std::time::Duration::from_secs(config.hooks.timeout_secs),
+        )
+        .await
+    {
+        warn!(
+            "on_workstream_remove hook for {} failed: {}",
+            worktree_path.display(),
+            e
+        );
     }
+}
 
-    // Wait for last command to finish, then kill
-    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-    let _ = manager.kill_session(session_id).await;
-    Ok(())
+/// Resolve `sub` against `base`, refusing anything that would land outside
+/// it (absolute paths, `..`, or symlinks pointing elsewhere).
+fn resolve_subdir(base: &Path, sub: &Path) -> Result<std::path::PathBuf> {
+    use std::path::Component;
+    if !sub
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        anyhow::bail!(
+            "cwd '{}' must be a relative path inside the worktree",
+            sub.display()
+        );
+    }
+    let dir = base.join(sub);
+    if !dir.is_dir() {
+        anyhow::bail!(
+            "cwd '{}' does not exist in {}",
+            sub.display(),
+            base.display()
+        );
+    }
+    let dir = dir.canonicalize()?;
+    if !dir.starts_with(base.canonicalize()?) {
+        anyhow::bail!("cwd '{}' escapes {}", sub.display(), base.display());
+    }
+    Ok(dir)
 }
 
 async fn handle_agent_watch<W: AsyncWrite + Unpin>(
@@ -783,3 +1490,121 @@ async fn handle_agent_watch<W: AsyncWrite + Unpin>(
         pos = reader.stream_position()?;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_subdir_accepts_nested_dirs_and_rejects_escapes() {
+        let base = std::env::temp_dir().join(format!("vex-cwd-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(base.join("services/worker")).unwrap();
+        std::os::unix::fs::symlink("/", base.join("root-link")).unwrap();
+
+        let resolved = resolve_subdir(&base, Path::new("services/worker")).unwrap();
+        assert!(resolved.ends_with("services/worker"));
+        assert!(resolve_subdir(&base, Path::new("./services")).is_ok());
+
+        assert!(resolve_subdir(&base, Path::new("../")).is_err());
+        assert!(resolve_subdir(&base, Path::new("services/../..")).is_err());
+        assert!(resolve_subdir(&base, Path::new("/etc")).is_err());
+        assert!(resolve_subdir(&base, Path::new("missing")).is_err());
+        assert!(resolve_subdir(&base, Path::new("root-link")).is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tagged_replies_arrive_in_completion_order() {
+        let (tx, mut rx) = mpsc::channel(8);
+        spawn_tagged(1, tx.clone(), |mut pipe| async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            send_server_message(&mut pipe, &ServerMessage::Pong).await
+        });
+        spawn_tagged(2, tx.clone(), |mut pipe| async move {
+            send_server_message(&mut pipe, &ServerMessage::Detached).await?;
+            send_server_message(&mut pipe, &ServerMessage::Pong).await
+        });
+        spawn_tagged(3, tx, |_| async { anyhow::bail!("boom") });
+
+        let mut replies = Vec::new();
+        for _ in 0..4 {
+            replies.push(rx.recv().await.unwrap());
+        }
+        let ids: Vec<u64> = replies.iter().map(|(id, _)| *id).collect();
+        assert_eq!(
+            ids.last(),
+            Some(&1),
+            "slow request finishes last: {:?}",
+            ids
+        );
+        assert!(replies.contains(&(
+            3,
+            ServerMessage::Error {
+                message: "boom".into()
+            }
+        )));
+        let two: Vec<&ServerMessage> = replies
+            .iter()
+            .filter(|(id, _)| *id == 2)
+            .map(|(_, m)| m)
+            .collect();
+        assert_eq!(two, [&ServerMessage::Detached, &ServerMessage::Pong]);
+    }
+
+    #[tokio::test]
+    async fn connection_answers_tagged_requests_with_their_id() {
+        let vex_dir = std::env::temp_dir().join(format!("vex-tagged-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&vex_dir).unwrap();
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(handle_connection(
+            server,
+            Arc::new(SessionManager::new()),
+            agent::new_agent_store(),
+            super::super::repo::new_repo_store(&vex_dir),
+            super::super::workstream::new_workstream_store(&vex_dir),
+            Arc::new(VexConfig::default()),
+        ));
+
+        let (mut reader, mut writer) = tokio::io::split(client);
+        for (request_id, message) in [
+            (
+                10,
+                ClientMessage::AttachSession {
+                    id: Uuid::new_v4(),
+                    cols: 80,
+                    rows: 24,
+                    since: None,
+                },
+            ),
+            (11, ClientMessage::Ping),
+        ] {
+            let tagged = ClientMessage::Tagged {
+                request_id,
+                message: Box::new(message),
+            };
+            vex_cli::proto::send_client_message(&mut writer, &tagged)
+                .await
+                .unwrap();
+        }
+
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            let Some(Frame::Control(data)) = read_frame(&mut reader).await.unwrap() else {
+                panic!("expected a control frame");
+            };
+            match serde_json::from_slice(&data).unwrap() {
+                ServerMessage::Tagged {
+                    request_id,
+                    message,
+                } => replies.push((request_id, *message)),
+                other => panic!("untagged reply: {:?}", other),
+            }
+        }
+        replies.sort_by_key(|(id, _)| *id);
+        assert!(matches!(replies[0], (10, ServerMessage::Error { .. })));
+        assert_eq!(replies[1], (11, ServerMessage::Pong));
+
+        std::fs::remove_dir_all(&vex_dir).unwrap();
+    }
+}
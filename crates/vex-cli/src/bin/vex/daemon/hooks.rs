@@ -0,0 +1,191 @@
+//! Running configured hook commands (`hooks:` in config.yml).
+
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use nix::sys::signal::{Signal, killpg};
+use nix::unistd::Pid;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::info;
+
+/// How much of a failed hook's stderr to include in the error.
+const STDERR_TAIL_LINES: usize = 10;
+/// Most stderr kept per hook; older output is dropped first.
+const STDERR_MAX_BYTES: usize = 64 * 1024;
+/// How long to keep reading stderr once the hook has exited. A background
+/// process it started (`npm run dev &`) can hold the pipe open forever.
+const STDERR_GRACE: Duration = Duration::from_millis(200);
+
+/// Run each command with `sh -c` in `dir`, in order, stopping at the first
+/// failure. Each command gets `timeout`; on expiry its whole process group
+/// is killed so stragglers like package managers don't linger.
+pub async fn run_hooks(commands: &[String], dir: &Path, timeout: Duration) -> Result<()> {
+    for cmd in commands {
+        info!("running hook '{}' in {}", cmd, dir.display());
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("hook '{}' could not start: {}", cmd, e))?;
+        let pgid = child.id();
+        let stderr = Arc::new(Mutex::new(Vec::new()));
+        let mut drain = tokio::spawn(drain_stderr(
+            child.stderr.take().expect("stderr is piped"),
+            Arc::clone(&stderr),
+        ));
+
+        let status = match tokio::time::timeout(timeout, child.wait()).await {
+            Ok(status) => status?,
+            Err(_) => {
+                drain.abort();
+                if let Some(pgid) = pgid {
+                    let _ = killpg(Pid::from_raw(pgid as i32), Signal::SIGKILL);
+                }
+                bail!("hook '{}' timed out after {:?}", cmd, timeout);
+            }
+        };
+        if tokio::time::timeout(STDERR_GRACE, &mut drain)
+            .await
+            .is_err()
+        {
+            drain.abort();
+        }
+
+        if !status.success() {
+            let status = match status.code() {
+                Some(code) => format!("exit code {}", code),
+                None => "killed by a signal".to_string(),
+            };
+            let tail = stderr_tail(&stderr.lock().unwrap());
+            if tail.is_empty() {
+                bail!("hook '{}' failed ({})", cmd, status);
+            }
+            bail!("hook '{}' failed ({}):\n{}", cmd, status, tail);
+        }
+    }
+    Ok(())
+}
+
+/// Read `pipe` into `out` until EOF, keeping at most `STDERR_MAX_BYTES`.
+async fn drain_stderr(mut pipe: impl AsyncRead + Unpin, out: Arc<Mutex<Vec<u8>>>) {
+    let mut buf = [0u8; 4096];
+    while let Ok(n) = pipe.read(&mut buf).await
+        && n > 0
+    {
+        let mut out = out.lock().unwrap();
+        out.extend_from_slice(&buf[..n]);
+        let excess = out.len().saturating_sub(STDERR_MAX_BYTES);
+        out.drain(..excess);
+    }
+}
+
+fn stderr_tail(stderr: &[u8]) -> String {
+    let text = String::from_utf8_lossy(stderr);
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmds(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn hooks_run_in_order_in_the_given_dir() {
+        let dir = std::env::temp_dir().join(format!("vex-hooks-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        run_hooks(
+            &cmds(&["echo one > out", "echo two >> out"]),
+            &dir,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("out")).unwrap(),
+            "one\ntwo\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_hook_reports_exit_code_and_stderr_tail() {
+        let err = run_hooks(
+            &cmds(&[
+                "echo first >&2; echo 'registry unreachable' >&2; exit 3",
+                "true",
+            ]),
+            &std::env::temp_dir(),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("exit code 3"), "{}", err);
+        assert!(err.ends_with("first\nregistry unreachable"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn background_child_holding_stderr_does_not_block() {
+        let start = std::time::Instant::now();
+        run_hooks(
+            &cmds(&["sleep 30 & echo started >&2"]),
+            &std::env::temp_dir(),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let err = run_hooks(
+            &cmds(&["sleep 30 & echo 'port busy' >&2; exit 1"]),
+            &std::env::temp_dir(),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(err.ends_with("port busy"), "{}", err);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn slow_hook_times_out() {
+        let start = std::time::Instant::now();
+        let err = run_hooks(
+            &cmds(&["sleep 30"]),
+            &std::env::temp_dir(),
+            Duration::from_millis(200),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("hook 'sleep 30' timed out after 200ms"),
+            "{}",
+            err
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn stderr_tail_keeps_last_lines() {
+        let input: String = (1..=15).map(|i| format!("line {}\n", i)).collect();
+        let tail = stderr_tail(input.as_bytes());
+        assert!(tail.starts_with("line 6\n"));
+        assert!(tail.ends_with("line 15"));
+    }
+}
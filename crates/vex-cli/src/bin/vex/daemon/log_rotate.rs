@@ -0,0 +1,154 @@
+//! Size-based rotation of `daemon.log`. The daemon's stdout and stderr are
+//! the log file itself (see `daemon start`), so rotating means renaming the
+//! file and pointing both descriptors at a fresh one.
+
+use std::os::fd::AsFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_KEEP: u32 = 3;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// When to rotate and how many old logs to keep, from `VEX_LOG_MAX_BYTES`
+/// and `VEX_LOG_KEEP`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RotationPolicy {
+    pub max_bytes: u64,
+    /// `daemon.log.1` through `daemon.log.<keep>`; 0 discards old output.
+    pub keep: u32,
+}
+
+impl RotationPolicy {
+    fn from_env() -> Self {
+        Self::parse(
+            std::env::var("VEX_LOG_MAX_BYTES").ok().as_deref(),
+            std::env::var("VEX_LOG_KEEP").ok().as_deref(),
+        )
+    }
+
+    fn parse(max_bytes: Option<&str>, keep: Option<&str>) -> Self {
+        Self {
+            max_bytes: max_bytes
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_MAX_BYTES),
+            keep: keep
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_KEEP),
+        }
+    }
+}
+
+/// Check the log now and then every minute. Does nothing unless stdout is
+/// `log_path`, which it isn't when `daemon run` is started by hand.
+pub fn spawn_rotation_task(log_path: PathBuf) {
+    if !stdout_is(&log_path) {
+        return;
+    }
+    let policy = RotationPolicy::from_env();
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            tick.tick().await;
+            if let Err(e) = rotate_if_needed(&log_path, &policy) {
+                warn!("rotating {} failed: {}", log_path.display(), e);
+            }
+        }
+    });
+}
+
+fn stdout_is(path: &Path) -> bool {
+    let Ok(log) = std::fs::metadata(path) else {
+        return false;
+    };
+    std::io::stdout()
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| std::fs::File::from(fd).metadata())
+        .is_ok_and(|out| out.dev() == log.dev() && out.ino() == log.ino())
+}
+
+fn rotate_if_needed(log_path: &Path, policy: &RotationPolicy) -> std::io::Result<()> {
+    let size = std::fs::metadata(log_path)?.len();
+    if size <= policy.max_bytes {
+        return Ok(());
+    }
+    shift(log_path, policy.keep)?;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?;
+    nix::unistd::dup2_stdout(&file)?;
+    nix::unistd::dup2_stderr(&file)?;
+    info!("rotated log after it reached {} bytes", size);
+    Ok(())
+}
+
+/// Move `log` to `log.1`, `log.1` to `log.2` and so on, dropping whatever
+/// would land past `log.<keep>`.
+fn shift(log_path: &Path, keep: u32) -> std::io::Result<()> {
+    let numbered = |n: u32| PathBuf::from(format!("{}.{}", log_path.display(), n));
+    if keep == 0 {
+        return std::fs::remove_file(log_path);
+    }
+    for n in (1..keep).rev() {
+        match std::fs::rename(numbered(n), numbered(n + 1)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    std::fs::rename(log_path, numbered(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_falls_back_to_defaults() {
+        assert_eq!(
+            RotationPolicy::parse(None, Some("junk")),
+            RotationPolicy {
+                max_bytes: DEFAULT_MAX_BYTES,
+                keep: DEFAULT_KEEP
+            }
+        );
+        assert_eq!(
+            RotationPolicy::parse(Some("1024"), Some("0")),
+            RotationPolicy {
+                max_bytes: 1024,
+                keep: 0
+            }
+        );
+    }
+
+    #[test]
+    fn shift_keeps_the_newest_logs() {
+        let dir = std::env::temp_dir().join(format!("vex-rotate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("daemon.log");
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).ok();
+
+        std::fs::write(&log, "first").unwrap();
+        shift(&log, 2).unwrap();
+        std::fs::write(&log, "second").unwrap();
+        shift(&log, 2).unwrap();
+        std::fs::write(&log, "third").unwrap();
+        shift(&log, 2).unwrap();
+
+        assert_eq!(read("daemon.log"), None);
+        assert_eq!(read("daemon.log.1").as_deref(), Some("third"));
+        assert_eq!(read("daemon.log.2").as_deref(), Some("second"));
+        assert_eq!(read("daemon.log.3"), None);
+
+        std::fs::write(&log, "fourth").unwrap();
+        shift(&log, 0).unwrap();
+        assert_eq!(read("daemon.log"), None);
+        assert_eq!(read("daemon.log.1").as_deref(), Some("third"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+//! Rate limiting for log sites that can fire on every poll, so a condition
+//! that persists (or flaps) shows up once per interval instead of flooding
+//! the daemon log.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Keys should name a call site or category rather than carry the message
+/// itself; entries older than the interval are dropped as new keys arrive.
+pub struct LogThrottle {
+    interval: Duration,
+    /// key -> (last emitted, occurrences suppressed since then)
+    seen: HashMap<String, (Instant, u64)>,
+}
+
+impl LogThrottle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Whether `key` should be logged at `now`. When it should, returns how
+    /// many occurrences were suppressed since it was last logged.
+    pub fn check(&mut self, key: &str, now: Instant) -> Option<u64> {
+        match self.seen.get_mut(key) {
+            Some((last, suppressed)) if now.duration_since(*last) < self.interval => {
+                *suppressed += 1;
+                None
+            }
+            Some((last, suppressed)) => {
+                let dropped = *suppressed;
+                *last = now;
+                *suppressed = 0;
+                Some(dropped)
+            }
+            None => {
+                let interval = self.interval;
+                self.seen
+                    .retain(|_, (last, _)| now.duration_since(*last) < interval);
+                self.seen.insert(key.to_string(), (now, 0));
+                Some(0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_within_interval_are_suppressed_and_counted() {
+        let mut throttle = LogThrottle::new(Duration::from_secs(60));
+        let t0 = Instant::now();
+
+        assert_eq!(throttle.check("missing", t0), Some(0));
+        assert_eq!(throttle.check("missing", t0 + Duration::from_secs(1)), None);
+        assert_eq!(
+            throttle.check("missing", t0 + Duration::from_secs(59)),
+            None
+        );
+        assert_eq!(
+            throttle.check("missing", t0 + Duration::from_secs(60)),
+            Some(2)
+        );
+        assert_eq!(
+            throttle.check("missing", t0 + Duration::from_secs(61)),
+            None
+        );
+    }
+
+    #[test]
+    fn keys_are_independent() {
+        let mut throttle = LogThrottle::new(Duration::from_secs(60));
+        let t0 = Instant::now();
+
+        assert_eq!(throttle.check("a", t0), Some(0));
+        assert_eq!(throttle.check("b", t0), Some(0));
+        assert_eq!(throttle.check("a", t0), None);
+    }
+
+    #[test]
+    fn expired_keys_are_evicted() {
+        let mut throttle = LogThrottle::new(Duration::from_secs(60));
+        let t0 = Instant::now();
+
+        for i in 0..100 {
+            throttle.check(&format!("key-{i}"), t0);
+        }
+        assert_eq!(throttle.seen.len(), 100);
+
+        assert_eq!(
+            throttle.check("late", t0 + Duration::from_secs(60)),
+            Some(0)
+        );
+        assert_eq!(throttle.seen.len(), 1);
+    }
+}
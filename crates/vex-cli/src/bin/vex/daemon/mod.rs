@@ -1,16 +1,25 @@
 mod agent;
+pub mod check;
 pub mod config;
+mod exec;
 mod handler;
+mod hooks;
+mod log_rotate;
+mod log_throttle;
+mod notify;
+mod prefetch;
 mod repo;
 mod session;
+pub mod status;
 mod workstream;
 
+use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
 
 use anyhow::Result;
-use tokio::net::TcpListener;
-use tracing::{error, info};
+use tokio::net::{TcpListener, TcpSocket};
+use tracing::{debug, error, info, warn};
 
 use agent::{new_agent_store, spawn_detection_task};
 use config::VexConfig;
@@ -18,22 +27,91 @@ use repo::new_repo_store;
 use session::SessionManager;
 use workstream::new_workstream_store;
 
+/// Replace `path` with `data` via a synced temp file and a rename, so a crash
+/// mid-write leaves the old contents rather than a truncated file that the
+/// stores would load as empty.
+fn write_atomic(path: &Path, data: &str) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = std::path::PathBuf::from(tmp_name);
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(data.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Fail fast when another process already holds `port`, rather than
+/// spawning a daemon only for it to exit. Probes with the daemon's own
+/// `bind`, so a port left in TIME_WAIT by a stopped daemon counts as free.
+pub fn check_port_free(port: u16) -> Result<()> {
+    bind(port).map(drop)
+}
+
+/// Bind the daemon's listener. SO_REUSEADDR lets a restarted daemon reclaim
+/// the port while the old one's connections sit in TIME_WAIT; it does not let
+/// two listeners share the port.
+fn bind(port: u16) -> Result<TcpListener> {
+    let socket = TcpSocket::new_v4()?;
+    socket.set_reuseaddr(true)?;
+    socket
+        .bind(SocketAddr::from(([127, 0, 0, 1], port)))
+        .map_err(|e| bind_error(port, e))?;
+    Ok(socket.listen(1024)?)
+}
+
+fn bind_error(port: u16, e: std::io::Error) -> anyhow::Error {
+    if e.kind() == std::io::ErrorKind::AddrInUse {
+        anyhow::anyhow!(
+            "port {} is already in use by another process; pick a different one with --port or VEX_PORT",
+            port
+        )
+    } else {
+        anyhow::anyhow!("cannot listen on 127.0.0.1:{}: {}", port, e)
+    }
+}
+
 pub async fn run(port: u16, vex_dir: &Path) -> Result<()> {
-    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    let listener = bind(port)?;
     info!("daemon listening on 127.0.0.1:{}", port);
 
-    let manager = Arc::new(SessionManager::new());
+    let config = Arc::new(VexConfig::load(vex_dir));
+    let manager = Arc::new(if config.scrollback.persist {
+        SessionManager::with_output_log(vex_dir.join("sessions"), config.scrollback.replay_bytes)?
+    } else {
+        SessionManager::new()
+    });
     let agent_store = new_agent_store();
     let repo_store = new_repo_store(vex_dir);
+    let autoregistered = repo_store
+        .lock()
+        .await
+        .autoregister(&config.autoregister.dirs, config.autoregister.depth);
+    for (name, path) in autoregistered {
+        info!("auto-registered repo '{}' at {}", name, path.display());
+    }
     let workstream_store = new_workstream_store(vex_dir);
-    let config = Arc::new(VexConfig::load(vex_dir));
+    for ws in workstream_store.lock().await.missing_worktrees() {
+        warn!(
+            "worktree of workstream {}/{} is missing at {}; remove it with `vex workstream remove`",
+            ws.repo,
+            ws.name,
+            ws.worktree_path.display()
+        );
+    }
 
     // Start agent detection background task
     spawn_detection_task(Arc::clone(&manager), Arc::clone(&agent_store));
+    status::spawn_status_task(vex_dir, port);
+    prefetch::spawn_prefetch_task(Arc::clone(&repo_store), &config.prefetch);
+    log_rotate::spawn_rotation_task(vex_dir.join("daemon.log"));
+    notify::notify_ready();
 
     // Signal handler for graceful shutdown
     let manager_signal = Arc::clone(&manager);
     let pid_path = vex_dir.join("daemon.pid");
+    let status_dir = vex_dir.to_path_buf();
     tokio::spawn(async move {
         let mut sigterm =
             tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
@@ -52,6 +130,7 @@ pub async fn run(port: u16, vex_dir: &Path) -> Result<()> {
         info!("shutting down...");
         manager_signal.kill_all().await;
         let _ = std::fs::remove_file(&pid_path);
+        status::remove(&status_dir);
         std::process::exit(0);
     });
 
@@ -59,7 +138,7 @@ pub async fn run(port: u16, vex_dir: &Path) -> Result<()> {
     loop {
         match listener.accept().await {
             Ok((stream, addr)) => {
-                info!("new connection from {}", addr);
+                debug!("new connection from {}", addr);
                 let manager = Arc::clone(&manager);
                 let agent_store = Arc::clone(&agent_store);
                 let repo_store = Arc::clone(&repo_store);
@@ -83,3 +162,44 @@ pub async fn run(port: u16, vex_dir: &Path) -> Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn port_held_by_another_listener_is_reported() {
+        let other = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = other.local_addr().unwrap().port();
+
+        let err = bind(port).unwrap_err().to_string();
+        assert!(
+            err.contains(&format!("port {} is already in use", port)),
+            "{}",
+            err
+        );
+        assert!(check_port_free(port).is_err());
+
+        drop(other);
+        check_port_free(port).unwrap();
+        bind(port).unwrap();
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_and_cleans_up() {
+        let dir = std::env::temp_dir().join(format!("vex-write-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("repos.json");
+
+        write_atomic(&path, "old").unwrap();
+        write_atomic(&path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.join("repos.json.tmp").exists());
+
+        // A failed write leaves the previous contents alone
+        assert!(write_atomic(&dir.join("missing/repos.json"), "x").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
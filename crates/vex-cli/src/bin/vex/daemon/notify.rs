@@ -0,0 +1,76 @@
+//! systemd readiness notification, so a unit running `vex daemon run` with
+//! `Type=notify` is only considered started once the port is bound. Does
+//! nothing outside systemd, where `NOTIFY_SOCKET` is unset; `status.json`
+//! (see `status`) serves the same purpose for other supervisors.
+
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+use tracing::warn;
+
+/// Tell the service manager the daemon is accepting connections.
+pub fn notify_ready() {
+    let Ok(socket) = std::env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = notify(&socket, "READY=1") {
+        warn!("failed to notify systemd at {}: {}", socket, e);
+    }
+}
+
+/// Send `state` to the datagram socket `socket`; a leading `@` names a
+/// socket in the abstract namespace.
+fn notify(socket: &str, state: &str) -> std::io::Result<()> {
+    let addr = match socket.strip_prefix('@') {
+        Some(name) => abstract_addr(name)?,
+        None => SocketAddr::from_pathname(socket)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &str) -> std::io::Result<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    SocketAddr::from_abstract_name(name)
+}
+
+/// Abstract sockets are Linux-only, and so is systemd.
+#[cfg(not(target_os = "linux"))]
+fn abstract_addr(_name: &str) -> std::io::Result<SocketAddr> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "abstract sockets are only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_state_to_path_and_abstract_sockets() {
+        let dir = std::env::temp_dir().join(format!("vex-notify-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let listener = UnixDatagram::bind(&path).unwrap();
+        let mut buf = [0u8; 64];
+
+        notify(path.to_str().unwrap(), "READY=1").unwrap();
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        #[cfg(target_os = "linux")]
+        {
+            let name = format!("vex-notify-{}", uuid::Uuid::new_v4());
+            let listener = UnixDatagram::bind_addr(&abstract_addr(&name).unwrap()).unwrap();
+            notify(&format!("@{}", name), "READY=1").unwrap();
+            let n = listener.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..n], b"READY=1");
+        }
+        #[cfg(not(target_os = "linux"))]
+        assert!(notify("@vex-notify", "READY=1").is_err());
+
+        assert!(notify(dir.join("missing.sock").to_str().unwrap(), "READY=1").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
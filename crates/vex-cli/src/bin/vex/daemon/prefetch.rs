@@ -0,0 +1,236 @@
+//! Background `git fetch` for registered repos, so the first workstream
+//! created from a repo sees up-to-date remote branches without waiting on
+//! the network.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use super::config::PrefetchConfig;
+use super::repo::RepoStore;
+
+/// How often the task wakes up to look for repos that are due.
+const TICK: Duration = Duration::from_secs(60);
+/// A fetch still running after this is killed.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Tracks when each repo was last fetched.
+pub struct FetchSchedule {
+    interval: Duration,
+    last: HashMap<String, Instant>,
+}
+
+impl FetchSchedule {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Repos due for a fetch: never fetched first, then the least recently
+    /// fetched. Entries for repos no longer registered are dropped.
+    pub fn due(&mut self, repos: &[String], now: Instant) -> Vec<String> {
+        self.last.retain(|name, _| repos.contains(name));
+        let mut due: Vec<(Option<Instant>, &String)> = repos
+            .iter()
+            .map(|name| (self.last.get(name).copied(), name))
+            .filter(|(last, _)| last.is_none_or(|t| now.duration_since(t) >= self.interval))
+            .collect();
+        due.sort();
+        due.into_iter().map(|(_, name)| name.clone()).collect()
+    }
+
+    /// Record an attempt. Failures count too, so an unreachable remote is
+    /// retried on the normal interval rather than every tick.
+    pub fn record(&mut self, repo: &str, now: Instant) {
+        self.last.insert(repo.to_string(), now);
+    }
+}
+
+/// Start the pre-warm task if enabled. Returns immediately; the first round
+/// runs in the background.
+pub fn spawn_prefetch_task(repo_store: RepoStore, config: &PrefetchConfig) {
+    if !config.enabled {
+        return;
+    }
+    let interval = Duration::from_secs(config.interval_secs.max(60));
+    let spacing = Duration::from_secs(config.spacing_secs);
+    info!("prefetching registered repos every {:?}", interval);
+    tokio::spawn(async move {
+        let mut schedule = FetchSchedule::new(interval);
+        let mut tick = tokio::time::interval(TICK.min(interval));
+        loop {
+            tick.tick().await;
+            // Snapshot and release the lock before touching the network
+            let repos: HashMap<String, std::path::PathBuf> = repo_store
+                .lock()
+                .await
+                .list()
+                .into_iter()
+                .map(|r| (r.name, r.path))
+                .collect();
+            let names: Vec<String> = repos.keys().cloned().collect();
+            for name in schedule.due(&names, Instant::now()) {
+                let path = &repos[&name];
+                match fetch(path).await {
+                    Ok(()) => debug!("prefetched repo '{}'", name),
+                    Err(e) => warn!("prefetch of repo '{}' failed: {}", name, e),
+                }
+                schedule.record(&name, Instant::now());
+                tokio::time::sleep(spacing).await;
+            }
+        }
+    });
+}
+
+async fn fetch(repo_path: &Path) -> anyhow::Result<()> {
+    // git -C <repo_path> fetch --all --prune --quiet
+    git_fetch(repo_path, &["--all", "--prune", "--quiet"]).await
+}
+
+/// Bring `origin/<branch>` up to date ahead of checking the branch out.
+pub async fn fetch_branch(repo_path: &Path, branch: &str) -> anyhow::Result<()> {
+    super::workstream::validate_branch(branch)?;
+    // git -C <repo_path> fetch --quiet -- origin +refs/heads/<b>:refs/remotes/origin/<b>
+    let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch);
+    git_fetch(repo_path, &["--quiet", "--", "origin", &refspec]).await
+}
+
+async fn git_fetch(repo_path: &Path, args: &[&str]) -> anyhow::Result<()> {
+    let child = tokio::process::Command::new("git")
+        .args(["-C", &repo_path.to_string_lossy()])
+        .arg("fetch")
+        .args(args)
+        // Never wait on a credential or host-key prompt
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env(
+            "GIT_SSH_COMMAND",
+            std::env::var("GIT_SSH_COMMAND").unwrap_or_else(|_| "ssh -o BatchMode=yes".into()),
+        )
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let output = tokio::time::timeout(FETCH_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {:?}", FETCH_TIMEOUT))??;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn everything_is_due_at_first() {
+        let mut schedule = FetchSchedule::new(Duration::from_secs(600));
+        let now = Instant::now();
+        let mut due = schedule.due(&names(&["a", "b"]), now);
+        due.sort();
+        assert_eq!(due, names(&["a", "b"]));
+    }
+
+    #[test]
+    fn recently_fetched_repos_wait_for_the_interval() {
+        let mut schedule = FetchSchedule::new(Duration::from_secs(600));
+        let start = Instant::now();
+        let repos = names(&["a", "b"]);
+        schedule.record("a", start);
+        schedule.record("b", start + Duration::from_secs(100));
+
+        assert!(
+            schedule
+                .due(&repos, start + Duration::from_secs(599))
+                .is_empty()
+        );
+        assert_eq!(
+            schedule.due(&repos, start + Duration::from_secs(600)),
+            names(&["a"])
+        );
+        assert_eq!(
+            schedule.due(&repos, start + Duration::from_secs(700)),
+            names(&["a", "b"])
+        );
+    }
+
+    #[test]
+    fn new_repos_jump_the_queue() {
+        let mut schedule = FetchSchedule::new(Duration::from_secs(600));
+        let start = Instant::now();
+        schedule.record("old", start);
+        let due = schedule.due(&names(&["old", "new"]), start + Duration::from_secs(900));
+        assert_eq!(due, names(&["new", "old"]));
+    }
+
+    #[test]
+    fn removed_repos_are_forgotten() {
+        let mut schedule = FetchSchedule::new(Duration::from_secs(600));
+        let start = Instant::now();
+        schedule.record("gone", start);
+        schedule.due(&names(&["other"]), start);
+        // Re-registered later: treated as never fetched
+        assert_eq!(schedule.due(&names(&["gone"]), start), names(&["gone"]));
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-C", &dir.to_string_lossy()])
+            .args(["-c", "user.name=vex", "-c", "user.email=vex@test"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[tokio::test]
+    async fn fetch_branch_picks_up_a_branch_pushed_since_cloning() {
+        let root = std::env::temp_dir().join(format!("vex-fetch-test-{}", uuid::Uuid::new_v4()));
+        let origin = root.join("origin");
+        std::fs::create_dir_all(&origin).unwrap();
+        git(&origin, &["init", "-q"]);
+        git(&origin, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        git(&root, &["clone", "-q", "origin", "clone"]);
+        let clone = root.join("clone");
+
+        git(&origin, &["branch", "review/late"]);
+        let remote_ref = "refs/remotes/origin/review/late";
+        let has_ref = |r: &str| {
+            std::process::Command::new("git")
+                .args(["-C", &clone.to_string_lossy()])
+                .args(["rev-parse", "--verify", "--quiet", r])
+                .output()
+                .unwrap()
+                .status
+                .success()
+        };
+        assert!(!has_ref(remote_ref));
+
+        fetch_branch(&clone, "review/late").await.unwrap();
+        assert!(has_ref(remote_ref));
+        assert!(fetch_branch(&clone, "missing").await.is_err());
+
+        // Neither an option nor a refspec gets through to git
+        git(&clone, &["branch", "keep"]);
+        assert!(
+            fetch_branch(&clone, "--upload-pack=touch pwned")
+                .await
+                .is_err()
+        );
+        assert!(fetch_branch(&clone, "review/late:keep").await.is_err());
+        assert!(!clone.join("pwned").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
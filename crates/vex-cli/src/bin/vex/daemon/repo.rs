@@ -3,23 +3,64 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
+use tracing::warn;
 use vex_cli::proto::RepoEntry;
 
 pub type RepoStore = Arc<Mutex<RepoStoreInner>>;
 
+/// A registered repo as kept in `repos.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "PersistedRepo")]
+struct RepoRecord {
+    path: PathBuf,
+    /// `origin`'s URL when the repo was registered (or the daemon started).
+    remote_url: Option<String>,
+}
+
+/// Entries were once bare paths; both forms load.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PersistedRepo {
+    Path(PathBuf),
+    Record {
+        path: PathBuf,
+        #[serde(default)]
+        remote_url: Option<String>,
+    },
+}
+
+impl From<PersistedRepo> for RepoRecord {
+    fn from(repo: PersistedRepo) -> Self {
+        match repo {
+            PersistedRepo::Path(path) => Self {
+                path,
+                remote_url: None,
+            },
+            PersistedRepo::Record { path, remote_url } => Self { path, remote_url },
+        }
+    }
+}
+
 pub struct RepoStoreInner {
-    repos: HashMap<String, PathBuf>,
+    repos: HashMap<String, RepoRecord>,
     persist_path: PathBuf,
 }
 
 impl RepoStoreInner {
     pub fn load(vex_dir: &Path) -> Self {
         let persist_path = vex_dir.join("repos.json");
-        let repos = std::fs::read_to_string(&persist_path)
+        let mut repos = std::fs::read_to_string(&persist_path)
             .ok()
-            .and_then(|data| serde_json::from_str::<HashMap<String, PathBuf>>(&data).ok())
+            .and_then(|data| serde_json::from_str::<HashMap<String, RepoRecord>>(&data).ok())
             .unwrap_or_default();
+        // Older entries, and repos that had no remote when registered
+        for record in repos.values_mut() {
+            if record.remote_url.is_none() {
+                record.remote_url = origin_url(&record.path);
+            }
+        }
         Self {
             repos,
             persist_path,
@@ -27,6 +68,10 @@ impl RepoStoreInner {
     }
 
     pub fn add(&mut self, name: String, path: PathBuf) -> Result<()> {
+        // The name becomes a directory under workstreams/
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+            bail!("invalid repo name '{}'", name.escape_debug());
+        }
         if !path.is_dir() {
             bail!(
                 "path does not exist or is not a directory: {}",
@@ -35,8 +80,10 @@ impl RepoStoreInner {
         }
         let path = std::fs::canonicalize(&path)?;
         // Check for duplicate name (allow overwrite) but reject duplicate path
-        if let Some((existing_name, _)) =
-            self.repos.iter().find(|(n, p)| **p == path && **n != name)
+        if let Some((existing_name, _)) = self
+            .repos
+            .iter()
+            .find(|(n, r)| r.path == path && **n != name)
         {
             bail!(
                 "path '{}' is already registered as repo '{}'",
@@ -44,7 +91,8 @@ impl RepoStoreInner {
                 existing_name,
             );
         }
-        self.repos.insert(name, path);
+        let remote_url = origin_url(&path);
+        self.repos.insert(name, RepoRecord { path, remote_url });
         self.flush()
     }
 
@@ -58,43 +106,142 @@ impl RepoStoreInner {
     pub fn list(&self) -> Vec<RepoEntry> {
         self.repos
             .iter()
-            .map(|(name, path)| RepoEntry {
+            .map(|(name, record)| RepoEntry {
                 name: name.clone(),
-                path: path.clone(),
+                path: record.path.clone(),
+                remote_url: record.remote_url.clone(),
             })
             .collect()
     }
 
     pub fn get(&self, name: &str) -> Option<PathBuf> {
-        self.repos.get(name).cloned()
+        self.repos.get(name).map(|r| r.path.clone())
+    }
+
+    /// Register every repo found under `dirs` that isn't registered yet,
+    /// named after its directory. Returns the new `(name, path)` pairs; a
+    /// repo whose name is already taken by another path is skipped.
+    pub fn autoregister(&mut self, dirs: &[PathBuf], depth: usize) -> Vec<(String, PathBuf)> {
+        let mut added = Vec::new();
+        for dir in dirs {
+            for path in discover_repos(&expand_home(dir), depth) {
+                let (name, path, _, _) = introspect_path(&path);
+                if self.repos.values().any(|r| r.path == path) {
+                    continue;
+                }
+                if self.repos.contains_key(&name) {
+                    warn!(
+                        "not auto-registering {}: repo name '{}' is taken",
+                        path.display(),
+                        name
+                    );
+                    continue;
+                }
+                match self.add(name.clone(), path.clone()) {
+                    Ok(()) => added.push((name, path)),
+                    Err(e) => warn!("failed to auto-register {}: {}", path.display(), e),
+                }
+            }
+        }
+        added
     }
 
     fn flush(&self) -> Result<()> {
         let data = serde_json::to_string_pretty(&self.repos)?;
-        std::fs::write(&self.persist_path, data)?;
+        super::write_atomic(&self.persist_path, &data)?;
         Ok(())
     }
 }
 
+/// Strictly read `repos.json` without a store, for `vex daemon check`. A
+/// missing file is an empty registry.
+pub fn read_persisted(vex_dir: &Path) -> Result<HashMap<String, PathBuf>> {
+    match std::fs::read_to_string(vex_dir.join("repos.json")) {
+        Ok(data) => Ok(serde_json::from_str::<HashMap<String, RepoRecord>>(&data)?
+            .into_iter()
+            .map(|(name, record)| (name, record.path))
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Most directories `discover_repos` looks at, so pointing it at `~` or a
+/// huge tree can't stall startup.
+const MAX_SCAN_DIRS: usize = 10_000;
+const MAX_SCAN_DEPTH: usize = 3;
+
+/// Git repos (work trees or bare) in `dir` and up to `depth` levels below
+/// it, sorted. Hidden directories and the insides of repos are not searched.
+pub fn discover_repos(dir: &Path, depth: usize) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![(dir.to_path_buf(), 0)];
+    let mut scanned = 0;
+    while let Some((dir, level)) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if scanned == MAX_SCAN_DIRS {
+                warn!("stopped scanning for repos after {} directories", scanned);
+                found.sort();
+                return found;
+            }
+            let path = entry.path();
+            if !entry.file_type().is_ok_and(|t| t.is_dir())
+                || entry.file_name().to_string_lossy().starts_with('.')
+            {
+                continue;
+            }
+            scanned += 1;
+            if is_repo(&path) {
+                found.push(path);
+            } else if level + 1 < depth.min(MAX_SCAN_DEPTH) {
+                pending.push((path, level + 1));
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+fn is_repo(path: &Path) -> bool {
+    path.join(".git").exists() || (path.join("HEAD").is_file() && path.join("objects").is_dir())
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
 pub fn new_repo_store(vex_dir: &Path) -> RepoStore {
     Arc::new(Mutex::new(RepoStoreInner::load(vex_dir)))
 }
 
+/// The URL of the repo's `origin` remote, if it has one.
+fn origin_url(path: &Path) -> Option<String> {
+    std::process::Command::new("git")
+        .args(["remote", "get-url", "origin"])
+        .current_dir(path)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+}
+
 /// Introspect a path for git repository information.
 pub fn introspect_path(path: &Path) -> (String, PathBuf, Option<String>, Option<String>) {
     let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    // A bare repo's directory is usually named `<name>.git`
     let suggested_name = canonical
         .file_name()
         .map(|n| n.to_string_lossy().to_string())
+        .map(|n| n.strip_suffix(".git").map(String::from).unwrap_or(n))
         .unwrap_or_else(|| "unnamed".to_string());
 
-    let git_remote = std::process::Command::new("git")
-        .args(["remote", "get-url", "origin"])
-        .current_dir(&canonical)
-        .output()
-        .ok()
-        .filter(|o| o.status.success())
-        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+    let git_remote = origin_url(&canonical);
 
     let git_branch = std::process::Command::new("git")
         .args(["symbolic-ref", "--short", "HEAD"])
@@ -106,3 +253,114 @@ pub fn introspect_path(path: &Path) -> (String, PathBuf, Option<String>, Option<
 
     (suggested_name, canonical, git_remote, git_branch)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-C", &dir.to_string_lossy()])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn remote_url_is_stored_and_old_entries_still_load() {
+        let root = std::env::temp_dir().join(format!("vex-repo-test-{}", uuid::Uuid::new_v4()));
+        let repo = root.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        git(
+            &repo,
+            &["remote", "add", "origin", "git@example.com:me/repo.git"],
+        );
+        let repo = std::fs::canonicalize(&repo).unwrap();
+
+        let mut store = RepoStoreInner::load(&root);
+        store.add("repo".into(), repo.clone()).unwrap();
+        let remote = |store: &RepoStoreInner| store.list()[0].remote_url.clone();
+        assert_eq!(
+            remote(&store).as_deref(),
+            Some("git@example.com:me/repo.git")
+        );
+        assert_eq!(remote(&RepoStoreInner::load(&root)), remote(&store));
+
+        // A bare-path entry from before remotes were stored gets one derived
+        let old = serde_json::json!({ "repo": repo });
+        std::fs::write(root.join("repos.json"), old.to_string()).unwrap();
+        let store = RepoStoreInner::load(&root);
+        assert_eq!(store.get("repo"), Some(repo.clone()));
+        assert_eq!(
+            remote(&store).as_deref(),
+            Some("git@example.com:me/repo.git")
+        );
+        assert_eq!(read_persisted(&root).unwrap()["repo"], repo);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn bare_repos_are_suggested_without_the_git_suffix() {
+        let root = std::env::temp_dir().join(format!("vex-repo-test-{}", uuid::Uuid::new_v4()));
+        let bare = root.join("myrepo.git");
+        std::fs::create_dir_all(&bare).unwrap();
+        git(&bare, &["init", "-q", "--bare", "-b", "main"]);
+
+        let (name, path, _, branch) = introspect_path(&bare);
+        assert_eq!(name, "myrepo");
+        assert_eq!(path, std::fs::canonicalize(&bare).unwrap());
+        assert_eq!(branch.as_deref(), Some("main"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn autoregister_finds_repos_once() {
+        let root = std::env::temp_dir().join(format!("vex-repo-test-{}", uuid::Uuid::new_v4()));
+        let projects = root.join("projects");
+        for dir in ["api", "group/web", "group/deep/tool", ".hidden/x", "notes"] {
+            std::fs::create_dir_all(projects.join(dir)).unwrap();
+        }
+        for repo in ["api", "group/web", "group/deep/tool", ".hidden/x"] {
+            git(&projects.join(repo), &["init", "-q"]);
+        }
+        git(&projects, &["init", "-q", "--bare", "lib.git"]);
+        // Nothing inside a repo is searched
+        std::fs::create_dir_all(projects.join("api/vendor/dep")).unwrap();
+        git(&projects.join("api/vendor/dep"), &["init", "-q"]);
+
+        let found = |depth| -> Vec<String> {
+            discover_repos(&projects, depth)
+                .iter()
+                .map(|p| p.strip_prefix(&projects).unwrap().display().to_string())
+                .collect()
+        };
+        assert_eq!(found(1), ["api", "lib.git"]);
+        assert_eq!(found(2), ["api", "group/web", "lib.git"]);
+        assert_eq!(found(9), ["api", "group/deep/tool", "group/web", "lib.git"]);
+
+        let mut store = RepoStoreInner::load(&root);
+        let added = store.autoregister(std::slice::from_ref(&projects), 1);
+        let names: Vec<_> = added.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["api", "lib"]);
+        // A second scan registers nothing new, even after a reload
+        assert!(
+            store
+                .autoregister(std::slice::from_ref(&projects), 1)
+                .is_empty()
+        );
+        let mut store = RepoStoreInner::load(&root);
+        assert!(
+            store
+                .autoregister(std::slice::from_ref(&projects), 1)
+                .is_empty()
+        );
+        assert_eq!(store.list().len(), 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,15 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Result, bail};
 use chrono::Utc;
+use nix::sys::signal::{Signal, killpg};
+use nix::unistd::Pid;
 use pty_process::Size;
 use tokio::io::AsyncReadExt;
 use tokio::sync::{Mutex, broadcast};
+use tracing::warn;
 use uuid::Uuid;
-use vex_cli::proto::{ServerMessage, SessionInfo};
+use vex_cli::proto::{ExitedAgent, ServerMessage, SessionInfo};
 
 const MAX_SCROLLBACK: usize = 64 * 1024;
+/// How many ended agent sessions to remember for `vex agent list`.
+const MAX_EXITED_AGENTS: usize = 50;
 
 pub struct SessionHandle {
     pub id: Uuid,
@@ -17,24 +23,301 @@ pub struct SessionHandle {
     pub cols: u16,
     pub rows: u16,
     pub created_at: chrono::DateTime<Utc>,
+    pub working_dir: Option<PathBuf>,
+    /// Spawned to run an agent rather than a shell; `reset_shells` skips it.
+    pub agent: bool,
     pub pty_writer: Arc<Mutex<pty_process::OwnedWritePty>>,
     pub output_tx: broadcast::Sender<Vec<u8>>,
-    pub scrollback: Arc<Mutex<Vec<u8>>>,
+    pub scrollback: Arc<Mutex<Scrollback>>,
     /// Tracks attached clients and their terminal dimensions.
     pub clients: HashMap<Uuid, (u16, u16)>,
     /// Channel for presence events (ClientJoined/ClientLeft).
     pub event_tx: broadcast::Sender<ServerMessage>,
 }
 
+/// The tail of a session's output plus its absolute position in the stream,
+/// so a reattaching client can ask for just the bytes it missed.
+#[derive(Default)]
+pub struct Scrollback {
+    buf: Vec<u8>,
+    /// Total bytes ever produced; `buf` holds the last `buf.len()` of them.
+    end_offset: u64,
+    /// Appended to by the PTY reader after `push`, outside this lock.
+    log: Option<Arc<std::sync::Mutex<OutputLog>>>,
+}
+
+/// A session's recent output on disk, so attaching clients can be sent more
+/// history than fits in memory. Once the file holds `replay_bytes` it
+/// becomes `<id>.log.1` and a new one starts, so the log never takes more
+/// than about twice `replay_bytes` however long the session runs.
+struct OutputLog {
+    path: PathBuf,
+    file: Option<std::fs::File>,
+    /// Stream offset of the current file's first byte.
+    start: u64,
+    /// Stream offset of the rotated file's first byte, if there is one.
+    prev_start: Option<u64>,
+    /// Stream offset just past the last byte written.
+    end: u64,
+    /// Most history to replay from the log on attach.
+    replay_bytes: u64,
+}
+
+/// Create (or truncate) a log file readable only by the daemon's user:
+/// session output can include anything echoed, passwords and tokens too.
+fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+impl OutputLog {
+    fn create(path: PathBuf, replay_bytes: u64) -> std::io::Result<Self> {
+        let file = create_private(&path)?;
+        Ok(Self {
+            path,
+            file: Some(file),
+            start: 0,
+            prev_start: None,
+            end: 0,
+            replay_bytes,
+        })
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        path.into()
+    }
+
+    /// Append the next chunk of output. A failed write leaves a hole that
+    /// can't be replayed from, so the log is dropped instead.
+    fn append(&mut self, chunk: &[u8]) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let result = std::io::Write::write_all(file, chunk).and_then(|()| {
+            self.end += chunk.len() as u64;
+            if self.end - self.start < self.replay_bytes.max(1) {
+                return Ok(());
+            }
+            std::fs::rename(&self.path, self.rotated_path())?;
+            self.file = Some(create_private(&self.path)?);
+            self.prev_start = Some(self.start);
+            self.start = self.end;
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!("writing {} failed, dropping it: {}", self.path.display(), e);
+            self.file = None;
+            self.remove();
+        }
+    }
+
+    /// Offset of the oldest byte still on disk, or `None` without a log.
+    fn oldest(&self) -> Option<u64> {
+        self.file.as_ref()?;
+        Some(self.prev_start.unwrap_or(self.start))
+    }
+
+    /// Bytes `from..to` of the session's output; `from` must be at least
+    /// `oldest()` and `to` at most `end`.
+    fn read(&self, from: u64, to: u64) -> std::io::Result<Vec<u8>> {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut bytes = Vec::with_capacity((to - from) as usize);
+        let mut read_range = |path: &Path, file_start: u64, from: u64, to: u64| {
+            let mut file = std::fs::File::open(path)?;
+            file.seek(SeekFrom::Start(from - file_start))?;
+            file.take(to - from).read_to_end(&mut bytes)?;
+            std::io::Result::Ok(())
+        };
+        if let Some(prev_start) = self.prev_start
+            && from < self.start
+        {
+            read_range(&self.rotated_path(), prev_start, from, to.min(self.start))?;
+        }
+        if to > self.start {
+            read_range(&self.path, self.start, from.max(self.start), to)?;
+        }
+        if bytes.len() as u64 != to - from {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(bytes)
+    }
+
+    fn remove(&self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_file(self.rotated_path());
+    }
+}
+
+impl Scrollback {
+    fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+        self.end_offset += chunk.len() as u64;
+        if self.buf.len() > MAX_SCROLLBACK {
+            let drain = self.buf.len() - MAX_SCROLLBACK;
+            self.buf.drain(..drain);
+        }
+    }
+
+    /// What to replay to a client attaching after `since`: the in-memory
+    /// part, plus the range to read from the output log (up to its
+    /// `replay_bytes`) for history no longer held in memory. The log is read
+    /// by `replay`, outside this lock.
+    fn plan_replay(&self, since: Option<u64>) -> (Vec<u8>, Option<LogRange>) {
+        let start_offset = self.end_offset - self.buf.len() as u64;
+        let wanted = since.unwrap_or(0);
+        let memory = self.since(since).to_vec();
+        if wanted >= start_offset {
+            return (memory, None);
+        }
+        let range = self.log.as_ref().map(|log| LogRange {
+            log: Arc::clone(log),
+            wanted,
+            to: start_offset,
+        });
+        (memory, range)
+    }
+
+    /// Bytes after `since`. Falls back to the whole buffer when `since` is
+    /// unset or already trimmed away, since a partial gap can't be repaired;
+    /// `skipped` tells the client how much is missing.
+    fn since(&self, since: Option<u64>) -> &[u8] {
+        let start_offset = self.end_offset - self.buf.len() as u64;
+        match since {
+            Some(s) if s >= self.end_offset => &[],
+            Some(s) if s >= start_offset => &self.buf[(s - start_offset) as usize..],
+            _ => &self.buf,
+        }
+    }
+}
+
+/// Output older than the in-memory buffer, to be read from the log: from
+/// `wanted` (or as far back as the log reaches) up to `to`.
+struct LogRange {
+    log: Arc<std::sync::Mutex<OutputLog>>,
+    wanted: u64,
+    to: u64,
+}
+
+impl LogRange {
+    /// Blocking: reads the log file.
+    fn read(&self) -> Option<Vec<u8>> {
+        let log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        let oldest = log.oldest()?;
+        let from = self
+            .wanted
+            .max(log.end.saturating_sub(log.replay_bytes))
+            .max(oldest);
+        // Everything before `to` has been written unless the reader has
+        // fallen more than a whole buffer behind; then use memory alone
+        if from >= self.to || log.end < self.to {
+            return None;
+        }
+        log.read(from, self.to)
+            .inspect_err(|e| warn!("replaying {} failed: {}", log.path.display(), e))
+            .ok()
+    }
+}
+
+/// A client's replay on attach.
+pub struct Replay {
+    pub bytes: Vec<u8>,
+    /// Output offset of the first byte of `bytes`.
+    pub start: u64,
+    /// Bytes between the requested `since` and the start of `bytes` that
+    /// are no longer retained.
+    pub skipped: u64,
+}
+
+impl Replay {
+    fn new(bytes: Vec<u8>, end_offset: u64, since: Option<u64>) -> Self {
+        let start = end_offset - bytes.len() as u64;
+        Self {
+            skipped: since.map_or(0, |s| start.saturating_sub(s)),
+            bytes,
+            start,
+        }
+    }
+}
+
+/// Replay for an attaching client: `memory` from `plan_replay`, preceded by
+/// whatever of `range` the log still holds.
+async fn replay(memory: Vec<u8>, range: Option<LogRange>) -> Vec<u8> {
+    let Some(range) = range else {
+        return memory;
+    };
+    match tokio::task::spawn_blocking(move || range.read()).await {
+        Ok(Some(mut history)) => {
+            history.extend_from_slice(&memory);
+            history
+        }
+        _ => memory,
+    }
+}
+
+/// The suffix of `text` holding its last `n` lines. A trailing newline does
+/// not start a new line.
+fn last_lines(text: &str, n: usize) -> &str {
+    if n == 0 {
+        return "";
+    }
+    let body = text.strip_suffix('\n').unwrap_or(text);
+    match body.rmatch_indices('\n').nth(n - 1) {
+        Some((i, _)) => &text[i + 1..],
+        None => text,
+    }
+}
+
+/// An ended agent session and the command line, environment and
+/// directory it was started with, so it can be restarted.
+struct ExitedRecord {
+    info: ExitedAgent,
+    command: Vec<String>,
+    env: Vec<(String, String)>,
+}
+
 pub struct SessionManager {
     sessions: Arc<Mutex<HashMap<Uuid, SessionHandle>>>,
+    /// Ended agent sessions with their exit codes, newest first.
+    exited_agents: Arc<Mutex<VecDeque<ExitedRecord>>>,
+    /// Where to keep each session's output log, and how much of it to
+    /// replay on attach. `None` keeps output in memory only.
+    output_log: Option<(PathBuf, u64)>,
 }
 
 impl SessionManager {
     pub fn new() -> Self {
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
+            exited_agents: Arc::new(Mutex::new(VecDeque::new())),
+            output_log: None,
+        }
+    }
+
+    /// A manager that logs each session's output under `dir`. Logs left by a
+    /// previous daemon are removed: their sessions died with it.
+    pub fn with_output_log(dir: PathBuf, replay_bytes: u64) -> Result<Self> {
+        match std::fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&dir)?;
         }
+        Ok(Self {
+            output_log: Some((dir, replay_bytes)),
+            ..Self::new()
+        })
     }
 
     pub async fn create_session(
@@ -46,14 +329,16 @@ impl SessionManager {
     ) -> Result<Uuid> {
         let shell = shell
             .unwrap_or_else(|| std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string()));
-        self.spawn_session(vec![shell], cols, rows, working_dir)
+        self.spawn_session(vec![shell], &[], cols, rows, working_dir, false)
             .await
     }
 
-    /// Create a session running a custom command (program + args).
+    /// Create an agent session running a custom command (program + args)
+    /// with extra environment variables layered over the daemon's own.
     pub async fn create_session_with_command(
         &self,
         command: Vec<String>,
+        env: &[(String, String)],
         cols: u16,
         rows: u16,
         working_dir: Option<std::path::PathBuf>,
@@ -61,25 +346,36 @@ impl SessionManager {
         if command.is_empty() {
             bail!("command must not be empty");
         }
-        self.spawn_session(command, cols, rows, working_dir).await
+        for (key, value) in env {
+            if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+                bail!("invalid environment variable name '{}'", key);
+            }
+        }
+        self.spawn_session(command, env, cols, rows, working_dir, true)
+            .await
     }
 
     async fn spawn_session(
         &self,
         command: Vec<String>,
+        env: &[(String, String)],
         cols: u16,
         rows: u16,
         working_dir: Option<std::path::PathBuf>,
+        agent: bool,
     ) -> Result<Uuid> {
         let (pty, pts) = pty_process::open().map_err(|e| anyhow::anyhow!("{}", e))?;
         pty.resize(Size::new(rows, cols))
             .map_err(|e| anyhow::anyhow!("{}", e))?;
 
+        let command_line = command.join(" ");
+        let launch = agent.then(|| (command.clone(), env.to_vec()));
         let mut cmd = pty_process::Command::new(&command[0]);
         for arg in &command[1..] {
             cmd = cmd.arg(arg);
         }
-        if let Some(dir) = working_dir {
+        cmd = cmd.envs(env.iter().map(|(k, v)| (k, v)));
+        if let Some(dir) = &working_dir {
             cmd = cmd.current_dir(dir);
         }
         let child = cmd.spawn(pts).map_err(|e| anyhow::anyhow!("{}", e))?;
@@ -89,16 +385,29 @@ impl SessionManager {
 
         let (read_pty, write_pty) = pty.into_split();
         let (output_tx, _) = broadcast::channel(256);
-        let scrollback = Arc::new(Mutex::new(Vec::new()));
+        let id = Uuid::new_v4();
+        let log = self.output_log.as_ref().and_then(|(dir, replay_bytes)| {
+            let path = dir.join(format!("{}.log", id));
+            OutputLog::create(path, *replay_bytes)
+                .inspect_err(|e| warn!("cannot log output of session {}: {}", id, e))
+                .ok()
+                .map(|log| Arc::new(std::sync::Mutex::new(log)))
+        });
+        let scrollback = Arc::new(Mutex::new(Scrollback {
+            log: log.clone(),
+            ..Scrollback::default()
+        }));
+        let log_for_cleanup = log.clone();
         let (event_tx, _) = broadcast::channel(16);
 
-        let id = Uuid::new_v4();
         let handle = SessionHandle {
             id,
             shell_pid,
             cols,
             rows,
             created_at: Utc::now(),
+            working_dir: working_dir.clone(),
+            agent,
             pty_writer: Arc::new(Mutex::new(write_pty)),
             output_tx: output_tx.clone(),
             scrollback: Arc::clone(&scrollback),
@@ -120,14 +429,19 @@ impl SessionManager {
                 match read_pty.read(&mut buf).await {
                     Ok(0) => break,
                     Ok(n) => {
-                        let chunk = &buf[..n];
-                        let mut sb = scrollback.lock().await;
-                        sb.extend_from_slice(chunk);
-                        if sb.len() > MAX_SCROLLBACK {
-                            let drain = sb.len() - MAX_SCROLLBACK;
-                            sb.drain(..drain);
+                        let chunk = buf[..n].to_vec();
+                        {
+                            let mut sb = scrollback.lock().await;
+                            sb.push(&chunk);
+                            let _ = output_tx.send(chunk.clone());
+                        }
+                        if let Some(log) = &log {
+                            let log = Arc::clone(log);
+                            let _ = tokio::task::spawn_blocking(move || {
+                                log.lock().unwrap_or_else(|e| e.into_inner()).append(&chunk)
+                            })
+                            .await;
                         }
-                        let _ = output_tx.send(chunk.to_vec());
                     }
                     Err(_) => break,
                 }
@@ -136,12 +450,31 @@ impl SessionManager {
 
         // Child waiter task
         let sessions = Arc::clone(&self.sessions);
+        let exited_agents = Arc::clone(&self.exited_agents);
+        let cwd = working_dir;
         tokio::spawn(async move {
             let mut child = child;
-            let _ = child.wait().await;
+            let status = child.wait().await;
 
-            let mut sessions = sessions.lock().await;
-            sessions.remove(&id);
+            sessions.lock().await.remove(&id);
+            if let Some(log) = log_for_cleanup {
+                log.lock().unwrap_or_else(|e| e.into_inner()).remove();
+            }
+            if let Some((command, env)) = launch {
+                let mut exited = exited_agents.lock().await;
+                exited.push_front(ExitedRecord {
+                    info: ExitedAgent {
+                        vex_session_id: id,
+                        command: command_line,
+                        cwd,
+                        exit_code: status.ok().and_then(|s| s.code()),
+                        ended_at: Utc::now(),
+                    },
+                    command,
+                    env,
+                });
+                exited.truncate(MAX_EXITED_AGENTS);
+            }
         });
 
         Ok(id)
@@ -163,20 +496,24 @@ impl SessionManager {
 
     /// Atomically snapshot the scrollback buffer and subscribe to live output.
     /// This guarantees no gaps or duplicates between the replay and the stream.
+    /// Returns the replay (only what follows `since`, when given) and the
+    /// live receiver.
     pub async fn attach_session(
         &self,
         id: Uuid,
-    ) -> Result<(Vec<u8>, broadcast::Receiver<Vec<u8>>)> {
-        let sessions = self.sessions.lock().await;
-        match sessions.get(&id) {
-            Some(h) => {
-                let sb = h.scrollback.lock().await;
-                let buffer = sb.clone();
-                let rx = h.output_tx.subscribe();
-                Ok((buffer, rx))
-            }
-            None => bail!("session not found: {}", id),
-        }
+        since: Option<u64>,
+    ) -> Result<(Replay, broadcast::Receiver<Vec<u8>>)> {
+        let (memory, range, end_offset, rx) = {
+            let sessions = self.sessions.lock().await;
+            let Some(h) = sessions.get(&id) else {
+                bail!("session not found: {}", id);
+            };
+            let sb = h.scrollback.lock().await;
+            let (memory, range) = sb.plan_replay(since);
+            (memory, range, sb.end_offset, h.output_tx.subscribe())
+        };
+        let bytes = replay(memory, range).await;
+        Ok((Replay::new(bytes, end_offset, since), rx))
     }
 
     pub async fn subscribe_events(&self, id: Uuid) -> Result<broadcast::Receiver<ServerMessage>> {
@@ -287,12 +624,113 @@ impl SessionManager {
         Ok(())
     }
 
+    pub async fn exited_agents(&self) -> Vec<ExitedAgent> {
+        self.exited_agents
+            .lock()
+            .await
+            .iter()
+            .map(|e| e.info.clone())
+            .collect()
+    }
+
+    /// Start an ended agent again in a new session, with the command,
+    /// environment and directory it had. Its old record stays in the
+    /// exited list.
+    pub async fn restart_agent(&self, id: Uuid) -> Result<Uuid> {
+        if self.sessions.lock().await.contains_key(&id) {
+            bail!("agent in session {} is still running", id);
+        }
+        let (command, env, cwd) = {
+            let exited = self.exited_agents.lock().await;
+            let Some(record) = exited.iter().find(|e| e.info.vex_session_id == id) else {
+                bail!("no ended agent in session {}", id);
+            };
+            (
+                record.command.clone(),
+                record.env.clone(),
+                record.info.cwd.clone(),
+            )
+        };
+        self.spawn_session(command, &env, 80, 24, cwd, true).await
+    }
+
+    /// The last `lines` lines of a session's retained output, or all of it.
+    /// The text is as the terminal received it, escape sequences included.
+    /// Once a session ends its output is dropped, which is an error rather
+    /// than an empty result so callers don't mistake it for a quiet agent.
+    pub async fn output_tail(&self, id: Uuid, lines: Option<u32>) -> Result<String> {
+        let scrollback = match self.sessions.lock().await.get(&id) {
+            Some(h) => Arc::clone(&h.scrollback),
+            None => {
+                if self
+                    .exited_agents
+                    .lock()
+                    .await
+                    .iter()
+                    .any(|e| e.info.vex_session_id == id)
+                {
+                    bail!("session {} has ended; its output is no longer kept", id);
+                }
+                bail!("session not found: {}", id);
+            }
+        };
+        let sb = scrollback.lock().await;
+        let text = String::from_utf8_lossy(&sb.buf);
+        Ok(match lines {
+            Some(n) => last_lines(&text, n as usize).to_string(),
+            None => text.into_owned(),
+        })
+    }
+
     /// Returns a map of vex session ID → shell PID for agent detection.
     pub async fn shell_pids(&self) -> HashMap<Uuid, u32> {
         let sessions = self.sessions.lock().await;
         sessions.iter().map(|(id, h)| (*id, h.shell_pid)).collect()
     }
 
+    /// Forcibly end shell sessions, optionally only those started under
+    /// `within`. Agent sessions, and any in `keep`, are left alone. Each
+    /// shell's process group gets SIGKILL, so even a wedged shell goes away;
+    /// attached clients see the session end as usual. Returns the ids ended.
+    pub async fn reset_shells(&self, within: Option<&Path>, keep: &HashSet<Uuid>) -> Vec<Uuid> {
+        let handles: Vec<SessionHandle> = {
+            let mut sessions = self.sessions.lock().await;
+            let ids: Vec<Uuid> = sessions
+                .values()
+                .filter(|h| !h.agent && !keep.contains(&h.id))
+                .filter(|h| match within {
+                    Some(dir) => h.working_dir.as_deref().is_some_and(|d| d.starts_with(dir)),
+                    None => true,
+                })
+                .map(|h| h.id)
+                .collect();
+            ids.iter().filter_map(|id| sessions.remove(id)).collect()
+        };
+
+        let mut ended = Vec::new();
+        for handle in handles {
+            // The PTY child is a session leader, so its pid is the group id
+            let _ = killpg(Pid::from_raw(handle.shell_pid as i32), Signal::SIGKILL);
+            use tokio::io::AsyncWriteExt;
+            let _ = handle.pty_writer.lock().await.shutdown().await;
+            ended.push(handle.id);
+        }
+        ended
+    }
+
+    /// Directories live sessions are working in: where each was started and
+    /// its process's current directory. `None` when a current directory
+    /// can't be read (no `/proc` off Linux): that shell could be anywhere.
+    pub async fn working_dirs(&self) -> Option<Vec<PathBuf>> {
+        let sessions = self.sessions.lock().await;
+        let mut dirs = Vec::new();
+        for handle in sessions.values() {
+            dirs.extend(handle.working_dir.clone());
+            dirs.push(std::fs::read_link(format!("/proc/{}/cwd", handle.shell_pid)).ok()?);
+        }
+        Some(dirs)
+    }
+
     pub async fn kill_all(&self) {
         let ids: Vec<Uuid> = {
             let sessions = self.sessions.lock().await;
@@ -303,3 +741,394 @@ impl SessionManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reset_shells_ends_matching_shells_only() {
+        let base = std::env::temp_dir().join(format!("vex-reset-{}", Uuid::new_v4()));
+        let (repo_a, repo_b) = (base.join("a"), base.join("b"));
+        std::fs::create_dir_all(&repo_a).unwrap();
+        std::fs::create_dir_all(&repo_b).unwrap();
+
+        let manager = SessionManager::new();
+        let shell = || Some("/bin/sh".to_string());
+        let in_a = manager
+            .create_session(shell(), 80, 24, Some(repo_a.clone()))
+            .await
+            .unwrap();
+        let in_b = manager
+            .create_session(shell(), 80, 24, Some(repo_b.clone()))
+            .await
+            .unwrap();
+        let kept = manager
+            .create_session(shell(), 80, 24, Some(repo_a.clone()))
+            .await
+            .unwrap();
+        let agent = manager
+            .create_session_with_command(vec!["/bin/sh".into()], &[], 80, 24, Some(repo_a.clone()))
+            .await
+            .unwrap();
+        let (_, mut output) = manager.attach_session(in_a, None).await.unwrap();
+
+        let ended = manager
+            .reset_shells(Some(&repo_a), &HashSet::from([kept]))
+            .await;
+        assert_eq!(ended, [in_a]);
+        let mut remaining: Vec<Uuid> = manager.list_sessions().await.iter().map(|s| s.id).collect();
+        remaining.sort();
+        let mut expected = vec![in_b, kept, agent];
+        expected.sort();
+        assert_eq!(remaining, expected);
+
+        // An attached client sees the output stream close
+        let closed = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                if let Err(broadcast::error::RecvError::Closed) = output.recv().await {
+                    break;
+                }
+            }
+        })
+        .await;
+        assert!(closed.is_ok());
+
+        let ended = manager.reset_shells(None, &HashSet::new()).await;
+        assert_eq!(ended.len(), 2);
+        let remaining: Vec<Uuid> = manager.list_sessions().await.iter().map(|s| s.id).collect();
+        assert_eq!(remaining, [agent]);
+
+        manager.kill_all().await;
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn exited_agents_keep_their_exit_codes() {
+        let manager = SessionManager::new();
+        let spawn = |script: &str| {
+            manager.create_session_with_command(
+                vec!["sh".into(), "-c".into(), script.into()],
+                &[],
+                80,
+                24,
+                None,
+            )
+        };
+        let clean = spawn("exit 0").await.unwrap();
+        let crashed = spawn("exit 3").await.unwrap();
+        manager
+            .create_session(Some("/bin/true".into()), 80, 24, None)
+            .await
+            .unwrap();
+
+        let exited = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                let exited = manager.exited_agents().await;
+                if exited.len() == 2 {
+                    return exited;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let code_of = |id| {
+            exited
+                .iter()
+                .find(|e| e.vex_session_id == id)
+                .unwrap()
+                .exit_code
+        };
+        assert_eq!(code_of(clean), Some(0));
+        assert_eq!(code_of(crashed), Some(3));
+        assert_eq!(exited[0].command.split(' ').next(), Some("sh"));
+    }
+
+    #[tokio::test]
+    async fn restarted_agents_rerun_with_their_env_and_cwd() {
+        let manager = SessionManager::new();
+        let dir = std::env::temp_dir().join(format!("vex-restart-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wait_for_exits = |n: usize| {
+            let manager = &manager;
+            async move {
+                tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                    while manager.exited_agents().await.len() < n {
+                        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    }
+                })
+                .await
+                .unwrap()
+            }
+        };
+        let script = "echo \"$RUN\" >> runs; read _";
+        let first = manager
+            .create_session_with_command(
+                vec!["sh".into(), "-c".into(), script.into()],
+                &[("RUN".into(), "again".into())],
+                80,
+                24,
+                Some(dir.clone()),
+            )
+            .await
+            .unwrap();
+
+        let err = manager.restart_agent(first).await.unwrap_err();
+        assert!(err.to_string().contains("still running"), "{}", err);
+        manager.write_input(first, b"\r").await.unwrap();
+        wait_for_exits(1).await;
+
+        let second = manager.restart_agent(first).await.unwrap();
+        assert_ne!(second, first);
+        manager.write_input(second, b"\r").await.unwrap();
+        wait_for_exits(2).await;
+
+        // Both runs are kept, newest first
+        let exited: Vec<Uuid> = manager
+            .exited_agents()
+            .await
+            .iter()
+            .map(|e| e.vex_session_id)
+            .collect();
+        assert_eq!(exited, [second, first]);
+        let runs = std::fs::read_to_string(dir.join("runs")).unwrap();
+        assert_eq!(runs, "again\nagain\n");
+        let err = manager.restart_agent(Uuid::new_v4()).await.unwrap_err();
+        assert!(err.to_string().contains("no ended agent"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn last_lines_keeps_the_tail() {
+        assert_eq!(last_lines("a\nb\nc\n", 2), "b\nc\n");
+        assert_eq!(last_lines("a\nb\nc", 2), "b\nc");
+        assert_eq!(last_lines("a\nb\n", 5), "a\nb\n");
+        assert_eq!(last_lines("a\nb\n", 0), "");
+    }
+
+    #[tokio::test]
+    async fn output_tail_is_gone_once_the_session_ends() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session_with_command(
+                vec![
+                    "sh".into(),
+                    "-c".into(),
+                    "echo one; echo two; read _".into(),
+                ],
+                &[],
+                80,
+                24,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let tail = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                let tail = manager.output_tail(id, Some(1)).await.unwrap();
+                if tail.contains("two") {
+                    break tail;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(tail, "two\r\n");
+
+        // Let the agent finish
+        manager.write_input(id, b"\r").await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while manager.exited_agents().await.is_empty() {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let err = manager.output_tail(id, None).await.unwrap_err();
+        assert!(err.to_string().contains("has ended"), "{}", err);
+        let err = manager.output_tail(Uuid::new_v4(), None).await.unwrap_err();
+        assert!(err.to_string().contains("session not found"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn reattach_after_output_loses_nothing() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session_with_command(
+                vec![
+                    "sh".into(),
+                    "-c".into(),
+                    "echo one; read x; echo two; read _".into(),
+                ],
+                &[],
+                80,
+                24,
+                None,
+            )
+            .await
+            .unwrap();
+        let wait_for = |text: &'static str| {
+            let manager = &manager;
+            async move {
+                tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                    while !manager.output_tail(id, None).await.unwrap().contains(text) {
+                        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    }
+                })
+                .await
+                .unwrap()
+            }
+        };
+
+        // A client counts every byte it receives from the replay onwards
+        wait_for("one").await;
+        let (first, rx) = manager.attach_session(id, None).await.unwrap();
+        let seen = first.start + first.bytes.len() as u64;
+        drop(rx);
+
+        // Output produced while disconnected
+        manager.write_input(id, b"\r").await.unwrap();
+        wait_for("two").await;
+
+        let (second, _rx) = manager.attach_session(id, Some(seen)).await.unwrap();
+        assert_eq!(second.start, seen);
+        assert_eq!(second.skipped, 0);
+        let (all, _rx) = manager.attach_session(id, Some(0)).await.unwrap();
+        assert_eq!([first.bytes, second.bytes].concat(), all.bytes);
+        assert!(String::from_utf8_lossy(&all.bytes).contains("two"));
+
+        manager.kill_all().await;
+    }
+
+    #[test]
+    fn reattach_replays_only_missed_bytes() {
+        let mut sb = Scrollback::default();
+        sb.push(b"hello ");
+        let seen = sb.end_offset;
+        sb.push(b"world");
+
+        assert_eq!(sb.since(Some(seen)), b"world");
+        assert_eq!(sb.since(Some(sb.end_offset)), b"");
+        assert_eq!(sb.since(None), b"hello world");
+    }
+
+    /// Push to memory and the log, the way the PTY reader does.
+    fn push_logged(sb: &mut Scrollback, chunk: &[u8]) {
+        sb.push(chunk);
+        if let Some(log) = &sb.log {
+            log.lock().unwrap().append(chunk);
+        }
+    }
+
+    /// `replay`, without a runtime.
+    fn replay_now(sb: &Scrollback, since: Option<u64>) -> Vec<u8> {
+        let (memory, range) = sb.plan_replay(since);
+        match range.and_then(|r| r.read()) {
+            Some(mut history) => {
+                history.extend_from_slice(&memory);
+                history
+            }
+            None => memory,
+        }
+    }
+
+    fn logged_scrollback(replay_bytes: u64) -> (Scrollback, PathBuf) {
+        let path = std::env::temp_dir().join(format!("vex-output-{}.log", Uuid::new_v4()));
+        let log = OutputLog::create(path.clone(), replay_bytes).unwrap();
+        let sb = Scrollback {
+            log: Some(Arc::new(std::sync::Mutex::new(log))),
+            ..Scrollback::default()
+        };
+        (sb, path)
+    }
+
+    #[test]
+    fn output_log_replays_history_beyond_memory() {
+        let (mut sb, path) = logged_scrollback(2 * MAX_SCROLLBACK as u64);
+        push_logged(&mut sb, &vec![b'a'; MAX_SCROLLBACK]);
+        push_logged(&mut sb, &vec![b'b'; MAX_SCROLLBACK]);
+        push_logged(&mut sb, b"end");
+
+        // Capped at replay_bytes, taken from the end
+        let all = replay_now(&sb, None);
+        assert_eq!(all.len(), 2 * MAX_SCROLLBACK);
+        assert!(all.ends_with(b"end"));
+        assert_eq!(
+            all.iter().filter(|&&c| c == b'a').count(),
+            MAX_SCROLLBACK - 3
+        );
+
+        // A reattach still gets exactly what it missed
+        let seen = MAX_SCROLLBACK as u64 + 10;
+        let missed = replay_now(&sb, Some(seen));
+        assert_eq!(missed.len() as u64, sb.end_offset - seen);
+        assert!(missed.starts_with(b"bbb"));
+
+        sb.log.unwrap().lock().unwrap().remove();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn output_log_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("vex-sessions-{}", Uuid::new_v4()));
+        SessionManager::with_output_log(dir.clone(), 1024).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn output_log_rotates_to_stay_bounded() {
+        let replay_bytes = 2 * MAX_SCROLLBACK as u64;
+        let (mut sb, path) = logged_scrollback(replay_bytes);
+        for i in 0..40u8 {
+            push_logged(&mut sb, &vec![b'a' + i % 26; MAX_SCROLLBACK / 4]);
+        }
+        let log = sb.log.clone().unwrap();
+        let rotated = log.lock().unwrap().rotated_path();
+        for file in [&path, &rotated] {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", file.display());
+        }
+        let on_disk =
+            std::fs::metadata(&path).unwrap().len() + std::fs::metadata(&rotated).unwrap().len();
+        assert!(on_disk <= 2 * replay_bytes, "{}", on_disk);
+
+        // Replay still reaches back replay_bytes, across both files
+        let all = replay_now(&sb, None);
+        assert_eq!(all.len() as u64, replay_bytes);
+        let expected: Vec<u8> = (0..40u8)
+            .flat_map(|i| vec![b'a' + i % 26; MAX_SCROLLBACK / 4])
+            .collect();
+        assert_eq!(all, expected[expected.len() - replay_bytes as usize..]);
+
+        log.lock().unwrap().remove();
+        assert!(!path.exists() && !rotated.exists());
+    }
+
+    #[test]
+    fn reattach_past_trimmed_history_gets_whole_buffer() {
+        let mut sb = Scrollback::default();
+        sb.push(&vec![b'a'; MAX_SCROLLBACK]);
+        sb.push(b"tail");
+        assert_eq!(sb.end_offset, MAX_SCROLLBACK as u64 + 4);
+        assert_eq!(sb.buf.len(), MAX_SCROLLBACK);
+
+        // Offset 0 was trimmed away, so the client gets everything retained
+        // and is told how much it missed
+        let replay = Replay::new(sb.since(Some(0)).to_vec(), sb.end_offset, Some(0));
+        assert_eq!(replay.bytes.len(), MAX_SCROLLBACK);
+        assert_eq!(replay.skipped, 4);
+        let since = MAX_SCROLLBACK as u64;
+        let replay = Replay::new(sb.since(Some(since)).to_vec(), sb.end_offset, Some(since));
+        assert_eq!(replay.bytes, b"tail");
+        assert_eq!(replay.skipped, 0);
+        assert_eq!(Replay::new(sb.buf.clone(), sb.end_offset, None).skipped, 0);
+    }
+}
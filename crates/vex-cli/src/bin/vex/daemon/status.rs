@@ -0,0 +1,101 @@
+//! `status.json` next to `daemon.pid`, so supervisors can read the daemon's
+//! version and uptime without speaking the protocol.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::log_throttle::LogThrottle;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub version: String,
+    pub port: u16,
+    pub started_at: DateTime<Utc>,
+    /// Last time the daemon rewrote this file; stale if far in the past.
+    pub updated_at: DateTime<Utc>,
+}
+
+pub fn status_path(vex_dir: &Path) -> PathBuf {
+    vex_dir.join("status.json")
+}
+
+/// Read the status file, if present and well-formed.
+pub fn read(vex_dir: &Path) -> Option<DaemonStatus> {
+    std::fs::read_to_string(status_path(vex_dir))
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+}
+
+/// Written atomically so readers never see a partial file.
+fn write(vex_dir: &Path, status: &DaemonStatus) -> Result<()> {
+    super::write_atomic(
+        &status_path(vex_dir),
+        &serde_json::to_string_pretty(status)?,
+    )?;
+    Ok(())
+}
+
+pub fn remove(vex_dir: &Path) {
+    let _ = std::fs::remove_file(status_path(vex_dir));
+}
+
+/// Write the status file now and keep `updated_at` fresh in the background.
+pub fn spawn_status_task(vex_dir: &Path, port: u16) {
+    let vex_dir = vex_dir.to_path_buf();
+    let started_at = Utc::now();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        let mut throttle = LogThrottle::new(Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            let status = DaemonStatus {
+                pid: std::process::id(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                port,
+                started_at,
+                updated_at: Utc::now(),
+            };
+            if let Err(e) = write(&vex_dir, &status)
+                && throttle
+                    .check("status-write", std::time::Instant::now())
+                    .is_some()
+            {
+                warn!("failed to write status file: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read_round_trips_and_leaves_no_temp_file() {
+        let dir = std::env::temp_dir().join(format!("vex-status-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let status = DaemonStatus {
+            pid: 42,
+            version: "0.0.0".into(),
+            port: 6969,
+            started_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+
+        write(&dir, &status).unwrap();
+        assert_eq!(read(&dir), Some(status));
+        assert!(!status_path(&dir).with_extension("json.tmp").exists());
+
+        remove(&dir);
+        assert_eq!(read(&dir), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -4,17 +4,39 @@ use std::sync::Arc;
 
 use anyhow::{Result, bail};
 use chrono::Utc;
-use tokio::sync::Mutex;
-use vex_cli::proto::WorkstreamInfo;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+use vex_cli::proto::{GitState, WorkstreamInfo, WorkstreamSort};
 
 pub type WorkstreamStore = Arc<Mutex<WorkstreamStoreInner>>;
 
+/// Worktrees whose git status is read at once by `add_git_status`.
+const STATUS_CONCURRENCY: usize = 8;
+const STATUS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 struct WorkstreamData {
     worktree_path: PathBuf,
     repo_path: PathBuf,
     branch: String,
     created_at: chrono::DateTime<Utc>,
+    #[serde(default)]
+    notes: Option<String>,
+    /// The branch predates the workstream, so removing the workstream
+    /// leaves it alone.
+    #[serde(default)]
+    existing_branch: bool,
+}
+
+/// Where a new workstream's branch comes from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BranchSource {
+    /// A new branch off the repo's HEAD.
+    New,
+    /// A local branch that already exists.
+    Local,
+    /// A new local branch tracking `origin/<branch>`.
+    Origin,
 }
 
 pub struct WorkstreamStoreInner {
@@ -39,7 +61,50 @@ impl WorkstreamStoreInner {
         }
     }
 
+    /// The name check, `git worktree add` and the insert all happen under
+    /// the caller's store lock, so a duplicate fails before touching git.
     pub fn create(&mut self, repo_name: &str, name: &str, repo_path: &Path) -> Result<PathBuf> {
+        self.add(repo_name, name, repo_path, name, BranchSource::New)
+    }
+
+    /// Check out an existing branch in a new workstream named after it
+    /// (made a valid name if need be). A branch only on `origin` gets a local
+    /// branch tracking it. Returns the workstream's name and path.
+    pub fn create_from_branch(
+        &mut self,
+        repo_name: &str,
+        branch: &str,
+        repo_path: &Path,
+    ) -> Result<(String, PathBuf)> {
+        validate_branch(branch)?;
+        let source = if branch_exists(repo_path, branch) {
+            BranchSource::Local
+        } else if ref_exists(repo_path, &format!("refs/remotes/origin/{}", branch)) {
+            BranchSource::Origin
+        } else {
+            bail!("branch '{}' not found locally or on origin", branch);
+        };
+        let name = match validate_name(branch) {
+            Ok(()) => branch.to_string(),
+            Err(_) => suggest_name(branch),
+        };
+        if name.is_empty() {
+            bail!("cannot make a workstream name from branch '{}'", branch);
+        }
+        let path = self.add(repo_name, &name, repo_path, branch, source)?;
+        Ok((name, path))
+    }
+
+    fn add(
+        &mut self,
+        repo_name: &str,
+        name: &str,
+        repo_path: &Path,
+        branch: &str,
+        source: BranchSource,
+    ) -> Result<PathBuf> {
+        validate_name(name)?;
+
         // Check if already exists
         if let Some(repo_ws) = self.workstreams.get(repo_name)
             && repo_ws.contains_key(name)
@@ -54,19 +119,29 @@ impl WorkstreamStoreInner {
         let worktree_path = self.workstreams_base.join(repo_name).join(name);
         std::fs::create_dir_all(worktree_path.parent().unwrap())?;
 
-        // git -C <repo_path> worktree add -b <name> <worktree_path>
+        // Only a branch this call creates may be deleted on rollback; a
+        // pre-existing branch of the same name belongs to the user.
+        let branch_existed = branch_exists(repo_path, branch);
+
+        let worktree = worktree_path.to_string_lossy();
+        let upstream = format!("origin/{}", branch);
+        let args: Vec<&str> = match source {
+            BranchSource::New => vec!["-b", branch, &worktree],
+            BranchSource::Local => vec![&worktree, branch],
+            BranchSource::Origin => vec!["--track", "-b", branch, &worktree, &upstream],
+        };
+        // git -C <repo_path> worktree add <args>
         let output = std::process::Command::new("git")
             .args(["-C", &repo_path.to_string_lossy()])
-            .args([
-                "worktree",
-                "add",
-                "-b",
-                name,
-                &worktree_path.to_string_lossy(),
-            ])
+            .args(["worktree", "add"])
+            .args(&args)
             .output()?;
 
         if !output.status.success() {
+            // `worktree add -b` can create the branch and then fail the checkout
+            if !branch_existed && branch_exists(repo_path, branch) {
+                delete_branch(repo_path, branch);
+            }
             let stderr = String::from_utf8_lossy(&output.stderr);
             bail!("git worktree add failed: {}", stderr.trim());
         }
@@ -74,20 +149,48 @@ impl WorkstreamStoreInner {
         let data = WorkstreamData {
             worktree_path: worktree_path.clone(),
             repo_path: repo_path.to_path_buf(),
-            branch: name.to_string(),
+            branch: branch.to_string(),
             created_at: Utc::now(),
+            notes: None,
+            existing_branch: source == BranchSource::Local,
         };
 
         self.workstreams
             .entry(repo_name.to_string())
             .or_default()
             .insert(name.to_string(), data);
-        self.flush()?;
+        if let Err(e) = self.flush() {
+            self.forget(repo_name, name);
+            remove_worktree(repo_path, &worktree_path);
+            if !branch_existed {
+                delete_branch(repo_path, branch);
+            }
+            return Err(e);
+        }
 
         Ok(worktree_path)
     }
 
+    fn forget(&mut self, repo_name: &str, name: &str) {
+        if let Some(repo_ws) = self.workstreams.get_mut(repo_name) {
+            repo_ws.remove(name);
+            if repo_ws.is_empty() {
+                self.workstreams.remove(repo_name);
+            }
+        }
+    }
+
     pub fn remove(&mut self, repo_name: &str, name: &str) -> Result<()> {
+        self.remove_with(repo_name, name, true)
+    }
+
+    /// Remove a workstream's worktree and state but leave its branch, for
+    /// when the branch may hold work that exists nowhere else.
+    pub fn remove_keeping_branch(&mut self, repo_name: &str, name: &str) -> Result<()> {
+        self.remove_with(repo_name, name, false)
+    }
+
+    fn remove_with(&mut self, repo_name: &str, name: &str, delete: bool) -> Result<()> {
         let data = self
             .workstreams
             .get(repo_name)
@@ -97,31 +200,13 @@ impl WorkstreamStoreInner {
             })?
             .clone();
 
-        // git -C <repo_path> worktree remove <worktree_path> --force
-        let _ = std::process::Command::new("git")
-            .args(["-C", &data.repo_path.to_string_lossy()])
-            .args([
-                "worktree",
-                "remove",
-                &data.worktree_path.to_string_lossy(),
-                "--force",
-            ])
-            .output();
-
-        // git -C <repo_path> branch -D <branch>
-        let _ = std::process::Command::new("git")
-            .args(["-C", &data.repo_path.to_string_lossy()])
-            .args(["branch", "-D", &data.branch])
-            .output();
-
-        // Remove from store
-        if let Some(repo_ws) = self.workstreams.get_mut(repo_name) {
-            repo_ws.remove(name);
-            if repo_ws.is_empty() {
-                self.workstreams.remove(repo_name);
-            }
+        remove_worktree(&data.repo_path, &data.worktree_path);
+        if delete && !data.existing_branch {
+            delete_branch(&data.repo_path, &data.branch);
         }
 
+        self.forget(repo_name, name);
+
         // Clean up empty dirs
         let repo_dir = self.workstreams_base.join(repo_name);
         let _ = std::fs::remove_dir(&repo_dir);
@@ -129,7 +214,55 @@ impl WorkstreamStoreInner {
         self.flush()
     }
 
-    pub fn list(&self, repo_filter: Option<&str>) -> Vec<WorkstreamInfo> {
+    /// Rename a workstream. Only the name vex shows changes: the branch and
+    /// worktree directory keep theirs, so sessions and editors already open
+    /// in the worktree are unaffected.
+    pub fn rename(
+        &mut self,
+        repo_name: &str,
+        name: &str,
+        new_name: &str,
+    ) -> Result<WorkstreamInfo> {
+        validate_name(new_name)?;
+        let repo_ws = self
+            .workstreams
+            .get_mut(repo_name)
+            .filter(|ws| ws.contains_key(name))
+            .ok_or_else(|| {
+                anyhow::anyhow!("workstream '{}' not found for repo '{}'", name, repo_name)
+            })?;
+        if repo_ws.contains_key(new_name) {
+            bail!(
+                "workstream '{}' already exists for repo '{}'",
+                new_name,
+                repo_name
+            );
+        }
+
+        let data = repo_ws.remove(name).expect("checked above");
+        repo_ws.insert(new_name.to_string(), data.clone());
+        if let Err(e) = self.flush() {
+            let repo_ws = self.workstreams.get_mut(repo_name).expect("still present");
+            repo_ws.remove(new_name);
+            repo_ws.insert(name.to_string(), data);
+            return Err(e);
+        }
+        Ok(info(repo_name, new_name, &data))
+    }
+
+    pub fn set_notes(&mut self, repo_name: &str, name: &str, notes: Option<String>) -> Result<()> {
+        let data = self
+            .workstreams
+            .get_mut(repo_name)
+            .and_then(|ws| ws.get_mut(name))
+            .ok_or_else(|| {
+                anyhow::anyhow!("workstream '{}' not found for repo '{}'", name, repo_name)
+            })?;
+        data.notes = notes.filter(|n| !n.trim().is_empty());
+        self.flush()
+    }
+
+    pub fn list(&self, repo_filter: Option<&str>, sort: WorkstreamSort) -> Vec<WorkstreamInfo> {
         let mut result = Vec::new();
         for (repo_name, ws_map) in &self.workstreams {
             if let Some(filter) = repo_filter
@@ -138,18 +271,41 @@ impl WorkstreamStoreInner {
                 continue;
             }
             for (ws_name, data) in ws_map {
-                result.push(WorkstreamInfo {
-                    repo: repo_name.clone(),
-                    name: ws_name.clone(),
-                    worktree_path: data.worktree_path.clone(),
-                    branch: data.branch.clone(),
-                    created_at: data.created_at,
-                });
+                result.push(info(repo_name, ws_name, data));
             }
         }
+        sort_workstreams(&mut result, sort);
         result
     }
 
+    /// Workstreams whose worktree directory no longer exists.
+    pub fn missing_worktrees(&self) -> Vec<WorkstreamInfo> {
+        let mut missing = self.list(None, WorkstreamSort::Name);
+        missing.retain(|ws| ws.missing);
+        missing
+    }
+
+    /// Names of a repo's workstreams, sorted.
+    pub fn names_for_repo(&self, repo_name: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .workstreams
+            .get(repo_name)
+            .map(|ws| ws.keys().cloned().collect())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    /// The repo path and branch of a workstream whose branch vex created,
+    /// i.e. one `remove` would delete.
+    pub fn owned_branch(&self, repo_name: &str, name: &str) -> Option<(PathBuf, String)> {
+        self.workstreams
+            .get(repo_name)?
+            .get(name)
+            .filter(|d| !d.existing_branch)
+            .map(|d| (d.repo_path.clone(), d.branch.clone()))
+    }
+
     pub fn get_worktree_path(&self, repo_name: &str, name: &str) -> Option<PathBuf> {
         self.workstreams
             .get(repo_name)?
@@ -159,11 +315,787 @@ impl WorkstreamStoreInner {
 
     fn flush(&self) -> Result<()> {
         let data = serde_json::to_string_pretty(&self.workstreams)?;
-        std::fs::write(&self.persist_path, data)?;
+        super::write_atomic(&self.persist_path, &data)?;
         Ok(())
     }
 }
 
+/// A workstream as persisted, for read-only inspection by `vex daemon check`.
+pub struct PersistedWorkstream {
+    pub repo: String,
+    pub name: String,
+    pub worktree_path: PathBuf,
+    pub repo_path: PathBuf,
+}
+
+/// Strictly read `workstreams.json` without a store. A missing file means
+/// no workstreams.
+pub fn read_persisted(vex_dir: &Path) -> Result<Vec<PersistedWorkstream>> {
+    let data = match std::fs::read_to_string(vex_dir.join("workstreams.json")) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let parsed: HashMap<String, HashMap<String, WorkstreamData>> = serde_json::from_str(&data)?;
+    let mut result: Vec<PersistedWorkstream> = parsed
+        .into_iter()
+        .flat_map(|(repo, ws_map)| {
+            ws_map
+                .into_iter()
+                .map(move |(name, data)| PersistedWorkstream {
+                    repo: repo.clone(),
+                    name,
+                    worktree_path: data.worktree_path,
+                    repo_path: data.repo_path,
+                })
+        })
+        .collect();
+    result.sort_by(|a, b| a.repo.cmp(&b.repo).then_with(|| a.name.cmp(&b.name)));
+    Ok(result)
+}
+
 pub fn new_workstream_store(vex_dir: &Path) -> WorkstreamStore {
     Arc::new(Mutex::new(WorkstreamStoreInner::load(vex_dir)))
 }
+
+/// Workstream names become both a branch name and a directory under the vex
+/// dir, so keep them to a conservative charset: no path separators or `..`,
+/// nothing a shell or git would interpret, and no leading `-` that could be
+/// read as an option. Checked before any git command runs.
+fn validate_name(name: &str) -> Result<()> {
+    let Some(problem) = name_problem(name) else {
+        return Ok(());
+    };
+    let suggestion = suggest_name(name);
+    let hint = if suggestion.is_empty() {
+        String::new()
+    } else {
+        format!("; try '{}'", suggestion)
+    };
+    bail!(
+        "invalid workstream name '{}': {}{}",
+        name.escape_debug(),
+        problem,
+        hint
+    )
+}
+
+fn name_problem(name: &str) -> Option<String> {
+    if name.is_empty() {
+        return Some("it is empty".to_string());
+    }
+    if name.len() > 100 {
+        return Some("it is longer than 100 characters".to_string());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '-' | '_' | '.'))
+    {
+        return Some(format!(
+            "'{}' is not allowed; use letters, digits, '-', '_' or '.'",
+            c.escape_debug()
+        ));
+    }
+    if name.starts_with(['-', '.']) {
+        return Some("it must start with a letter or digit".to_string());
+    }
+    if name.contains("..") {
+        return Some("it contains '..'".to_string());
+    }
+    if name.ends_with(".lock") {
+        return Some("it ends with '.lock'".to_string());
+    }
+    if name.ends_with('.') {
+        return Some("it ends with '.'".to_string());
+    }
+    None
+}
+
+/// The nearest valid name to `name`: disallowed characters become `-`, runs
+/// of separators collapse and the ends are trimmed. Empty if nothing is left.
+fn suggest_name(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        let c = if c.is_ascii_alphanumeric() || matches!(c, '_' | '.') {
+            c
+        } else {
+            '-'
+        };
+        if matches!(c, '-' | '.') && slug.ends_with(['-', '.']) {
+            continue;
+        }
+        slug.push(c);
+    }
+    let mut slug = slug
+        .trim_start_matches(|c: char| !c.is_ascii_alphanumeric())
+        .to_string();
+    // Only ASCII is left, so truncating by bytes is safe
+    slug.truncate(100);
+    loop {
+        let trimmed = slug.trim_end_matches(['-', '.']);
+        match trimmed.strip_suffix(".lock") {
+            Some(rest) => slug = rest.to_string(),
+            None => return trimmed.to_string(),
+        }
+    }
+}
+
+fn info(repo_name: &str, name: &str, data: &WorkstreamData) -> WorkstreamInfo {
+    WorkstreamInfo {
+        repo: repo_name.to_string(),
+        name: name.to_string(),
+        worktree_path: data.worktree_path.clone(),
+        branch: data.branch.clone(),
+        created_at: data.created_at,
+        notes: data.notes.clone(),
+        git: None,
+        missing: !data.worktree_path.is_dir(),
+    }
+}
+
+/// Split `workstreams` for pruning into those nothing is using, and unused
+/// ones that must stay because they have uncommitted changes. A workstream
+/// is in use when any of `busy` is inside its worktree. Expects `git` filled
+/// in by `add_git_status`: missing worktrees have nothing to lose, and a
+/// status that couldn't be read counts as dirty.
+pub fn prune_candidates(
+    workstreams: Vec<WorkstreamInfo>,
+    busy: &[PathBuf],
+) -> (Vec<WorkstreamInfo>, Vec<WorkstreamInfo>) {
+    let mut prunable = Vec::new();
+    let mut dirty = Vec::new();
+    for ws in workstreams {
+        if busy.iter().any(|dir| dir.starts_with(&ws.worktree_path)) {
+            continue;
+        }
+        if ws.missing || ws.git.as_ref().is_some_and(|s| s.dirty_files == 0) {
+            prunable.push(ws);
+        } else {
+            dirty.push(ws);
+        }
+    }
+    (prunable, dirty)
+}
+
+fn git_status(worktree_path: &Path) -> Result<GitState> {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(["-C", &worktree_path.to_string_lossy()])
+            .args(args)
+            .output()
+    };
+
+    // git -C <worktree> status --porcelain
+    let output = git(&["status", "--porcelain"])?;
+    if !output.status.success() {
+        bail!(
+            "git status failed in {}: {}",
+            worktree_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let dirty_files = String::from_utf8_lossy(&output.stdout).lines().count() as u32;
+
+    // Fails when HEAD is detached
+    let output = git(&["symbolic-ref", "--quiet", "--short", "HEAD"])?;
+    let branch = output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+    // Fails without an upstream; prints "<behind>\t<ahead>"
+    let output = git(&["rev-list", "--left-right", "--count", "@{upstream}...HEAD"])?;
+    let (ahead, behind) = if output.status.success() {
+        let counts = String::from_utf8_lossy(&output.stdout);
+        let mut counts = counts.split_whitespace().map(str::parse::<u32>);
+        match (counts.next(), counts.next()) {
+            (Some(Ok(behind)), Some(Ok(ahead))) => (Some(ahead), Some(behind)),
+            _ => (None, None),
+        }
+    } else {
+        (None, None)
+    };
+
+    Ok(GitState {
+        branch,
+        dirty_files,
+        ahead,
+        behind,
+    })
+}
+
+/// `git_status` off the async runtime, giving up after `STATUS_TIMEOUT` so a
+/// slow or locked repo can't hold up the caller.
+pub async fn read_git_status(worktree_path: PathBuf) -> Result<GitState> {
+    let status = tokio::task::spawn_blocking(move || git_status(&worktree_path));
+    match tokio::time::timeout(STATUS_TIMEOUT, status).await {
+        Ok(joined) => joined?,
+        Err(_) => bail!("git status timed out after {:?}", STATUS_TIMEOUT),
+    }
+}
+
+/// Fill in `git` on each workstream, a few worktrees at a time. A worktree
+/// whose status can't be read within `STATUS_TIMEOUT` is left without one
+/// rather than holding up the whole list.
+pub async fn add_git_status(workstreams: &mut [WorkstreamInfo]) {
+    let limit = Arc::new(Semaphore::new(STATUS_CONCURRENCY));
+    let mut tasks = JoinSet::new();
+    for (i, ws) in workstreams.iter().enumerate() {
+        let path = ws.worktree_path.clone();
+        let limit = Arc::clone(&limit);
+        tasks.spawn(async move {
+            let _permit = limit.acquire_owned().await.ok()?;
+            read_git_status(path).await.ok().map(|status| (i, status))
+        });
+    }
+    while let Some(done) = tasks.join_next().await {
+        if let Ok(Some((i, status))) = done {
+            workstreams[i].git = Some(status);
+        }
+    }
+}
+
+/// Check a client-supplied name of an existing branch before it reaches git,
+/// where it could be read as an option, a refspec or a revision expression
+/// (`x:main`, `main~1`). Uses git's own ref-name rules.
+pub fn validate_branch(branch: &str) -> Result<()> {
+    if branch.starts_with('-') {
+        bail!(
+            "invalid branch name '{}': it starts with '-'",
+            branch.escape_debug()
+        );
+    }
+    // git check-ref-format refs/heads/<branch>
+    let valid = std::process::Command::new("git")
+        .args(["check-ref-format", &format!("refs/heads/{}", branch)])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+    if !valid {
+        bail!("invalid branch name '{}'", branch.escape_debug());
+    }
+    Ok(())
+}
+
+fn branch_exists(repo_path: &Path, branch: &str) -> bool {
+    ref_exists(repo_path, &format!("refs/heads/{}", branch))
+}
+
+fn ref_exists(repo_path: &Path, full_ref: &str) -> bool {
+    // git -C <repo_path> show-ref --verify --quiet <full_ref>
+    std::process::Command::new("git")
+        .args(["-C", &repo_path.to_string_lossy()])
+        .args(["show-ref", "--verify", "--quiet", full_ref])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+fn remove_worktree(repo_path: &Path, worktree_path: &Path) {
+    // git -C <repo_path> worktree remove <worktree_path> --force
+    let _ = std::process::Command::new("git")
+        .args(["-C", &repo_path.to_string_lossy()])
+        .args([
+            "worktree",
+            "remove",
+            &worktree_path.to_string_lossy(),
+            "--force",
+        ])
+        .output();
+}
+
+/// Whether deleting `branch` loses no commits: it is merged into the repo's
+/// HEAD, or everything on it has been pushed to its upstream. Anything git
+/// can't answer counts as unsafe.
+pub fn branch_is_merged_or_pushed(repo_path: &Path, branch: &str) -> bool {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(["-C", &repo_path.to_string_lossy()])
+            .args(args)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+    };
+    let full_ref = format!("refs/heads/{}", branch);
+    if git(&["merge-base", "--is-ancestor", &full_ref, "HEAD"]).is_some() {
+        return true;
+    }
+    git(&[
+        "rev-list",
+        "--count",
+        &format!("{}@{{upstream}}..{}", branch, full_ref),
+    ])
+    .is_some_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "0")
+}
+
+fn delete_branch(repo_path: &Path, branch: &str) {
+    // git -C <repo_path> branch -D <branch>
+    let _ = std::process::Command::new("git")
+        .args(["-C", &repo_path.to_string_lossy()])
+        .args(["branch", "-D", branch])
+        .output();
+}
+
+/// Order workstreams for presentation. Every key falls back to repo + name
+/// so the output is stable regardless of HashMap iteration order.
+fn sort_workstreams(workstreams: &mut [WorkstreamInfo], sort: WorkstreamSort) {
+    match sort {
+        WorkstreamSort::Created => workstreams.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| a.repo.cmp(&b.repo))
+                .then_with(|| a.name.cmp(&b.name))
+        }),
+        WorkstreamSort::Name => {
+            workstreams.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.repo.cmp(&b.repo)))
+        }
+        WorkstreamSort::Repo => {
+            workstreams.sort_by(|a, b| a.repo.cmp(&b.repo).then_with(|| a.name.cmp(&b.name)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn ws(repo: &str, name: &str, created_secs: i64) -> WorkstreamInfo {
+        WorkstreamInfo {
+            repo: repo.into(),
+            name: name.into(),
+            worktree_path: PathBuf::from(format!("/tmp/{}/{}", repo, name)),
+            branch: name.into(),
+            created_at: Utc.timestamp_opt(created_secs, 0).unwrap(),
+            notes: None,
+            git: None,
+            missing: false,
+        }
+    }
+
+    fn names(list: &[WorkstreamInfo]) -> Vec<String> {
+        list.iter()
+            .map(|w| format!("{}/{}", w.repo, w.name))
+            .collect()
+    }
+
+    fn sample() -> Vec<WorkstreamInfo> {
+        vec![
+            ws("vex", "beta", 200),
+            ws("api", "gamma", 300),
+            ws("vex", "alpha", 100),
+            ws("api", "alpha", 200),
+        ]
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-C", &dir.to_string_lossy()])
+            .args(["-c", "user.name=vex", "-c", "user.email=vex@test"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// A fresh vex dir plus a git repo with one commit inside it.
+    fn scratch() -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("vex-ws-test-{}", uuid::Uuid::new_v4()));
+        let repo = root.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        (root, repo)
+    }
+
+    #[test]
+    fn names_are_restricted_to_safe_characters() {
+        for ok in ["feat-1", "fix_auth", "v2.0", "A1"] {
+            assert!(validate_name(ok).is_ok(), "{}", ok);
+        }
+        for bad in [
+            "",
+            "x; rm -rf ~",
+            "$(touch pwned)",
+            "feat/x",
+            "../escape",
+            "a..b",
+            "-rf",
+            ".hidden",
+            "trailing.",
+            "branch.lock",
+            "with space",
+            "tab\tname",
+            "ünicode",
+        ] {
+            assert!(validate_name(bad).is_err(), "{:?}", bad);
+        }
+        assert!(validate_name(&"a".repeat(101)).is_err());
+    }
+
+    #[test]
+    fn invalid_names_say_why_and_suggest_a_fix() {
+        let err = |name: &str| validate_name(name).unwrap_err().to_string();
+        assert_eq!(
+            err("fix login bug"),
+            "invalid workstream name 'fix login bug': ' ' is not allowed; use letters, digits, '-', '_' or '.'; try 'fix-login-bug'"
+        );
+        assert_eq!(
+            err("feat/x"),
+            "invalid workstream name 'feat/x': '/' is not allowed; use letters, digits, '-', '_' or '.'; try 'feat-x'"
+        );
+        assert!(err("").ends_with("it is empty"));
+        assert!(err("-rf").contains("must start with a letter or digit; try 'rf'"));
+        assert!(err("a..b").contains("contains '..'; try 'a.b'"));
+        assert!(err("branch.lock").contains("ends with '.lock'; try 'branch'"));
+        assert!(err("v2.").contains("ends with '.'; try 'v2'"));
+        assert!(err(&"a".repeat(101)).contains("longer than 100 characters"));
+        assert!(err("$(touch pwned)").ends_with("try 'touch-pwned'"));
+        // Nothing usable left to suggest
+        assert!(err("../").ends_with("use letters, digits, '-', '_' or '.'"));
+    }
+
+    #[test]
+    fn suggested_names_are_valid() {
+        for bad in [
+            "x; rm -rf ~",
+            "../escape",
+            ".hidden",
+            "a.lock.lock",
+            "tab\tname",
+            "ünicode",
+            "trailing-.",
+            &"ab ".repeat(50),
+            &format!("{}.lock", "a".repeat(98)),
+        ] {
+            let suggestion = suggest_name(bad);
+            assert!(
+                validate_name(&suggestion).is_ok(),
+                "{:?} -> {:?}",
+                bad,
+                suggestion
+            );
+        }
+    }
+
+    #[test]
+    fn create_rejects_injection_before_touching_git() {
+        let (root, repo) = scratch();
+        let mut store = WorkstreamStoreInner::load(&root);
+        let err = store
+            .create("repo", "x; touch pwned", &repo)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("invalid workstream name"));
+        assert!(!repo.join("pwned").exists());
+        assert!(!root.join("workstreams").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failed_create_deletes_the_branch_it_created() {
+        let (root, repo) = scratch();
+        let mut store = WorkstreamStoreInner::load(&root);
+        // A directory where the store file should be makes the flush fail
+        // after the worktree and branch already exist.
+        std::fs::create_dir_all(root.join("workstreams.json")).unwrap();
+
+        assert!(store.create("repo", "feature", &repo).is_err());
+        assert!(!branch_exists(&repo, "feature"));
+        assert!(!root.join("workstreams/repo/feature").exists());
+        assert!(store.list(None, WorkstreamSort::default()).is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn git_status_counts_changes_and_commits_against_upstream() {
+        let (root, repo) = scratch();
+        let status = git_status(&repo).unwrap();
+        assert_eq!(status.dirty_files, 0);
+        assert_eq!((status.ahead, status.behind), (None, None));
+
+        let clone = root.join("clone");
+        git(&root, &["clone", "-q", "repo", "clone"]);
+        git(&clone, &["commit", "-q", "--allow-empty", "-m", "one"]);
+        git(&clone, &["commit", "-q", "--allow-empty", "-m", "two"]);
+        std::fs::write(clone.join("a.txt"), "a").unwrap();
+        std::fs::write(clone.join("b.txt"), "b").unwrap();
+        let status = git_status(&clone).unwrap();
+        assert!(status.branch.is_some());
+        assert_eq!(status.dirty_files, 2);
+        assert_eq!((status.ahead, status.behind), (Some(2), Some(0)));
+
+        git(&clone, &["checkout", "-q", "--detach"]);
+        let status = git_status(&clone).unwrap();
+        assert_eq!(status.branch, None);
+        assert_eq!((status.ahead, status.behind), (None, None));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn git_status_is_only_added_on_request() {
+        let (root, repo) = scratch();
+        let mut store = WorkstreamStoreInner::load(&root);
+        store.create("repo", "feature", &repo).unwrap();
+        store.create("repo", "other", &repo).unwrap();
+        std::fs::write(root.join("workstreams/repo/feature/new.txt"), "x").unwrap();
+
+        let mut list = store.list(None, WorkstreamSort::Name);
+        assert!(list.iter().all(|ws| ws.git.is_none()));
+
+        add_git_status(&mut list).await;
+        let git: Vec<_> = list.iter().map(|ws| ws.git.clone().unwrap()).collect();
+        assert_eq!(git[0].branch.as_deref(), Some("feature"));
+        assert_eq!(git[0].dirty_files, 1);
+        assert_eq!(git[1].branch.as_deref(), Some("other"));
+        assert_eq!(git[1].dirty_files, 0);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn worktrees_deleted_outside_vex_are_flagged() {
+        let (root, repo) = scratch();
+        let mut store = WorkstreamStoreInner::load(&root);
+        let path = store.create("repo", "gone", &repo).unwrap();
+        store.create("repo", "kept", &repo).unwrap();
+        assert!(store.missing_worktrees().is_empty());
+
+        std::fs::remove_dir_all(&path).unwrap();
+        let list = store.list(None, WorkstreamSort::Name);
+        assert_eq!(
+            list.iter().map(|ws| ws.missing).collect::<Vec<_>>(),
+            [true, false]
+        );
+        let missing = store.missing_worktrees();
+        assert_eq!(names(&missing), ["repo/gone"]);
+
+        // Removing it still cleans up the branch
+        store.remove("repo", "gone").unwrap();
+        assert!(!branch_exists(&repo, "gone"));
+        assert!(store.missing_worktrees().is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn prune_skips_busy_and_dirty_worktrees() {
+        let (root, repo) = scratch();
+        let mut store = WorkstreamStoreInner::load(&root);
+        let busy = store.create("repo", "busy", &repo).unwrap();
+        let dirty = store.create("repo", "dirty", &repo).unwrap();
+        store.create("repo", "idle", &repo).unwrap();
+        let gone = store.create("repo", "gone", &repo).unwrap();
+        std::fs::write(dirty.join("wip.txt"), "x").unwrap();
+        std::fs::remove_dir_all(&gone).unwrap();
+        std::fs::create_dir_all(busy.join("src")).unwrap();
+
+        let mut list = store.list(None, WorkstreamSort::Name);
+        add_git_status(&mut list).await;
+        let (prunable, kept) = prune_candidates(list, &[busy.join("src"), root.join("elsewhere")]);
+        assert_eq!(names(&prunable), ["repo/gone", "repo/idle"]);
+        assert_eq!(names(&kept), ["repo/dirty"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unmerged_unpushed_branches_are_kept() {
+        let (root, repo) = scratch();
+        let mut store = WorkstreamStoreInner::load(&root);
+        let merged = store.create("repo", "merged", &repo).unwrap();
+        let local = store.create("repo", "local", &repo).unwrap();
+        for dir in [&merged, &local] {
+            let message = format!("work in {}", dir.display());
+            git(dir, &["commit", "-q", "--allow-empty", "-m", &message]);
+        }
+        git(&repo, &["merge", "-q", "--ff-only", "merged"]);
+        store.create("repo", "fresh", &repo).unwrap();
+
+        assert!(branch_is_merged_or_pushed(&repo, "merged"));
+        assert!(branch_is_merged_or_pushed(&repo, "fresh"));
+        assert!(!branch_is_merged_or_pushed(&repo, "local"));
+        assert!(!branch_is_merged_or_pushed(&repo, "nope"));
+
+        // Pushed work is safe to delete
+        let remote = root.join("remote.git");
+        git(&root, &["init", "-q", "--bare", &remote.to_string_lossy()]);
+        git(
+            &repo,
+            &["remote", "add", "origin", &remote.to_string_lossy()],
+        );
+        git(&local, &["push", "-q", "-u", "origin", "local"]);
+        assert!(branch_is_merged_or_pushed(&repo, "local"));
+
+        assert_eq!(
+            store.owned_branch("repo", "local"),
+            Some((repo.clone(), "local".into()))
+        );
+        store.remove_keeping_branch("repo", "local").unwrap();
+        assert!(!local.exists());
+        assert!(branch_exists(&repo, "local"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_creates_of_one_name_make_one_worktree() {
+        let (root, repo) = scratch();
+        let store = new_workstream_store(&root);
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let store = Arc::clone(&store);
+                let repo = repo.clone();
+                tokio::spawn(async move { store.lock().await.create("repo", "feature", &repo) })
+            })
+            .collect();
+        let mut created = 0;
+        for task in tasks {
+            match task.await.unwrap() {
+                Ok(_) => created += 1,
+                Err(e) => assert!(e.to_string().contains("already exists"), "{}", e),
+            }
+        }
+        assert_eq!(created, 1);
+
+        let worktrees = std::process::Command::new("git")
+            .args([
+                "-C",
+                &repo.to_string_lossy(),
+                "worktree",
+                "list",
+                "--porcelain",
+            ])
+            .output()
+            .unwrap();
+        let worktrees = String::from_utf8_lossy(&worktrees.stdout);
+        // The main checkout plus the one workstream
+        assert_eq!(worktrees.matches("worktree ").count(), 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn creates_from_local_and_origin_branches() {
+        let (root, origin) = scratch();
+        git(&origin, &["branch", "fix/remote"]);
+        let repo = root.join("clone");
+        git(
+            &root,
+            &[
+                "clone",
+                "-q",
+                &origin.to_string_lossy(),
+                &repo.to_string_lossy(),
+            ],
+        );
+        git(&repo, &["branch", "local"]);
+        let mut store = WorkstreamStoreInner::load(&root);
+
+        let (name, path) = store.create_from_branch("repo", "local", &repo).unwrap();
+        assert_eq!(name, "local");
+        assert!(path.is_dir());
+        let (name, _) = store
+            .create_from_branch("repo", "fix/remote", &repo)
+            .unwrap();
+        assert_eq!(name, "fix-remote");
+        assert!(branch_exists(&repo, "fix/remote"));
+
+        let err = store.create_from_branch("repo", "gone", &repo).unwrap_err();
+        assert!(err.to_string().contains("not found"), "{}", err);
+        assert!(!root.join("workstreams/repo/gone").exists());
+
+        // Revision expressions and refspecs aren't branch names
+        for bad in ["local~1", "local@{1}", "x:local", "-b"] {
+            let err = store.create_from_branch("repo", bad, &repo).unwrap_err();
+            assert!(err.to_string().contains("invalid branch name"), "{}", err);
+        }
+
+        let reloaded = WorkstreamStoreInner::load(&root);
+        let list = reloaded.list(None, WorkstreamSort::Name);
+        assert_eq!(names(&list), ["repo/fix-remote", "repo/local"]);
+        assert_eq!(list[0].branch, "fix/remote");
+
+        // The branch existed before the workstream, so it outlives it
+        store.remove("repo", "local").unwrap();
+        assert!(branch_exists(&repo, "local"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failed_create_keeps_a_pre_existing_branch() {
+        let (root, repo) = scratch();
+        git(&repo, &["branch", "existing"]);
+        let mut store = WorkstreamStoreInner::load(&root);
+
+        assert!(store.create("repo", "existing", &repo).is_err());
+        assert!(branch_exists(&repo, "existing"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rename_keeps_branch_and_worktree() {
+        let (root, repo) = scratch();
+        let mut store = WorkstreamStoreInner::load(&root);
+        let path = store.create("repo", "feature", &repo).unwrap();
+        store.create("repo", "other", &repo).unwrap();
+
+        let renamed = store.rename("repo", "feature", "auth-rework").unwrap();
+        assert_eq!(renamed.name, "auth-rework");
+        assert_eq!(renamed.branch, "feature");
+        assert_eq!(renamed.worktree_path, path);
+        assert!(path.exists());
+
+        // Persisted under the new name only
+        let reloaded = WorkstreamStoreInner::load(&root);
+        assert_eq!(reloaded.names_for_repo("repo"), ["auth-rework", "other"]);
+
+        let err = store.rename("repo", "auth-rework", "other").unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{}", err);
+        let err = store.rename("repo", "feature", "x").unwrap_err();
+        assert!(err.to_string().contains("not found"), "{}", err);
+        let err = store.rename("repo", "other", "bad name").unwrap_err();
+        assert!(
+            err.to_string().contains("invalid workstream name"),
+            "{}",
+            err
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sort_created_newest_first() {
+        let mut list = sample();
+        sort_workstreams(&mut list, WorkstreamSort::Created);
+        assert_eq!(
+            names(&list),
+            ["api/gamma", "api/alpha", "vex/beta", "vex/alpha"]
+        );
+    }
+
+    #[test]
+    fn sort_by_name() {
+        let mut list = sample();
+        sort_workstreams(&mut list, WorkstreamSort::Name);
+        assert_eq!(
+            names(&list),
+            ["api/alpha", "vex/alpha", "vex/beta", "api/gamma"]
+        );
+    }
+
+    #[test]
+    fn sort_by_repo() {
+        let mut list = sample();
+        sort_workstreams(&mut list, WorkstreamSort::Repo);
+        assert_eq!(
+            names(&list),
+            ["api/alpha", "api/gamma", "vex/alpha", "vex/beta"]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+
+/// How often `--follow` checks the log for new lines.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn parse(word: &str) -> Option<Self> {
+        match word {
+            "TRACE" => Some(Self::Trace),
+            "DEBUG" => Some(Self::Debug),
+            "INFO" => Some(Self::Info),
+            "WARN" => Some(Self::Warn),
+            "ERROR" => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Selects daemon log lines by minimum level and a plain substring.
+pub struct LogFilter {
+    pattern: Option<String>,
+    min_level: Option<Level>,
+    /// Level of the last record seen. Lines without one (e.g. the rest of a
+    /// multi-line hook error) belong to the record above them.
+    current: Option<Level>,
+}
+
+impl LogFilter {
+    pub fn new(pattern: Option<String>, min_level: Option<Level>) -> Self {
+        Self {
+            pattern,
+            min_level,
+            current: None,
+        }
+    }
+
+    pub fn matches(&mut self, line: &str) -> bool {
+        let plain = strip_ansi(line);
+        if let Some(level) = record_level(&plain) {
+            self.current = Some(level);
+        }
+        if let Some(min) = self.min_level
+            && self.current.is_none_or(|level| level < min)
+        {
+            return false;
+        }
+        match &self.pattern {
+            Some(pattern) => plain.contains(pattern.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// The level of a tracing record, which follows its timestamp:
+/// `2026-01-01T00:00:00.000000Z  WARN vex::daemon: ...`.
+fn record_level(plain: &str) -> Option<Level> {
+    let mut words = plain.split_whitespace();
+    let timestamp = words.next()?;
+    if !timestamp.ends_with('Z') || !timestamp.contains('T') {
+        return None;
+    }
+    Level::parse(words.next()?)
+}
+
+/// Drop the color escapes the daemon writes into its log.
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI: ESC [ params... final byte in @..~
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Print matching lines from the log, then with `follow` keep printing new
+/// ones as they are written. A log that is truncated or replaced is reopened
+/// from the start.
+pub fn show(log_path: &Path, follow: bool, filter: &mut LogFilter) -> Result<()> {
+    if !log_path.exists() {
+        bail!("no log file found (has the daemon been started?)");
+    }
+    let mut file = File::open(log_path)?;
+    let mut inode = file.metadata()?.ino();
+    let mut pos = print_new_lines(&mut file, 0, filter)?;
+    if !follow {
+        return Ok(());
+    }
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        if let Ok(meta) = std::fs::metadata(log_path)
+            && (meta.ino() != inode || meta.len() < pos)
+        {
+            file = File::open(log_path)?;
+            inode = file.metadata()?.ino();
+            pos = 0;
+        }
+        pos = print_new_lines(&mut file, pos, filter)?;
+    }
+}
+
+/// Print complete lines after `pos` that pass the filter. Returns the offset
+/// just past the last complete line, so a half-written line is picked up
+/// whole on the next call.
+fn print_new_lines(file: &mut File, pos: u64, filter: &mut LogFilter) -> Result<u64> {
+    file.seek(SeekFrom::Start(pos))?;
+    let mut reader = BufReader::new(file);
+    let mut pos = pos;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf)?;
+        if n == 0 || buf.last() != Some(&b'\n') {
+            return Ok(pos);
+        }
+        pos += n as u64;
+        let line = String::from_utf8_lossy(&buf);
+        if filter.matches(line.trim_end_matches('\n')) {
+            print!("{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &[&str] = &[
+        "\x1b[2m2026-10-16T00:31:46.705140Z\x1b[0m \x1b[32m INFO\x1b[0m \x1b[2mvex::daemon\x1b[0m\x1b[2m:\x1b[0m daemon listening on 127.0.0.1:6969",
+        "2026-10-16T00:31:47.000000Z  WARN vex::daemon::handler: hook 'make' failed (exit code 2):",
+        "make: *** No rule to make target",
+        "2026-10-16T00:31:48.000000Z ERROR vex::daemon: accept error: too many open files",
+        "2026-10-16T00:31:49.000000Z DEBUG vex::daemon: client 1234 disconnected",
+    ];
+
+    fn run(filter: &mut LogFilter) -> Vec<usize> {
+        (0..SAMPLE.len())
+            .filter(|&i| filter.matches(SAMPLE[i]))
+            .collect()
+    }
+
+    #[test]
+    fn no_filter_keeps_everything() {
+        assert_eq!(run(&mut LogFilter::new(None, None)), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn level_keeps_that_level_and_above() {
+        assert_eq!(run(&mut LogFilter::new(None, Some(Level::Warn))), [1, 2, 3]);
+        assert_eq!(run(&mut LogFilter::new(None, Some(Level::Error))), [3]);
+        assert_eq!(
+            run(&mut LogFilter::new(None, Some(Level::Info))),
+            [0, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn grep_matches_text_without_color_codes() {
+        let mut filter = LogFilter::new(Some("vex::daemon: daemon listening".into()), None);
+        assert_eq!(run(&mut filter), [0]);
+        let mut filter = LogFilter::new(Some("error".into()), Some(Level::Error));
+        assert_eq!(run(&mut filter), [3]);
+    }
+
+    #[test]
+    fn partial_lines_wait_for_their_newline() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("vex-logs-{}.log", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "one\ntw").unwrap();
+        let mut file = File::open(&path).unwrap();
+        let mut filter = LogFilter::new(None, None);
+
+        // The partial line is left for the next read
+        let pos = print_new_lines(&mut file, 0, &mut filter).unwrap();
+        assert_eq!(pos, 4);
+        let mut append = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        append.write_all(b"o\n").unwrap();
+        assert_eq!(print_new_lines(&mut file, pos, &mut filter).unwrap(), 8);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
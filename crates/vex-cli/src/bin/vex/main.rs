@@ -1,8 +1,11 @@
 mod agent;
+mod cache;
 mod client;
 mod daemon;
+mod logs;
 mod repo;
 mod session;
+mod table;
 mod workstream;
 
 use std::net::SocketAddr;
@@ -14,17 +17,78 @@ use clap::{CommandFactory, Parser, Subcommand};
 use nix::sys::signal::{Signal, kill};
 use nix::unistd::Pid;
 use serde::{Deserialize, Serialize};
+use vex_cli::proto::{ClientMessage, ServerMessage, WorkstreamSort};
 
 const DEFAULT_PORT: u16 = 6969;
 
-fn vex_dir() -> PathBuf {
-    if let Ok(dir) = std::env::var("VEX_DIR") {
-        PathBuf::from(dir)
-    } else {
-        dirs::home_dir()
-            .expect("could not determine home directory")
-            .join(".vex")
+fn vex_dir() -> Result<PathBuf> {
+    resolve_vex_dir(
+        std::env::var_os("VEX_DIR").map(PathBuf::from),
+        dirs::home_dir(),
+        std::env::var_os("XDG_STATE_HOME").map(PathBuf::from),
+        std::env::var_os("XDG_DATA_HOME").map(PathBuf::from),
+        is_writable_location,
+    )
+}
+
+/// `VEX_DIR` always wins. Otherwise prefer `~/.vex`, falling back to
+/// `$XDG_STATE_HOME/vex` and then `$XDG_DATA_HOME/vex` when the home
+/// directory is missing or read-only. If nothing is writable the first
+/// candidate is returned so the eventual error names the expected location.
+fn resolve_vex_dir(
+    explicit: Option<PathBuf>,
+    home: Option<PathBuf>,
+    xdg_state: Option<PathBuf>,
+    xdg_data: Option<PathBuf>,
+    writable: impl Fn(&Path) -> bool,
+) -> Result<PathBuf> {
+    if let Some(dir) = explicit {
+        return Ok(dir);
     }
+    let candidates: Vec<PathBuf> = [
+        home.map(|h| h.join(".vex")),
+        xdg_state.map(|d| d.join("vex")),
+        xdg_data.map(|d| d.join("vex")),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    candidates
+        .iter()
+        .find(|c| writable(c))
+        .or(candidates.first())
+        .cloned()
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "could not determine a vex directory; set VEX_DIR to a writable location"
+            )
+        })
+}
+
+/// Whether `dir` could be created (or already exists) and written to, judged
+/// by its nearest existing ancestor. Does not touch the filesystem.
+fn is_writable_location(dir: &Path) -> bool {
+    use nix::unistd::{AccessFlags, access};
+    dir.ancestors()
+        .find(|p| p.exists())
+        .is_some_and(|p| p.is_dir() && access(p, AccessFlags::W_OK).is_ok())
+}
+
+/// Create the vex directory and verify we can write into it, so an
+/// unwritable home surfaces as an actionable error instead of a failure
+/// deep in daemon startup.
+fn ensure_writable_dir(dir: &Path) -> Result<()> {
+    let probe = dir.join(".write-test");
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "cannot write to {}: {}; set VEX_DIR to a writable location",
+                dir.display(),
+                e
+            )
+        })
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,12 +97,49 @@ struct SavedConnection {
     tunnel_port: u16,
 }
 
-fn load_saved_connection(vex_dir: &Path) -> Option<SavedConnection> {
-    let path = vex_dir.join("connect.json");
-    let data = std::fs::read_to_string(path).ok()?;
+/// Location of the saved-connection file: `--config`/`VEX_CONFIG` when
+/// given, otherwise `connect.json` inside the vex directory.
+fn connection_config_path(vex_dir: &Path, override_path: Option<PathBuf>) -> PathBuf {
+    override_path.unwrap_or_else(|| vex_dir.join("connect.json"))
+}
+
+/// SSH control socket of the tunnel saved in `config_path`. Each connection
+/// config gets its own, so profiles don't replace or close each other's
+/// tunnels. Kept in the vex directory because socket paths are short; the
+/// default config keeps the original `ssh.sock`.
+fn control_socket_path(vex_dir: &Path, config_path: &Path) -> PathBuf {
+    if config_path == vex_dir.join("connect.json") {
+        return vex_dir.join("ssh.sock");
+    }
+    let config_path = std::path::absolute(config_path).unwrap_or_else(|_| config_path.into());
+    // FNV-1a: stable across builds, unlike std's hasher
+    let hash = config_path
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |h, &b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+    vex_dir.join(format!("ssh-{:016x}.sock", hash))
+}
+
+fn load_saved_connection(config_path: &Path) -> Option<SavedConnection> {
+    let data = std::fs::read_to_string(config_path).ok()?;
     serde_json::from_str(&data).ok()
 }
 
+fn save_connection(config_path: &Path, conn: &SavedConnection) -> Result<()> {
+    if let Some(parent) = config_path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.is_dir()
+    {
+        bail!("config directory does not exist: {}", parent.display());
+    }
+    let data = serde_json::to_string(conn)?;
+    std::fs::write(config_path, data)?;
+    Ok(())
+}
+
 #[derive(Parser)]
 #[command(name = "vex", about = concat!("Vex terminal multiplexer v", env!("CARGO_PKG_VERSION")), version)]
 struct Cli {
@@ -46,6 +147,18 @@ struct Cli {
     #[arg(long, env = "VEX_PORT", default_value_t = DEFAULT_PORT)]
     port: u16,
 
+    /// Connection config file (defaults to $VEX_DIR/connect.json)
+    #[arg(long, env = "VEX_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Reach this SSH host for a single command, without saving a connection
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Print list and status output as JSON instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -120,44 +233,109 @@ enum SessionCommand {
     Attach {
         /// Session ID or unique prefix
         id: String,
+        /// Fixed terminal width, instead of following this terminal's size
+        /// (for capturing a session from a script)
+        #[arg(long, requires = "rows")]
+        cols: Option<u16>,
+        /// Fixed terminal height
+        #[arg(long, requires = "cols")]
+        rows: Option<u16>,
     },
 }
 
 #[derive(Subcommand)]
 enum DaemonCommand {
     /// Start the daemon in the background
-    Start,
+    Start {
+        /// Allow running the daemon as root
+        #[arg(long, env = "VEX_ALLOW_ROOT", value_parser = clap::builder::BoolishValueParser::new())]
+        allow_root: bool,
+    },
     /// Stop the running daemon
     Stop,
     /// Show daemon status
     Status,
+    /// Validate the daemon's state files without starting it
+    Check,
+    /// Forcibly end every shell session on the local daemon (agents are
+    /// left running)
+    ResetShells {
+        /// Only end shells started in this repo
+        #[arg(short = 'r', long = "repo")]
+        repo: Option<String>,
+    },
     /// Show daemon logs
     Logs {
         /// Follow log output
         #[arg(short, long)]
         follow: bool,
+        /// Only show lines containing this text
+        #[arg(long, value_name = "TEXT")]
+        grep: Option<String>,
+        /// Only show lines at this level or above
+        #[arg(long, value_enum)]
+        level: Option<logs::Level>,
     },
     /// Run the daemon (internal)
     #[command(hide = true)]
-    Run,
+    Run {
+        /// Allow running the daemon as root
+        #[arg(long, env = "VEX_ALLOW_ROOT", value_parser = clap::builder::BoolishValueParser::new())]
+        allow_root: bool,
+    },
 }
 
 #[derive(Subcommand)]
 enum AgentCommand {
     /// List detected Claude Code agents
     #[command(alias = "ls")]
-    List,
+    List {
+        /// Print each agent with a template instead of a table, e.g.
+        /// '{session}\t{pid}'. Fields: session, claude_session, pid, cwd,
+        /// detected, needs_intervention, prompts, last_prompt, prompt
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+        /// Only agents whose last prompt sent through vex contains TEXT,
+        /// ignoring case
+        #[arg(long, value_name = "TEXT")]
+        grep: Option<String>,
+        /// Print prompts in full instead of truncating them
+        #[arg(long)]
+        full_prompt: bool,
+    },
     /// Show agents that need human intervention
     #[command(alias = "notif")]
     Notifications,
-    /// Watch a Claude Code agent's conversation
+    /// Watch Claude Code agents' conversations
     Watch {
-        /// Vex session ID or unique prefix
-        id: String,
+        /// Vex session IDs or unique prefixes; with more than one, lines are
+        /// tagged with the agent they came from
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        ids: Vec<String>,
+        /// Watch every detected agent
+        #[arg(long)]
+        all: bool,
         /// Show thinking blocks
         #[arg(long)]
         show_thinking: bool,
     },
+    /// Start an ended agent again with the same command, environment and
+    /// directory (see the ended agents in `agent list`)
+    Restart {
+        /// Vex session ID or unique prefix of the ended agent
+        id: String,
+        /// Attach to the new session
+        #[arg(short, long)]
+        attach: bool,
+    },
+    /// Print recent terminal output of an agent's session
+    Logs {
+        /// Vex session ID or unique prefix
+        id: String,
+        /// Only the last N lines
+        #[arg(short = 'n', long)]
+        lines: Option<u32>,
+    },
     /// Spawn a Claude Code agent in a repo
     Spawn {
         /// Repository name
@@ -166,6 +344,15 @@ enum AgentCommand {
         /// Workstream to spawn in
         #[arg(short = 'w', long = "workstream")]
         workstream: Option<String>,
+        /// Set an environment variable for the agent (repeatable)
+        #[arg(short = 'e', long = "env", value_name = "KEY=VALUE", value_parser = agent::parse_env_var)]
+        env: Vec<(String, String)>,
+        /// Start in this subdirectory of the repo or workstream
+        #[arg(long, value_name = "DIR")]
+        cwd: Option<PathBuf>,
+        /// Run a named agent profile from the daemon's config.yml
+        #[arg(short, long)]
+        profile: Option<String>,
         /// Attach to the session immediately
         #[arg(short, long)]
         attach: bool,
@@ -212,10 +399,17 @@ enum RepoCommand {
     Remove {
         /// Repository name
         name: String,
+        /// Also remove the repo's workstreams (worktrees and branches)
+        #[arg(long)]
+        delete_workstreams: bool,
     },
     /// List registered repositories
     #[command(alias = "ls")]
-    List,
+    List {
+        /// Serve from a short-lived local cache when possible
+        #[arg(long)]
+        fast: bool,
+    },
     /// Introspect a path for repository information
     IntrospectPath {
         /// Path to introspect
@@ -227,16 +421,49 @@ enum RepoCommand {
 enum WorkstreamCommand {
     /// Create a new workstream (git worktree + branch)
     Create {
+        /// Defaults to the registered repo containing the current directory
         #[arg(short = 'r', long = "repo")]
-        repo: String,
+        repo: Option<String>,
         /// Workstream name (also used as branch name)
-        name: String,
+        #[arg(required_unless_present = "branches")]
+        name: Option<String>,
+        /// Check out an existing branch (local, or from origin) instead of
+        /// creating one; repeat for one workstream per branch
+        #[arg(
+            short = 'b',
+            long = "branch",
+            value_name = "BRANCH",
+            conflicts_with = "name"
+        )]
+        branches: Vec<String>,
+        /// Fetch each --branch from origin first instead of relying on the
+        /// last prefetch
+        #[arg(long, conflicts_with = "name")]
+        fetch: bool,
+        /// Open the new worktree in `editor_command` from config.yml, or
+        /// $VISUAL / $EDITOR (local daemon only)
+        #[arg(long, conflicts_with = "branches")]
+        open_editor: bool,
     },
     /// List workstreams
     #[command(alias = "ls")]
     List {
         #[arg(short = 'r', long = "repo")]
         repo: Option<String>,
+        /// Sort order: created (newest first), name, or repo
+        #[arg(long)]
+        sort: Option<WorkstreamSort>,
+        /// Serve from a short-lived local cache when possible
+        #[arg(long)]
+        fast: bool,
+        /// Print each workstream with a template instead of a table, e.g.
+        /// '{repo}\t{name}\t{branch}'. Fields: repo, name, branch, path,
+        /// created, notes, dirty, ahead, behind, missing
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+        /// Show each worktree's uncommitted changes and upstream drift
+        #[arg(short, long)]
+        status: bool,
     },
     /// Remove a workstream
     Remove {
@@ -245,12 +472,90 @@ enum WorkstreamCommand {
         /// Workstream name
         name: String,
     },
+    /// Remove workstreams no session or agent is working in; worktrees with
+    /// uncommitted changes are kept. Where a shell's current directory can't
+    /// be read (non-Linux hosts), nothing is pruned while shells are open
+    Prune {
+        /// Only prune this repo's workstreams
+        #[arg(short = 'r', long = "repo")]
+        repo: Option<String>,
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Attach a free-form note to a workstream
+    Note {
+        #[arg(short = 'r', long = "repo")]
+        repo: String,
+        /// Workstream name
+        name: String,
+        /// Note text (omit to clear the note)
+        text: Option<String>,
+    },
+    /// Rename a workstream (its branch and worktree keep their names)
+    Rename {
+        #[arg(short = 'r', long = "repo")]
+        repo: String,
+        /// Current workstream name
+        name: String,
+        /// New workstream name
+        new_name: String,
+    },
+    /// Show a workstream's branch, uncommitted changes and upstream drift
+    Status {
+        #[arg(short = 'r', long = "repo")]
+        repo: String,
+        /// Workstream name
+        name: String,
+    },
+    /// Print a workstream's worktree path, e.g. cd "$(vex ws path -r vex feat-1)"
+    Path {
+        #[arg(short = 'r', long = "repo")]
+        repo: String,
+        /// Workstream name
+        name: String,
+    },
+    /// Run a command in a workstream's worktree and print its output
+    Exec {
+        #[arg(short = 'r', long = "repo")]
+        repo: String,
+        /// Workstream name
+        name: String,
+        /// Kill the command after this many seconds (default 60)
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+        /// Command to run with `sh -c`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
 }
 
 // ── Daemon management ────────────────────────────────────────────
 
+/// Whether `--host` means anything for `command`. Daemon management,
+/// saved connections and completions always act on this machine.
+fn uses_host(command: &Command) -> bool {
+    !matches!(
+        command,
+        Command::Daemon { .. } | Command::Remote { .. } | Command::Completions { .. }
+    )
+}
+
+/// Refuse to run the daemon as root unless explicitly allowed: worktrees and
+/// agent processes it creates would be owned by root and run with full
+/// privileges, which is almost always an accident (sudo, containers).
+fn check_root(uid: u32, allow_root: bool) -> Result<()> {
+    if uid == 0 && !allow_root {
+        bail!(
+            "refusing to run the daemon as root; worktrees and agents would be owned by root. \
+             Run as a regular user, or pass --allow-root (or set VEX_ALLOW_ROOT=1) if you really mean it"
+        );
+    }
+    Ok(())
+}
+
 fn daemon_start(vex_dir: &Path, port: u16) -> Result<()> {
-    std::fs::create_dir_all(vex_dir)?;
+    ensure_writable_dir(vex_dir)?;
 
     // Check if already running
     let pid_path = vex_dir.join("daemon.pid");
@@ -261,6 +566,9 @@ fn daemon_start(vex_dir: &Path, port: u16) -> Result<()> {
         eprintln!("daemon already running (pid {})", pid);
         return Ok(());
     }
+    // Otherwise the readiness check below could mistake whatever holds the
+    // port for our daemon
+    daemon::check_port_free(port)?;
 
     let log_path = vex_dir.join("daemon.log");
     let log_file = std::fs::OpenOptions::new()
@@ -275,6 +583,8 @@ fn daemon_start(vex_dir: &Path, port: u16) -> Result<()> {
         .arg(port.to_string())
         .arg("daemon")
         .arg("run")
+        // Root was already vetted by `daemon start`
+        .arg("--allow-root")
         .stdout(log_file)
         .stderr(log_err)
         .stdin(std::process::Stdio::null())
@@ -289,13 +599,21 @@ fn daemon_start(vex_dir: &Path, port: u16) -> Result<()> {
         });
     }
 
-    let child = cmd.spawn()?;
+    let mut child = cmd.spawn()?;
     let pid = child.id();
 
     std::fs::write(&pid_path, pid.to_string())?;
 
     // Wait for port to be ready
     for _ in 0..50 {
+        if let Some(status) = child.try_wait()? {
+            let _ = std::fs::remove_file(&pid_path);
+            bail!(
+                "daemon exited during startup ({}); check {}",
+                status,
+                log_path.display()
+            );
+        }
         if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
             eprintln!("daemon started on port {} (pid {})", port, pid);
             return Ok(());
@@ -338,34 +656,98 @@ fn daemon_stop(vex_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-fn daemon_status(vex_dir: &Path, port: u16) -> Result<()> {
+async fn daemon_status(vex_dir: &Path, port: u16, json: bool) -> Result<()> {
     let pid_path = vex_dir.join("daemon.pid");
-    if let Ok(pid_str) = std::fs::read_to_string(&pid_path)
-        && let Ok(pid) = pid_str.trim().parse::<i32>()
-        && kill(Pid::from_raw(pid), None).is_ok()
-    {
-        eprintln!("daemon running (pid {}, port {})", pid, port);
-    } else {
+    let running_pid = std::fs::read_to_string(&pid_path)
+        .ok()
+        .and_then(|pid_str| pid_str.trim().parse::<i32>().ok())
+        .filter(|&pid| kill(Pid::from_raw(pid), None).is_ok());
+    let Some(pid) = running_pid else {
+        if json {
+            return table::print_json(&serde_json::json!({ "running": false }));
+        }
         eprintln!("daemon not running");
+        return Ok(());
+    };
+
+    let status = daemon::status::read(vex_dir).filter(|s| s.pid as i32 == pid);
+    let rtt = client::ping(port).await;
+    let counts = client::request_all(
+        port,
+        vec![
+            ClientMessage::ListSessions,
+            ClientMessage::AgentList { full_prompt: false },
+        ],
+    )
+    .await
+    .ok()
+    .and_then(|replies| match replies.as_slice() {
+        [
+            ServerMessage::Sessions { sessions },
+            ServerMessage::AgentListResponse { agents, .. },
+        ] => Some((sessions.len(), agents.len())),
+        _ => None,
+    });
+
+    if json {
+        return table::print_json(&serde_json::json!({
+            "running": true,
+            "pid": pid,
+            "port": status.as_ref().map_or(port, |s| s.port),
+            "version": status.as_ref().map(|s| &s.version),
+            "started_at": status.as_ref().map(|s| s.started_at),
+            "rtt_ms": rtt.as_ref().ok().map(|rtt| rtt.as_secs_f64() * 1000.0),
+            "sessions": counts.map(|(sessions, _)| sessions),
+            "agents": counts.map(|(_, agents)| agents),
+        }));
+    }
+    match status {
+        Some(status) => {
+            let uptime = (chrono::Utc::now() - status.started_at)
+                .num_seconds()
+                .max(0);
+            eprintln!(
+                "daemon running (pid {}, port {}, version {}, up {}s)",
+                pid, status.port, status.version, uptime
+            );
+        }
+        None => eprintln!("daemon running (pid {}, port {})", pid, port),
+    }
+    match rtt {
+        Ok(rtt) => eprintln!("rtt: {}", client::format_rtt(rtt)),
+        Err(e) => eprintln!("not answering: {:#}", e),
+    }
+    if let Some((sessions, agents)) = counts {
+        eprintln!("sessions: {}, agents: {}", sessions, agents);
     }
     Ok(())
 }
 
-fn daemon_logs(vex_dir: &Path, follow: bool) -> Result<()> {
-    let log_path = vex_dir.join("daemon.log");
-    if !log_path.exists() {
-        bail!("no log file found (has the daemon been started?)");
-    }
-    if follow {
-        let status = std::process::Command::new("tail")
-            .arg("-f")
-            .arg(&log_path)
-            .status()?;
-        std::process::exit(status.code().unwrap_or(1));
-    }
-    let content = std::fs::read_to_string(&log_path)?;
-    print!("{content}");
-    Ok(())
+fn daemon_check(vex_dir: &Path) -> Result<()> {
+    let problems = daemon::check::check(vex_dir);
+    if problems.is_empty() {
+        println!("state in {} looks good", vex_dir.display());
+        return Ok(());
+    }
+    for p in &problems {
+        println!("problem: {}", p.message);
+        println!("    fix: {}", p.fix);
+    }
+    bail!(
+        "{} problem(s) found in {}",
+        problems.len(),
+        vex_dir.display()
+    );
+}
+
+fn daemon_logs(
+    vex_dir: &Path,
+    follow: bool,
+    grep: Option<String>,
+    level: Option<logs::Level>,
+) -> Result<()> {
+    let mut filter = logs::LogFilter::new(grep, level);
+    logs::show(&vex_dir.join("daemon.log"), follow, &mut filter)
 }
 
 // ── Connect / Disconnect (SSH tunnel) ────────────────────────────
@@ -375,36 +757,45 @@ fn find_free_port() -> Result<u16> {
     Ok(listener.local_addr()?.port())
 }
 
-fn connect_ssh(vex_dir: &Path, host: &str, remote_port: u16) -> Result<()> {
-    std::fs::create_dir_all(vex_dir)?;
-
-    // Disconnect existing tunnel if any
-    if load_saved_connection(vex_dir).is_some() {
-        let _ = disconnect_ssh(vex_dir);
+/// Build the SSH port-forward from `tunnel_port` to the remote daemon,
+/// managed through the control socket at `ssh_sock`. With `background` ssh
+/// forks itself away once the forward is up.
+fn tunnel_command(
+    ssh_sock: &Path,
+    host: &str,
+    tunnel_port: u16,
+    remote_port: u16,
+    background: bool,
+) -> std::process::Command {
+    let mut cmd = std::process::Command::new("ssh");
+    if background {
+        cmd.arg("-f");
     }
+    cmd.args([
+        "-N",
+        "-o",
+        "ExitOnForwardFailure=yes",
+        "-o",
+        "ServerAliveInterval=60",
+        "-o",
+        "ServerAliveCountMax=3",
+        "-o",
+        "ControlMaster=yes",
+        "-o",
+        &format!("ControlPath={}", ssh_sock.display()),
+        "-L",
+        &format!("{}:127.0.0.1:{}", tunnel_port, remote_port),
+        host,
+    ]);
+    cmd
+}
 
+/// Start a backgrounded SSH port-forward to the remote daemon that lives
+/// until closed through its control socket. Returns the local tunnel port.
+fn open_tunnel(ssh_sock: &Path, host: &str, remote_port: u16) -> Result<u16> {
     let tunnel_port = find_free_port()?;
-    let ssh_sock = vex_dir.join("ssh.sock");
 
-    // Start SSH tunnel with control socket for lifecycle management
-    let status = std::process::Command::new("ssh")
-        .args([
-            "-f",
-            "-N",
-            "-o",
-            "ExitOnForwardFailure=yes",
-            "-o",
-            "ServerAliveInterval=60",
-            "-o",
-            "ServerAliveCountMax=3",
-            "-o",
-            "ControlMaster=yes",
-            "-o",
-            &format!("ControlPath={}", ssh_sock.display()),
-            "-L",
-            &format!("{}:127.0.0.1:{}", tunnel_port, remote_port),
-            host,
-        ])
+    let status = tunnel_command(ssh_sock, host, tunnel_port, remote_port, true)
         .status()
         .map_err(|e| anyhow::anyhow!("failed to run ssh: {} (is OpenSSH installed?)", e))?;
 
@@ -414,6 +805,101 @@ fn connect_ssh(vex_dir: &Path, host: &str, remote_port: u16) -> Result<()> {
 
     // Brief wait for tunnel to be fully ready
     std::thread::sleep(Duration::from_millis(500));
+    Ok(tunnel_port)
+}
+
+/// Kill an SSH tunnel via its control socket.
+fn close_tunnel(ssh_sock: &Path, host: &str) {
+    let _ = std::process::Command::new("ssh")
+        .args([
+            "-O",
+            "exit",
+            "-o",
+            &format!("ControlPath={}", ssh_sock.display()),
+            host,
+        ])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+}
+
+/// A tunnel opened by `--host` for a single invocation; never saved to the
+/// connection config. The ssh process stays a child of vex and holds the
+/// forward open for as long as the command runs. It is closed on drop, and
+/// on Linux also gets SIGTERM when vex exits without dropping it (e.g.
+/// `session attach` calls `std::process::exit`).
+struct EphemeralTunnel {
+    conn: SavedConnection,
+    ssh_sock: PathBuf,
+    child: std::process::Child,
+}
+
+impl EphemeralTunnel {
+    fn open(vex_dir: &Path, host: &str, remote_port: u16) -> Result<Self> {
+        std::fs::create_dir_all(vex_dir)?;
+        let ssh_sock = vex_dir.join(format!("ssh-{}.sock", std::process::id()));
+        let tunnel_port = find_free_port()?;
+
+        let mut cmd = tunnel_command(&ssh_sock, host, tunnel_port, remote_port, false);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::process::CommandExt;
+            // SAFETY: prctl is async-signal-safe and touches no shared state.
+            unsafe {
+                cmd.pre_exec(|| {
+                    nix::sys::prctl::set_pdeathsig(nix::sys::signal::Signal::SIGTERM)
+                        .map_err(std::io::Error::from)
+                });
+            }
+        }
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("failed to run ssh: {} (is OpenSSH installed?)", e))?;
+
+        // ssh may still be prompting for a password, so wait on the forward
+        // itself rather than a fixed delay.
+        let addr = SocketAddr::from(([127, 0, 0, 1], tunnel_port));
+        loop {
+            if child.try_wait()?.is_some() {
+                let _ = std::fs::remove_file(&ssh_sock);
+                bail!("failed to establish SSH tunnel to {}", host);
+            }
+            if std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(Self {
+            conn: SavedConnection {
+                host: host.to_string(),
+                tunnel_port,
+            },
+            ssh_sock,
+            child,
+        })
+    }
+}
+
+impl Drop for EphemeralTunnel {
+    fn drop(&mut self) {
+        close_tunnel(&self.ssh_sock, &self.conn.host);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.ssh_sock);
+    }
+}
+
+fn connect_ssh(vex_dir: &Path, config_path: &Path, host: &str, remote_port: u16) -> Result<()> {
+    std::fs::create_dir_all(vex_dir)?;
+
+    // Disconnect existing tunnel if any
+    if load_saved_connection(config_path).is_some() {
+        let _ = disconnect_ssh(vex_dir, config_path);
+    }
+
+    let ssh_sock = control_socket_path(vex_dir, config_path);
+    let tunnel_port = open_tunnel(&ssh_sock, host, remote_port)?;
 
     // Verify remote daemon is reachable through tunnel
     let verified = std::net::TcpStream::connect_timeout(
@@ -427,8 +913,7 @@ fn connect_ssh(vex_dir: &Path, host: &str, remote_port: u16) -> Result<()> {
         host: host.to_string(),
         tunnel_port,
     };
-    let data = serde_json::to_string(&conn)?;
-    std::fs::write(vex_dir.join("connect.json"), &data)?;
+    save_connection(config_path, &conn)?;
 
     if verified {
         eprintln!("connected to {}", host);
@@ -440,28 +925,16 @@ fn connect_ssh(vex_dir: &Path, host: &str, remote_port: u16) -> Result<()> {
     Ok(())
 }
 
-fn disconnect_ssh(vex_dir: &Path) -> Result<()> {
-    let ssh_sock = vex_dir.join("ssh.sock");
+fn disconnect_ssh(vex_dir: &Path, config_path: &Path) -> Result<()> {
+    let ssh_sock = control_socket_path(vex_dir, config_path);
 
-    if let Some(saved) = load_saved_connection(vex_dir) {
-        // Kill SSH tunnel via control socket
-        let _ = std::process::Command::new("ssh")
-            .args([
-                "-O",
-                "exit",
-                "-o",
-                &format!("ControlPath={}", ssh_sock.display()),
-                &saved.host,
-            ])
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .status();
+    if let Some(saved) = load_saved_connection(config_path) {
+        close_tunnel(&ssh_sock, &saved.host);
     }
 
     let _ = std::fs::remove_file(&ssh_sock);
 
-    let path = vex_dir.join("connect.json");
-    match std::fs::remove_file(&path) {
+    match std::fs::remove_file(config_path) {
         Ok(()) => {}
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
         Err(e) => return Err(e.into()),
@@ -470,9 +943,16 @@ fn disconnect_ssh(vex_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-fn remote_list(vex_dir: &Path) -> Result<()> {
-    if let Some(conn) = load_saved_connection(vex_dir) {
-        println!("{} (tunnel port {})", conn.host, conn.tunnel_port);
+async fn remote_list(config_path: &Path, json: bool) -> Result<()> {
+    if json {
+        return table::print_json(&load_saved_connection(config_path));
+    }
+    if let Some(conn) = load_saved_connection(config_path) {
+        let rtt = match client::ping(conn.tunnel_port).await {
+            Ok(rtt) => format!("rtt {}", client::format_rtt(rtt)),
+            Err(_) => "unreachable".to_string(),
+        };
+        println!("{} (tunnel port {}, {})", conn.host, conn.tunnel_port, rtt);
     } else {
         println!("not connected to any remote");
     }
@@ -482,10 +962,42 @@ fn remote_list(vex_dir: &Path) -> Result<()> {
 // ── Main ─────────────────────────────────────────────────────────
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    if let Err(e) = run().await {
+        let message = format!("{:#}", e);
+        eprintln!("error: {}", message);
+        if let Some(hint) = client::hint_for(&message) {
+            eprintln!("hint: {}", hint);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Rust ignores SIGPIPE, so writing to a closed pipe (`vex ... | head`)
+/// panics in `println!`. Restore the default so we exit quietly like other
+/// CLI tools. The daemon keeps ignoring it: a client hanging up must surface
+/// as a write error on that connection, not kill the process.
+fn restore_default_sigpipe() {
+    use nix::sys::signal::{SigHandler, signal};
+    // SAFETY: installs the default disposition; no handler code runs.
+    unsafe {
+        let _ = signal(Signal::SIGPIPE, SigHandler::SigDfl);
+    }
+}
+
+async fn run() -> Result<()> {
     let cli = Cli::parse();
+    if !matches!(
+        cli.command,
+        Some(Command::Daemon {
+            command: DaemonCommand::Run { .. }
+        })
+    ) {
+        restore_default_sigpipe();
+    }
     let port = cli.port;
-    let vex_dir = vex_dir();
+    let vex_dir = vex_dir()?;
+    let config_path = connection_config_path(&vex_dir, cli.config);
 
     let command = match cli.command {
         Some(cmd) => cmd,
@@ -496,24 +1008,46 @@ async fn main() -> Result<()> {
     };
 
     // Phase 1: always-local commands
+    if cli.host.is_some() && !uses_host(&command) {
+        bail!(
+            "--host only applies to commands sent to a daemon; \
+             `vex daemon`, `vex remote` and `vex completions` run on this machine"
+        );
+    }
     match &command {
         Command::Daemon { command } => {
             return match command {
-                DaemonCommand::Start => daemon_start(&vex_dir, port),
+                DaemonCommand::Start { allow_root } => {
+                    check_root(nix::unistd::geteuid().as_raw(), *allow_root)?;
+                    daemon_start(&vex_dir, port)
+                }
                 DaemonCommand::Stop => daemon_stop(&vex_dir),
-                DaemonCommand::Status => daemon_status(&vex_dir, port),
-                DaemonCommand::Logs { follow } => daemon_logs(&vex_dir, *follow),
-                DaemonCommand::Run => {
-                    tracing_subscriber::fmt::init();
+                DaemonCommand::Status => daemon_status(&vex_dir, port, cli.json).await,
+                DaemonCommand::Check => daemon_check(&vex_dir),
+                DaemonCommand::ResetShells { repo } => {
+                    session::reset_shells(port, repo.clone()).await
+                }
+                DaemonCommand::Logs {
+                    follow,
+                    grep,
+                    level,
+                } => daemon_logs(&vex_dir, *follow, grep.clone(), *level),
+                DaemonCommand::Run { allow_root } => {
+                    check_root(nix::unistd::geteuid().as_raw(), *allow_root)?;
+                    ensure_writable_dir(&vex_dir)?;
+                    // RUST_LOG overrides the default level, e.g. RUST_LOG=debug
+                    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+                        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+                    tracing_subscriber::fmt().with_env_filter(filter).init();
                     daemon::run(port, &vex_dir).await
                 }
             };
         }
         Command::Remote { command } => {
             return match command {
-                RemoteCommand::Connect { host } => connect_ssh(&vex_dir, host, port),
-                RemoteCommand::Disconnect => disconnect_ssh(&vex_dir),
-                RemoteCommand::List => remote_list(&vex_dir),
+                RemoteCommand::Connect { host } => connect_ssh(&vex_dir, &config_path, host, port),
+                RemoteCommand::Disconnect => disconnect_ssh(&vex_dir, &config_path),
+                RemoteCommand::List => remote_list(&config_path, cli.json).await,
             };
         }
         Command::Completions { shell } => {
@@ -523,10 +1057,18 @@ async fn main() -> Result<()> {
         _ => {}
     }
 
-    // Phase 2: determine effective port (local daemon or SSH tunnel)
-    let effective_port = load_saved_connection(&vex_dir)
-        .map(|c| c.tunnel_port)
-        .unwrap_or(port);
+    // Phase 2: determine effective port (local daemon or SSH tunnel). An
+    // ad hoc --host takes precedence over the saved connection.
+    let saved = load_saved_connection(&config_path);
+    let ephemeral = match &cli.host {
+        Some(host) => Some(EphemeralTunnel::open(&vex_dir, host, port)?),
+        None => None,
+    };
+    let remote = match &ephemeral {
+        Some(tunnel) => Some(&tunnel.conn),
+        None => saved.as_ref(),
+    };
+    let effective_port = remote.map(|c| c.tunnel_port).unwrap_or(port);
 
     // Phase 3: commands routed through effective port
     match command {
@@ -537,31 +1079,51 @@ async fn main() -> Result<()> {
                 repo,
             } => {
                 let (target_port, resolved_repo) =
-                    resolve_repo_for_create(repo, effective_port, port, &vex_dir).await?;
+                    resolve_repo_for_create(repo, effective_port, port, remote).await?;
                 let id = session::session_create(target_port, shell, resolved_repo).await?;
                 if attach {
-                    session::session_attach(target_port, &id).await?;
+                    session::session_attach(target_port, &id, None).await?;
                 }
             }
             SessionCommand::List => {
-                session::session_list(effective_port).await?;
+                session::session_list(effective_port, cli.json).await?;
             }
             SessionCommand::Kill { id } => {
                 session::session_kill(effective_port, &id).await?;
             }
-            SessionCommand::Attach { id } => {
-                session::session_attach(effective_port, &id).await?;
+            SessionCommand::Attach { id, cols, rows } => {
+                let size = cols.zip(rows);
+                session::session_attach(effective_port, &id, size).await?;
             }
         },
         Command::Agent { command } => match command {
-            AgentCommand::List => {
-                agent::agent_list(effective_port).await?;
+            AgentCommand::List {
+                format,
+                grep,
+                full_prompt,
+            } => {
+                agent::agent_list(
+                    effective_port,
+                    format.as_deref(),
+                    grep.as_deref(),
+                    full_prompt,
+                    cli.json,
+                )
+                .await?;
             }
             AgentCommand::Notifications => {
-                agent::agent_notifications(effective_port).await?;
+                agent::agent_notifications(effective_port, cli.json).await?;
             }
-            AgentCommand::Watch { id, show_thinking } => {
-                agent::agent_watch(effective_port, &id, show_thinking).await?;
+            AgentCommand::Watch {
+                ids,
+                all,
+                show_thinking,
+            } => {
+                if let [id] = ids.as_slice() {
+                    agent::agent_watch(effective_port, id, show_thinking).await?;
+                } else {
+                    agent::agent_watch_many(effective_port, &ids, all, show_thinking).await?;
+                }
             }
             AgentCommand::Prompt {
                 id,
@@ -571,18 +1133,37 @@ async fn main() -> Result<()> {
             } => {
                 agent::agent_prompt(effective_port, &id, &text, watch, show_thinking).await?;
             }
+            AgentCommand::Logs { id, lines } => {
+                agent::agent_logs(effective_port, &id, lines).await?;
+            }
+            AgentCommand::Restart { id, attach } => {
+                let id = agent::agent_restart(effective_port, &id).await?;
+                if attach {
+                    session::session_attach(effective_port, &id, None).await?;
+                }
+            }
             AgentCommand::Spawn {
                 repo,
                 workstream,
+                env,
+                cwd,
+                profile,
                 attach,
             } => {
                 let (target_port, resolved_repo) =
-                    resolve_repo_for_create(Some(repo), effective_port, port, &vex_dir).await?;
+                    resolve_repo_for_create(Some(repo), effective_port, port, remote).await?;
                 let resolved_repo = resolved_repo.expect("repo was Some");
-                let id =
-                    agent::agent_spawn(target_port, &resolved_repo, workstream.as_deref()).await?;
+                let id = agent::agent_spawn(
+                    target_port,
+                    &resolved_repo,
+                    workstream.as_deref(),
+                    env,
+                    cwd,
+                    profile,
+                )
+                .await?;
                 if attach {
-                    session::session_attach(target_port, &id).await?;
+                    session::session_attach(target_port, &id, None).await?;
                 }
             }
         },
@@ -592,11 +1173,15 @@ async fn main() -> Result<()> {
                 RepoCommand::Add { name, path } => {
                     repo::repo_add(effective_port, &name, &path, is_local).await?;
                 }
-                RepoCommand::Remove { name } => {
-                    repo::repo_remove(effective_port, &name).await?;
+                RepoCommand::Remove {
+                    name,
+                    delete_workstreams,
+                } => {
+                    repo::repo_remove(effective_port, &name, delete_workstreams).await?;
                 }
-                RepoCommand::List => {
-                    repo::repo_list(effective_port).await?;
+                RepoCommand::List { fast } => {
+                    let cache_dir = fast.then_some(vex_dir.as_path());
+                    repo::repo_list(effective_port, cache_dir, cli.json).await?;
                 }
                 RepoCommand::IntrospectPath { path } => {
                     repo::repo_introspect_path(effective_port, &path, is_local).await?;
@@ -604,15 +1189,103 @@ async fn main() -> Result<()> {
             }
         }
         Command::Workstream { command } => match command {
-            WorkstreamCommand::Create { repo, name } => {
-                workstream::workstream_create(effective_port, &repo, &name).await?;
+            WorkstreamCommand::Create {
+                repo,
+                name,
+                branches,
+                fetch,
+                open_editor,
+            } => {
+                let repo = match repo {
+                    Some(repo) => repo,
+                    None if effective_port == port => {
+                        repo::detect_repo(effective_port, &std::env::current_dir()?).await?
+                    }
+                    None => bail!("--repo is required when talking to a remote daemon"),
+                };
+                let Some(name) = name else {
+                    return workstream::workstream_create_batch(
+                        effective_port,
+                        &repo,
+                        &branches,
+                        fetch,
+                    )
+                    .await;
+                };
+                let worktree_path =
+                    workstream::workstream_create(effective_port, &repo, &name).await?;
+                if open_editor {
+                    if effective_port != port {
+                        eprintln!(
+                            "warning: --open-editor ignored; the worktree is on the remote host"
+                        );
+                    } else {
+                        match daemon::config::VexConfig::load(&vex_dir).editor_command() {
+                            Some(editor) => workstream::open_editor(&editor, &worktree_path)?,
+                            None => bail!(
+                                "no editor configured; set editor_command in config.yml, $VISUAL or $EDITOR"
+                            ),
+                        }
+                    }
+                }
             }
-            WorkstreamCommand::List { repo } => {
-                workstream::workstream_list(effective_port, repo.as_deref()).await?;
+            WorkstreamCommand::List {
+                repo,
+                sort,
+                fast,
+                format,
+                status,
+            } => {
+                let cache_dir = fast.then_some(vex_dir.as_path());
+                workstream::workstream_list(
+                    effective_port,
+                    repo.as_deref(),
+                    sort,
+                    cache_dir,
+                    format.as_deref(),
+                    status,
+                    cli.json,
+                )
+                .await?;
             }
             WorkstreamCommand::Remove { repo, name } => {
                 workstream::workstream_remove(effective_port, &repo, &name).await?;
             }
+            WorkstreamCommand::Prune { repo, dry_run } => {
+                workstream::workstream_prune(effective_port, repo.as_deref(), dry_run).await?;
+            }
+            WorkstreamCommand::Note { repo, name, text } => {
+                workstream::workstream_set_notes(effective_port, &repo, &name, text).await?;
+            }
+            WorkstreamCommand::Rename {
+                repo,
+                name,
+                new_name,
+            } => {
+                workstream::workstream_rename(effective_port, &repo, &name, &new_name).await?;
+            }
+            WorkstreamCommand::Status { repo, name } => {
+                workstream::workstream_status(effective_port, &repo, &name, cli.json).await?;
+            }
+            WorkstreamCommand::Path { repo, name } => {
+                workstream::workstream_path(effective_port, &repo, &name).await?;
+            }
+            WorkstreamCommand::Exec {
+                repo,
+                name,
+                timeout,
+                command,
+            } => {
+                let code = workstream::workstream_exec(
+                    effective_port,
+                    &repo,
+                    &name,
+                    command.join(" "),
+                    timeout,
+                )
+                .await?;
+                std::process::exit(code);
+            }
         },
         _ => unreachable!(),
     }
@@ -627,7 +1300,7 @@ async fn resolve_repo_for_create(
     repo: Option<String>,
     effective_port: u16,
     local_port: u16,
-    vex_dir: &Path,
+    remote: Option<&SavedConnection>,
 ) -> Result<(u16, Option<String>)> {
     let Some(repo_name) = repo else {
         return Ok((effective_port, None));
@@ -639,7 +1312,7 @@ async fn resolve_repo_for_create(
             return Ok((local_port, Some(name.to_string())));
         }
         // Check if qualifier matches the remote host
-        if let Some(conn) = load_saved_connection(vex_dir)
+        if let Some(conn) = remote
             && conn.host == qualifier
         {
             return Ok((conn.tunnel_port, Some(name.to_string())));
@@ -651,13 +1324,10 @@ async fn resolve_repo_for_create(
     }
 
     // Unqualified name — check if remote is connected
-    let remote = load_saved_connection(vex_dir);
-    if remote.is_none() {
+    let Some(conn) = remote else {
         // No remote, just use effective port
         return Ok((effective_port, Some(repo_name)));
-    }
-
-    let conn = remote.unwrap();
+    };
 
     // Query both local and remote for this repo name
     let local_has = query_repo_exists(local_port, &repo_name)
@@ -683,10 +1353,164 @@ async fn resolve_repo_for_create(
 }
 
 async fn query_repo_exists(port: u16, name: &str) -> Result<bool> {
-    use vex_cli::proto::{ClientMessage, ServerMessage};
     let resp = client::request(port, &ClientMessage::RepoList).await?;
     match resp {
         ServerMessage::Repos { repos } => Ok(repos.iter().any(|r| r.name == name)),
         _ => Ok(false),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(s: &str) -> Option<PathBuf> {
+        Some(PathBuf::from(s))
+    }
+
+    #[test]
+    fn vex_dir_prefers_explicit_override() {
+        let dir = resolve_vex_dir(p("/custom"), p("/home/u"), p("/state"), None, |_| false);
+        assert_eq!(dir.unwrap(), PathBuf::from("/custom"));
+    }
+
+    #[test]
+    fn vex_dir_uses_home_when_writable() {
+        let dir = resolve_vex_dir(None, p("/home/u"), p("/state"), p("/data"), |_| true);
+        assert_eq!(dir.unwrap(), PathBuf::from("/home/u/.vex"));
+    }
+
+    #[test]
+    fn vex_dir_falls_back_to_xdg_state_then_data() {
+        let dir = resolve_vex_dir(None, p("/home/u"), p("/state"), p("/data"), |d| {
+            d.starts_with("/state")
+        });
+        assert_eq!(dir.unwrap(), PathBuf::from("/state/vex"));
+
+        let dir = resolve_vex_dir(None, p("/home/u"), p("/state"), p("/data"), |d| {
+            d.starts_with("/data")
+        });
+        assert_eq!(dir.unwrap(), PathBuf::from("/data/vex"));
+
+        let dir = resolve_vex_dir(None, None, None, p("/data"), |_| true);
+        assert_eq!(dir.unwrap(), PathBuf::from("/data/vex"));
+    }
+
+    #[test]
+    fn vex_dir_reports_first_candidate_when_nothing_writable() {
+        let dir = resolve_vex_dir(None, p("/home/u"), p("/state"), None, |_| false);
+        assert_eq!(dir.unwrap(), PathBuf::from("/home/u/.vex"));
+    }
+
+    #[test]
+    fn connection_config_override_is_used_for_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("vex-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let custom = dir.join("work.json");
+
+        let path = connection_config_path(&dir, Some(custom.clone()));
+        assert_eq!(path, custom);
+        save_connection(
+            &path,
+            &SavedConnection {
+                host: "work-box".into(),
+                tunnel_port: 4242,
+            },
+        )
+        .unwrap();
+
+        assert!(!dir.join("connect.json").exists());
+        let loaded = load_saved_connection(&custom).unwrap();
+        assert_eq!(loaded.host, "work-box");
+        assert_eq!(loaded.tunnel_port, 4242);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn host_is_only_used_by_daemon_routed_commands() {
+        let command = |args: &[&str]| Cli::try_parse_from(args).unwrap().command.unwrap();
+        assert!(!uses_host(&command(&["vex", "daemon", "status"])));
+        assert!(!uses_host(&command(&["vex", "remote", "list"])));
+        assert!(!uses_host(&command(&["vex", "completions", "bash"])));
+        assert!(uses_host(&command(&["vex", "session", "list"])));
+    }
+
+    #[test]
+    fn connection_config_defaults_to_vex_dir() {
+        assert_eq!(
+            connection_config_path(Path::new("/v"), None),
+            PathBuf::from("/v/connect.json")
+        );
+    }
+
+    #[test]
+    fn each_connection_config_gets_its_own_control_socket() {
+        let vex_dir = Path::new("/v");
+        let default = control_socket_path(vex_dir, &vex_dir.join("connect.json"));
+        assert_eq!(default, PathBuf::from("/v/ssh.sock"));
+        let work = control_socket_path(vex_dir, Path::new("/home/me/work.json"));
+        let home = control_socket_path(vex_dir, Path::new("/home/me/home.json"));
+        assert_ne!(work, home);
+        assert_ne!(work, default);
+        assert_eq!(
+            work,
+            control_socket_path(vex_dir, Path::new("/home/me/work.json"))
+        );
+        assert!(work.starts_with(vex_dir));
+    }
+
+    #[test]
+    fn save_connection_requires_existing_parent() {
+        let missing = std::env::temp_dir()
+            .join(format!("vex-missing-{}", uuid::Uuid::new_v4()))
+            .join("connect.json");
+        let err = save_connection(
+            &missing,
+            &SavedConnection {
+                host: "h".into(),
+                tunnel_port: 1,
+            },
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+    }
+
+    #[test]
+    fn vex_dir_errors_without_any_candidate() {
+        let err = resolve_vex_dir(None, None, None, None, |_| true).unwrap_err();
+        assert!(err.to_string().contains("VEX_DIR"));
+    }
+
+    #[tokio::test]
+    async fn qualified_repo_routes_to_the_given_remote() {
+        let remote = SavedConnection {
+            host: "box".into(),
+            tunnel_port: 4242,
+        };
+        let (port, name) =
+            resolve_repo_for_create(Some("box/api".into()), 4242, 6969, Some(&remote))
+                .await
+                .unwrap();
+        assert_eq!((port, name.as_deref()), (4242, Some("api")));
+
+        let (port, _) =
+            resolve_repo_for_create(Some("local/api".into()), 4242, 6969, Some(&remote))
+                .await
+                .unwrap();
+        assert_eq!(port, 6969);
+
+        assert!(
+            resolve_repo_for_create(Some("other/api".into()), 6969, 6969, None)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn root_is_refused_unless_allowed() {
+        assert!(check_root(0, false).is_err());
+        assert!(check_root(0, true).is_ok());
+        assert!(check_root(1000, false).is_ok());
+    }
+}
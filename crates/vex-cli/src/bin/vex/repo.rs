@@ -1,9 +1,11 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{Result, bail};
-use vex_cli::proto::{ClientMessage, ServerMessage};
+use vex_cli::proto::{ClientMessage, RepoEntry, ServerMessage};
 
+use super::cache::request_cached;
 use super::client::request;
+use super::table::{print_json, truncate};
 
 /// Make a relative path absolute using the client's cwd, but only when
 /// talking to the local daemon. For remote daemons, send the path as-is
@@ -38,11 +40,113 @@ pub async fn repo_add(port: u16, name: &str, path: &Path, is_local: bool) -> Res
     }
 }
 
-pub async fn repo_remove(port: u16, name: &str) -> Result<()> {
+/// Find the registered repo that `dir` is inside, for commands run without
+/// `--repo`. Works from a repo's own worktrees too. If the repo isn't
+/// registered, offers to register it when stdin is a terminal.
+pub async fn detect_repo(port: u16, dir: &Path) -> Result<String> {
+    let Some(toplevel) = git_toplevel(dir) else {
+        bail!("not inside a git repository; pass --repo");
+    };
+    let resp = request(port, &ClientMessage::RepoList).await?;
+    let repos = match resp {
+        ServerMessage::Repos { repos } => repos,
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    };
+    let candidates = [Some(toplevel.clone()), git_main_worktree(dir)];
+    if let Some(name) = candidates
+        .iter()
+        .flatten()
+        .find_map(|p| match_repo(&repos, p))
+    {
+        return Ok(name);
+    }
+
+    // A bare repo's directory is usually named `<name>.git`
+    let name = toplevel
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .map(|n| n.strip_suffix(".git").map(String::from).unwrap_or(n))
+        .unwrap_or_default();
+    if std::io::IsTerminal::is_terminal(&std::io::stdin()) && !name.is_empty() {
+        eprint!(
+            "{} is not a registered repo. Register it as '{}'? [y/N] ",
+            toplevel.display(),
+            name
+        );
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if matches!(answer.trim(), "y" | "Y" | "yes") {
+            repo_add(port, &name, &toplevel, true).await?;
+            return Ok(name);
+        }
+    }
+    bail!(
+        "{} is not a registered repo; register it with `vex repo add <name> {}` or pass --repo",
+        toplevel.display(),
+        toplevel.display()
+    );
+}
+
+/// The registered repo whose path is `toplevel`, comparing canonical paths
+/// so symlinks and trailing slashes don't matter.
+fn match_repo(repos: &[RepoEntry], toplevel: &Path) -> Option<String> {
+    let canonical = |p: &Path| std::fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    let toplevel = canonical(toplevel);
+    repos
+        .iter()
+        .find(|r| canonical(&r.path) == toplevel)
+        .map(|r| r.name.clone())
+}
+
+/// The top level of the worktree `dir` is in, or the repository itself
+/// when it is bare and has no worktree of its own.
+fn git_toplevel(dir: &Path) -> Option<PathBuf> {
+    git_path(dir, &["rev-parse", "--show-toplevel"]).or_else(|| {
+        is_bare(dir)
+            .then(|| git_path(dir, &["rev-parse", "--path-format=absolute", "--git-dir"]))
+            .flatten()
+    })
+}
+
+/// The main worktree of the repo `dir` belongs to: the parent of the shared
+/// `.git` directory, or that directory itself for a bare repo.
+fn git_main_worktree(dir: &Path) -> Option<PathBuf> {
+    let common = git_path(
+        dir,
+        &["rev-parse", "--path-format=absolute", "--git-common-dir"],
+    )?;
+    if is_bare(&common) {
+        return Some(common);
+    }
+    common.parent().map(Path::to_path_buf)
+}
+
+fn is_bare(dir: &Path) -> bool {
+    git_path(dir, &["rev-parse", "--is-bare-repository"])
+        .is_some_and(|out| out == Path::new("true"))
+}
+
+fn git_path(dir: &Path, args: &[&str]) -> Option<PathBuf> {
+    let output = std::process::Command::new("git")
+        .args(["-C", &dir.to_string_lossy()])
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+pub async fn repo_remove(port: u16, name: &str, delete_workstreams: bool) -> Result<()> {
     let resp = request(
         port,
         &ClientMessage::RepoRemove {
             name: name.to_string(),
+            delete_workstreams,
         },
     )
     .await?;
@@ -56,16 +160,23 @@ pub async fn repo_remove(port: u16, name: &str) -> Result<()> {
     }
 }
 
-pub async fn repo_list(port: u16) -> Result<()> {
-    let resp = request(port, &ClientMessage::RepoList).await?;
+pub async fn repo_list(port: u16, cache_dir: Option<&Path>, json: bool) -> Result<()> {
+    let resp = request_cached(port, &ClientMessage::RepoList, cache_dir).await?;
     match resp {
         ServerMessage::Repos { repos } => {
-            if repos.is_empty() {
+            if json {
+                print_json(&repos)?;
+            } else if repos.is_empty() {
                 println!("no repos registered");
             } else {
-                println!("{:<20}  PATH", "NAME");
+                println!("{:<20}  {:<40}  PATH", "NAME", "REMOTE");
                 for r in repos {
-                    println!("{:<20}  {}", r.name, r.path.display());
+                    println!(
+                        "{:<20}  {:<40}  {}",
+                        truncate(&r.name, 20),
+                        truncate(r.remote_url.as_deref().unwrap_or("-"), 40),
+                        r.path.display()
+                    );
                 }
             }
             Ok(())
@@ -99,3 +210,86 @@ pub async fn repo_introspect_path(port: u16, path: &Path, is_local: bool) -> Res
         other => bail!("unexpected response: {:?}", other),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-C", &dir.to_string_lossy()])
+            .args(["-c", "user.name=vex", "-c", "user.email=vex@test"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn detects_the_repo_from_subdirs_and_worktrees() {
+        let root = std::env::temp_dir().join(format!("vex-detect-{}", uuid::Uuid::new_v4()));
+        let repo = root.join("myrepo");
+        std::fs::create_dir_all(repo.join("src/deep")).unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        git(&repo, &["worktree", "add", "-q", "-b", "feat", "../wt"]);
+
+        let repos = vec![
+            RepoEntry {
+                name: "other".into(),
+                path: root.join("other"),
+                remote_url: None,
+            },
+            RepoEntry {
+                name: "mine".into(),
+                // Trailing components that canonicalize away still match
+                path: repo.join("src/.."),
+                remote_url: None,
+            },
+        ];
+        let top = git_toplevel(&repo.join("src/deep")).unwrap();
+        assert_eq!(match_repo(&repos, &top).as_deref(), Some("mine"));
+
+        // From a worktree the toplevel is the worktree; the main one matches
+        let wt = root.join("wt");
+        assert_eq!(match_repo(&repos, &git_toplevel(&wt).unwrap()), None);
+        let main = git_main_worktree(&wt).unwrap();
+        assert_eq!(match_repo(&repos, &main).as_deref(), Some("mine"));
+
+        // Not registered, and not a repo at all
+        assert_eq!(match_repo(&repos[..1], &top), None);
+        assert_eq!(git_toplevel(&std::path::PathBuf::from("/")), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn detects_bare_repos_and_their_worktrees() {
+        let root = std::env::temp_dir().join(format!("vex-detect-{}", uuid::Uuid::new_v4()));
+        let src = root.join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        git(&src, &["init", "-q"]);
+        git(&src, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        git(&root, &["clone", "-q", "--bare", "src", "myrepo.git"]);
+        let bare = root.join("myrepo.git");
+        git(&bare, &["worktree", "add", "-q", "-b", "feat", "../wt"]);
+
+        let repos = vec![RepoEntry {
+            name: "mine".into(),
+            path: bare.clone(),
+            remote_url: None,
+        }];
+        let top = git_toplevel(&bare).unwrap();
+        assert_eq!(match_repo(&repos, &top).as_deref(), Some("mine"));
+        let main = git_main_worktree(&root.join("wt")).unwrap();
+        assert_eq!(match_repo(&repos, &main).as_deref(), Some("mine"));
+        // A normal checkout's main worktree is still its parent directory
+        assert_eq!(
+            std::fs::canonicalize(git_main_worktree(&src).unwrap()).unwrap(),
+            std::fs::canonicalize(&src).unwrap()
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
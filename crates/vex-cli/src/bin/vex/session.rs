@@ -1,4 +1,4 @@
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 
 use anyhow::{Result, bail};
 use tokio::io::{self, AsyncReadExt};
@@ -8,6 +8,7 @@ use vex_cli::proto::{
 };
 
 use super::client::{connect, request};
+use super::table::print_json;
 
 pub async fn session_create(
     port: u16,
@@ -26,11 +27,13 @@ pub async fn session_create(
     }
 }
 
-pub async fn session_list(port: u16) -> Result<()> {
+pub async fn session_list(port: u16, json: bool) -> Result<()> {
     let resp = request(port, &ClientMessage::ListSessions).await?;
     match resp {
         ServerMessage::Sessions { sessions } => {
-            if sessions.is_empty() {
+            if json {
+                print_json(&sessions)?;
+            } else if sessions.is_empty() {
                 println!("no active sessions");
             } else {
                 println!(
@@ -67,41 +70,156 @@ pub async fn session_kill(port: u16, id_prefix: &str) -> Result<()> {
     }
 }
 
-pub async fn session_attach(port: u16, id_prefix: &str) -> Result<()> {
-    let id = resolve_session_id(port, id_prefix).await?;
+pub async fn reset_shells(port: u16, repo: Option<String>) -> Result<()> {
+    let resp = request(port, &ClientMessage::ResetShells { repo }).await?;
+    match resp {
+        ServerMessage::ShellsReset { ids } => {
+            for id in &ids {
+                println!("ended session {}", id);
+            }
+            println!("reset {} shell session(s)", ids.len());
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
 
+/// How many times to try reattaching after the connection drops.
+const REATTACH_ATTEMPTS: u32 = 5;
+
+/// One connection's worth of an attachment.
+struct Attachment {
+    writer: io::WriteHalf<tokio::net::TcpStream>,
+    frames: tokio::sync::mpsc::Receiver<Result<Frame>>,
+    reader: tokio::task::JoinHandle<()>,
+}
+
+/// Connect and attach to `id`, replaying only what follows `since` when
+/// given. Returns the attachment, the output offset of the first data frame
+/// (replay included), and how many bytes after `since` the daemon no longer
+/// had.
+async fn attach(
+    port: u16,
+    id: Uuid,
+    (cols, rows): (u16, u16),
+    since: Option<u64>,
+) -> Result<(Attachment, u64, u64)> {
     let stream = connect(port).await?;
     let (mut reader, mut writer) = io::split(stream);
 
-    // Detect terminal size for the attach request
-    let (cols, rows) = terminal_size::terminal_size()
-        .map(|(w, h)| (w.0, h.0))
-        .unwrap_or((80, 24));
-
-    // Send attach request with terminal dimensions
     send_client_message(
         &mut writer,
-        &ClientMessage::AttachSession { id, cols, rows },
+        &ClientMessage::AttachSession {
+            id,
+            cols,
+            rows,
+            since,
+        },
     )
     .await?;
 
     // Wait for Attached confirmation
-    match read_frame(&mut reader).await? {
+    let (offset, skipped) = match read_frame(&mut reader).await? {
         Some(Frame::Control(data)) => {
             let resp: ServerMessage = serde_json::from_slice(&data)?;
             match resp {
-                ServerMessage::Attached { id: _ } => {}
+                ServerMessage::Attached {
+                    offset, skipped, ..
+                } => (offset, skipped),
                 ServerMessage::Error { message } => bail!("{}", message),
                 other => bail!("unexpected response: {:?}", other),
             }
         }
         _ => bail!("unexpected response from server"),
+    };
+
+    // Frame reader task (read_frame is not cancel-safe in tokio::select!)
+    let (frame_tx, frames) = tokio::sync::mpsc::channel::<Result<Frame>>(64);
+    let reader = tokio::spawn(async move {
+        loop {
+            match read_frame(&mut reader).await {
+                Ok(Some(frame)) => {
+                    if frame_tx.send(Ok(frame)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = frame_tx.send(Err(e)).await;
+                    break;
+                }
+            }
+        }
+    });
+    Ok((
+        Attachment {
+            writer,
+            frames,
+            reader,
+        },
+        offset,
+        skipped,
+    ))
+}
+
+/// After the connection drops, attach again from `offset` so only output
+/// that was missed is replayed. `None` once the session is gone or the
+/// daemon stays unreachable.
+async fn reattach(port: u16, id: Uuid, size: (u16, u16), offset: u64) -> Option<(Attachment, u64)> {
+    for attempt in 1..=REATTACH_ATTEMPTS {
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        eprintln!(
+            "\r\n[connection lost; reattaching ({}/{})]\r",
+            attempt, REATTACH_ATTEMPTS
+        );
+        match attach(port, id, size, Some(offset)).await {
+            Ok((attachment, offset, skipped)) => {
+                if skipped > 0 {
+                    eprintln!("\r\n[reattached; {} bytes of output were lost]\r", skipped);
+                } else {
+                    eprintln!("\r\n[reattached]\r");
+                }
+                return Some((attachment, offset));
+            }
+            Err(e) if e.to_string().contains("session not found") => {
+                eprintln!("\r\n[session {} has ended]\r", id);
+                return None;
+            }
+            Err(_) => {}
+        }
     }
+    None
+}
 
-    // Enter raw mode
-    let _raw_guard = RawModeGuard::enter()?;
+/// Attach to a session. With `size` the session is given that fixed
+/// `(cols, rows)` instead of following the terminal's size. If the
+/// connection drops, the session is reattached from the last output offset
+/// received, so nothing is replayed twice.
+pub async fn session_attach(port: u16, id_prefix: &str, size: Option<(u16, u16)>) -> Result<()> {
+    let id = resolve_session_id(port, id_prefix).await?;
 
-    eprintln!("\r\n[attached to session {}; press Ctrl+] to detach]\r", id);
+    // Detect terminal size for the attach request
+    let current_size = move || {
+        size.unwrap_or_else(|| {
+            terminal_size::terminal_size()
+                .map(|(w, h)| (w.0, h.0))
+                .unwrap_or((80, 24))
+        })
+    };
+    let (mut conn, mut offset, _) = attach(port, id, current_size(), None).await?;
+
+    // Without a terminal (piped input, CI) there is nothing to put in raw
+    // mode or resize: stdin is passed through verbatim until EOF, and output
+    // streams until the session ends.
+    let interactive = std::io::stdin().is_terminal();
+    let _raw_guard = if interactive {
+        let guard = RawModeGuard::enter()?;
+        eprintln!("\r\n[attached to session {}; press Ctrl+] to detach]\r", id);
+        Some(guard)
+    } else {
+        None
+    };
 
     // Spawn stdin reader task
     let (stdin_tx, mut stdin_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(64);
@@ -124,6 +242,9 @@ pub async fn session_attach(port: u16, id_prefix: &str) -> Result<()> {
     // Spawn SIGWINCH handler
     let (resize_tx, mut resize_rx) = tokio::sync::mpsc::channel::<(u16, u16)>(4);
     let sigwinch_handle = tokio::spawn(async move {
+        if !interactive || size.is_some() {
+            return;
+        }
         let mut sig =
             tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change()).unwrap();
         loop {
@@ -137,31 +258,13 @@ pub async fn session_attach(port: u16, id_prefix: &str) -> Result<()> {
         }
     });
 
-    // Spawn frame reader task (read_frame is not cancel-safe in tokio::select!)
-    let (frame_tx, mut frame_rx) = tokio::sync::mpsc::channel::<Result<Frame>>(64);
-    let frame_handle = tokio::spawn(async move {
-        loop {
-            match read_frame(&mut reader).await {
-                Ok(Some(frame)) => {
-                    if frame_tx.send(Ok(frame)).await.is_err() {
-                        break;
-                    }
-                }
-                Ok(None) => break,
-                Err(e) => {
-                    let _ = frame_tx.send(Err(e)).await;
-                    break;
-                }
-            }
-        }
-    });
-
     // Main loop: multiplex stdin, resize signals, and server frames
     let result: Result<()> = loop {
         tokio::select! {
-            result = frame_rx.recv() => {
+            result = conn.frames.recv() => {
                 match result {
                     Some(Ok(Frame::Data(data))) => {
+                        offset += data.len() as u64;
                         let mut stdout = std::io::stdout().lock();
                         let _ = stdout.write_all(&data);
                         let _ = stdout.flush();
@@ -184,36 +287,44 @@ pub async fn session_attach(port: u16, id_prefix: &str) -> Result<()> {
                             _ => {}
                         }
                     }
-                    Some(Err(e)) => {
-                        break Err(e);
-                    }
-                    None => {
-                        eprintln!("\r\n[server disconnected]\r");
-                        break Ok(());
+                    Some(Err(_)) | None => {
+                        conn.reader.abort();
+                        match reattach(port, id, current_size(), offset).await {
+                            Some((next, next_offset)) => {
+                                conn = next;
+                                offset = next_offset;
+                            }
+                            None => {
+                                eprintln!("\r\n[server disconnected]\r");
+                                break Ok(());
+                            }
+                        }
                     }
                 }
             }
+            // Write errors mean the connection dropped, which the frame
+            // reader also sees and reattaches from
             Some(data) = stdin_rx.recv() => {
                 // Check for Ctrl+] (0x1D)
-                if data.contains(&0x1D) {
-                    send_client_message(&mut writer, &ClientMessage::DetachSession).await?;
+                if interactive && data.contains(&0x1D) {
+                    let _ = send_client_message(&mut conn.writer, &ClientMessage::DetachSession).await;
                     // Don't break yet — wait for the Detached response
                 } else {
-                    write_data(&mut writer, &data).await?;
+                    let _ = write_data(&mut conn.writer, &data).await;
                 }
             }
             Some((cols, rows)) = resize_rx.recv() => {
-                send_client_message(
-                    &mut writer,
+                let _ = send_client_message(
+                    &mut conn.writer,
                     &ClientMessage::ResizeSession { id, cols, rows },
-                ).await?;
+                ).await;
             }
         }
     };
 
     stdin_handle.abort();
     sigwinch_handle.abort();
-    frame_handle.abort();
+    conn.reader.abort();
 
     // Restore terminal before exiting
     drop(_raw_guard);
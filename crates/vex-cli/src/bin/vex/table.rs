@@ -0,0 +1,186 @@
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+/// Cut `s` to at most `width` characters, marking the cut with an ellipsis.
+/// Counts chars rather than bytes so multibyte names are never split mid-char.
+pub fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut out: String = s.chars().take(width - 1).collect();
+    out.push('…');
+    out
+}
+
+/// Print `value` as pretty JSON for `--json`.
+pub fn print_json(value: &impl Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// `--json` and `--format` both replace the table, so only one can apply.
+pub fn check_output_flags(json: bool, format: Option<&str>) -> Result<()> {
+    if json && format.is_some() {
+        bail!("--json and --format cannot be used together");
+    }
+    Ok(())
+}
+
+/// A `--format` template: literal text with `{field}` placeholders, e.g.
+/// `{repo}\t{name}`. `\t` and `\n` are expanded since shells pass them
+/// through literally; `{{` and `}}` are literal braces.
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+impl Template {
+    /// Parse `template`, rejecting placeholders not in `fields`.
+    pub fn parse(template: &str, fields: &[&str]) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => bail!("unclosed '{{' in format template"),
+                        }
+                    }
+                    if !fields.contains(&field.as_str()) {
+                        bail!(
+                            "unknown field '{{{}}}' in format template; available: {}",
+                            field,
+                            fields.join(", ")
+                        );
+                    }
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    parts.push(Part::Field(field));
+                }
+                '}' => bail!("unmatched '}}' in format template (use '}}}}' for a literal brace)"),
+                '\\' if chars.peek() == Some(&'t') => {
+                    chars.next();
+                    literal.push('\t');
+                }
+                '\\' if chars.peek() == Some(&'n') => {
+                    chars.next();
+                    literal.push('\n');
+                }
+                c => literal.push(c),
+            }
+        }
+        parts.push(Part::Literal(literal));
+        Ok(Self { parts })
+    }
+
+    /// Whether any placeholder is one of `fields`.
+    pub fn uses_any(&self, fields: &[&str]) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(name) if fields.contains(&name.as_str())))
+    }
+
+    /// Render one row, looking each placeholder up with `value`.
+    pub fn render(&self, value: impl Fn(&str) -> String) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(text) => text.clone(),
+                Part::Field(name) => value(name),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[&str] = &["repo", "name", "branch"];
+
+    fn row(field: &str) -> String {
+        match field {
+            "repo" => "vex".into(),
+            "name" => "feat-1".into(),
+            "branch" => "feat-1".into(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn templates_render_fields_and_escapes() {
+        let t = Template::parse("{repo}\\t{name}", FIELDS).unwrap();
+        assert_eq!(t.render(row), "vex\tfeat-1");
+        let t = Template::parse("{{{repo}}}/{branch}\\n", FIELDS).unwrap();
+        assert_eq!(t.render(row), "{vex}/feat-1\n");
+        let t = Template::parse("plain", FIELDS).unwrap();
+        assert_eq!(t.render(row), "plain");
+    }
+
+    #[test]
+    fn uses_any_only_looks_at_placeholders() {
+        let t = Template::parse("repo: {name}", FIELDS).unwrap();
+        assert!(t.uses_any(&["name", "branch"]));
+        assert!(!t.uses_any(&["repo"]));
+    }
+
+    #[test]
+    fn unknown_fields_list_the_valid_ones() {
+        let err = Template::parse("{repo} {status}", FIELDS)
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            err,
+            "unknown field '{status}' in format template; available: repo, name, branch"
+        );
+        assert!(Template::parse("{repo", FIELDS).is_err());
+        assert!(Template::parse("repo}", FIELDS).is_err());
+    }
+
+    #[test]
+    fn short_strings_are_unchanged() {
+        assert_eq!(truncate("vex", 20), "vex");
+        assert_eq!(truncate("exactly-ten", 11), "exactly-ten");
+        assert_eq!(truncate("", 5), "");
+    }
+
+    #[test]
+    fn long_strings_get_an_ellipsis_within_width() {
+        let out = truncate("a-very-long-workstream-name", 10);
+        assert_eq!(out, "a-very-lo…");
+        assert_eq!(out.chars().count(), 10);
+    }
+
+    #[test]
+    fn multibyte_names_are_cut_on_char_boundaries() {
+        assert_eq!(truncate("日本語のワークストリーム", 5), "日本語の…");
+        assert_eq!(truncate("émoji-🚀-branch", 8), "émoji-🚀…");
+        assert_eq!(truncate("ünïcödé", 7), "ünïcödé");
+    }
+
+    #[test]
+    fn degenerate_widths() {
+        assert_eq!(truncate("abc", 1), "…");
+        assert_eq!(truncate("abc", 0), "");
+    }
+}
@@ -1,9 +1,70 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::{Result, bail};
-use vex_cli::proto::{ClientMessage, ServerMessage};
+use vex_cli::proto::{ClientMessage, GitState, ServerMessage, WorkstreamInfo, WorkstreamSort};
 
+use super::cache::request_cached;
 use super::client::request;
+use super::daemon::config::Editor;
+use super::table::{Template, check_output_flags, print_json, truncate};
+
+/// Placeholders for `vex workstream list --format`.
+const FORMAT_FIELDS: &[&str] = &[
+    "repo", "name", "branch", "path", "created", "notes", "dirty", "ahead", "behind", "missing",
+];
+/// Fields that need the daemon to read each worktree's git status.
+const GIT_FIELDS: &[&str] = &["dirty", "ahead", "behind"];
+
+fn format_field(ws: &WorkstreamInfo, field: &str) -> String {
+    match field {
+        "repo" => ws.repo.clone(),
+        "name" => ws.name.clone(),
+        "branch" => ws.branch.clone(),
+        "path" => ws.worktree_path.display().to_string(),
+        "created" => ws.created_at.to_rfc3339(),
+        "notes" => ws.notes.clone().unwrap_or_default().replace('\n', " "),
+        "dirty" => git_field(ws, |g| Some(g.dirty_files)),
+        "ahead" => git_field(ws, |g| g.ahead),
+        "behind" => git_field(ws, |g| g.behind),
+        "missing" => ws.missing.to_string(),
+        _ => unreachable!("checked by Template::parse"),
+    }
+}
+
+/// Empty when the status is unknown.
+fn git_field(ws: &WorkstreamInfo, value: impl Fn(&GitState) -> Option<u32>) -> String {
+    ws.git
+        .as_ref()
+        .and_then(value)
+        .map(|n| n.to_string())
+        .unwrap_or_default()
+}
+
+/// Short git state for the list's GIT column, e.g. "2 changed +1/-0".
+fn git_summary(git: Option<&GitState>) -> String {
+    let Some(git) = git else {
+        return "?".to_string();
+    };
+    let mut summary = match git.dirty_files {
+        0 => "clean".to_string(),
+        n => format!("{} changed", n),
+    };
+    if let (Some(ahead), Some(behind)) = (git.ahead, git.behind) {
+        summary.push_str(&format!(" +{}/-{}", ahead, behind));
+    }
+    summary
+}
 
-pub async fn workstream_create(port: u16, repo: &str, name: &str) -> Result<()> {
+/// The PATH column, flagging worktrees that were deleted outside vex.
+fn path_column(ws: &WorkstreamInfo) -> String {
+    if ws.missing {
+        format!("{} (missing)", ws.worktree_path.display())
+    } else {
+        ws.worktree_path.display().to_string()
+    }
+}
+
+pub async fn workstream_create(port: u16, repo: &str, name: &str) -> Result<PathBuf> {
     let resp = request(
         port,
         &ClientMessage::WorkstreamCreate {
@@ -24,36 +85,355 @@ pub async fn workstream_create(port: u16, repo: &str, name: &str) -> Result<()>
                 repo,
                 worktree_path.display()
             );
-            Ok(())
+            Ok(worktree_path)
         }
         ServerMessage::Error { message } => bail!("{}", message),
         other => bail!("unexpected response: {:?}", other),
     }
 }
 
-pub async fn workstream_list(port: u16, repo: Option<&str>) -> Result<()> {
+/// Create one workstream per existing branch. Failures are reported per
+/// branch without undoing the ones that worked. With `fetch_latest` the
+/// daemon fetches each branch from origin before checking it out.
+pub async fn workstream_create_batch(
+    port: u16,
+    repo: &str,
+    branches: &[String],
+    fetch_latest: bool,
+) -> Result<()> {
     let resp = request(
+        port,
+        &ClientMessage::WorkstreamCreateBatch {
+            repo: repo.to_string(),
+            branches: branches.to_vec(),
+            fetch_latest,
+        },
+    )
+    .await?;
+    match resp {
+        ServerMessage::WorkstreamsCreated { created, failed } => {
+            for ws in &created {
+                println!(
+                    "created workstream '{}' for repo '{}' at {}",
+                    ws.name,
+                    ws.repo,
+                    ws.worktree_path.display()
+                );
+            }
+            for failure in &failed {
+                eprintln!("failed '{}': {}", failure.branch, failure.message);
+            }
+            if !failed.is_empty() {
+                bail!("{} of {} branches failed", failed.len(), branches.len());
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+/// Open `worktree_path` in `editor`: a GUI editor is detached so it outlives
+/// this command, a terminal editor runs here until it exits.
+pub fn open_editor(editor: &Editor, worktree_path: &Path) -> Result<()> {
+    let Some((program, args)) = editor.command.split_first() else {
+        bail!("editor command is empty");
+    };
+    let mut cmd = std::process::Command::new(program);
+    cmd.args(args).arg(worktree_path).current_dir(worktree_path);
+    if !editor.detach {
+        // A terminal editor takes over this terminal until it exits
+        let status = cmd
+            .status()
+            .map_err(|e| anyhow::anyhow!("failed to launch editor '{}': {}", program, e))?;
+        if !status.success() {
+            bail!("editor '{}' exited with {}", program, status);
+        }
+        return Ok(());
+    }
+    cmd.stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+
+    unsafe {
+        use std::os::unix::process::CommandExt;
+        cmd.pre_exec(|| {
+            nix::unistd::setsid().map_err(std::io::Error::other)?;
+            Ok(())
+        });
+    }
+
+    cmd.spawn()
+        .map_err(|e| anyhow::anyhow!("failed to launch editor '{}': {}", program, e))?;
+    println!("opened {} in {}", program, worktree_path.display());
+    Ok(())
+}
+
+pub async fn workstream_list(
+    port: u16,
+    repo: Option<&str>,
+    sort: Option<WorkstreamSort>,
+    cache_dir: Option<&Path>,
+    format: Option<&str>,
+    git_status: bool,
+    json: bool,
+) -> Result<()> {
+    check_output_flags(json, format)?;
+    // Reject a bad template before talking to the daemon
+    let template = format
+        .map(|f| Template::parse(f, FORMAT_FIELDS))
+        .transpose()?;
+    let git_status = git_status || template.as_ref().is_some_and(|t| t.uses_any(GIT_FIELDS));
+    let resp = request_cached(
         port,
         &ClientMessage::WorkstreamList {
             repo: repo.map(String::from),
+            sort,
+            git_status,
         },
+        cache_dir,
     )
     .await?;
     match resp {
         ServerMessage::Workstreams { workstreams } => {
-            if workstreams.is_empty() {
+            if json {
+                print_json(&workstreams)?;
+            } else if let Some(template) = template {
+                for ws in &workstreams {
+                    println!("{}", template.render(|f| format_field(ws, f)));
+                }
+            } else if workstreams.is_empty() {
                 println!("no workstreams");
+            } else if git_status {
+                println!(
+                    "{:<15}  {:<20}  {:<20}  {:<30}  PATH",
+                    "REPO", "WORKSTREAM", "GIT", "NOTES"
+                );
+                for ws in &workstreams {
+                    println!(
+                        "{:<15}  {:<20}  {:<20}  {:<30}  {}",
+                        truncate(&ws.repo, 15),
+                        truncate(&ws.name, 20),
+                        git_summary(ws.git.as_ref()),
+                        truncate(&ws.notes.clone().unwrap_or_default().replace('\n', " "), 30),
+                        path_column(ws)
+                    );
+                }
             } else {
-                println!("{:<15}  {:<20}  PATH", "REPO", "WORKSTREAM");
-                for ws in workstreams {
+                println!(
+                    "{:<15}  {:<20}  {:<30}  PATH",
+                    "REPO", "WORKSTREAM", "NOTES"
+                );
+                for ws in &workstreams {
                     println!(
-                        "{:<15}  {:<20}  {}",
-                        ws.repo,
-                        ws.name,
-                        ws.worktree_path.display()
+                        "{:<15}  {:<20}  {:<30}  {}",
+                        truncate(&ws.repo, 15),
+                        truncate(&ws.name, 20),
+                        truncate(&ws.notes.clone().unwrap_or_default().replace('\n', " "), 30),
+                        path_column(ws)
                     );
                 }
             }
+            let missing = workstreams.iter().filter(|ws| ws.missing).count();
+            if missing > 0 && !json && format.is_none() {
+                eprintln!(
+                    "{} workstream(s) have a missing worktree; clean up with `vex workstream remove`",
+                    missing
+                );
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+pub async fn workstream_rename(port: u16, repo: &str, name: &str, new_name: &str) -> Result<()> {
+    let resp = request(
+        port,
+        &ClientMessage::WorkstreamRename {
+            repo: repo.to_string(),
+            name: name.to_string(),
+            new_name: new_name.to_string(),
+        },
+    )
+    .await?;
+    match resp {
+        ServerMessage::WorkstreamRenamed { workstream } => {
+            println!(
+                "renamed workstream '{}' to '{}' in repo '{}' (branch '{}' unchanged)",
+                name, workstream.name, workstream.repo, workstream.branch
+            );
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+pub async fn workstream_status(port: u16, repo: &str, name: &str, json: bool) -> Result<()> {
+    let resp = request(
+        port,
+        &ClientMessage::WorkstreamStatus {
+            repo: repo.to_string(),
+            name: name.to_string(),
+        },
+    )
+    .await?;
+    match resp {
+        ServerMessage::WorkstreamGitStatus { status, .. } => {
+            if json {
+                return print_json(&status);
+            }
+            println!(
+                "branch:   {}",
+                status.branch.as_deref().unwrap_or("(detached HEAD)")
+            );
+            println!("changes:  {} file(s)", status.dirty_files);
+            match (status.ahead, status.behind) {
+                (Some(ahead), Some(behind)) => {
+                    println!("upstream: {} ahead, {} behind", ahead, behind)
+                }
+                _ => println!("upstream: none"),
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+/// Print only the worktree path, for `cd "$(vex workstream path ...)"`.
+pub async fn workstream_path(port: u16, repo: &str, name: &str) -> Result<()> {
+    let resp = request(
+        port,
+        &ClientMessage::WorkstreamPath {
+            repo: repo.to_string(),
+            name: name.to_string(),
+        },
+    )
+    .await?;
+    match resp {
+        ServerMessage::WorkstreamPath { path } => {
+            println!("{}", path.display());
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+/// Run a command in the workstream's worktree and relay its output. Returns
+/// the command's exit code, or 1 if it was killed by a signal.
+pub async fn workstream_exec(
+    port: u16,
+    repo: &str,
+    name: &str,
+    command: String,
+    timeout_secs: Option<u64>,
+) -> Result<i32> {
+    use std::io::Write;
+
+    let resp = request(
+        port,
+        &ClientMessage::WorkstreamExec {
+            repo: repo.to_string(),
+            name: name.to_string(),
+            command,
+            timeout_secs,
+        },
+    )
+    .await?;
+    match resp {
+        ServerMessage::ExecResult {
+            stdout,
+            stderr,
+            code,
+            truncated,
+        } => {
+            std::io::stdout().write_all(stdout.as_bytes())?;
+            std::io::stderr().write_all(stderr.as_bytes())?;
+            if truncated {
+                eprintln!("warning: output was truncated by the daemon");
+            }
+            Ok(code.unwrap_or(1))
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+pub async fn workstream_set_notes(
+    port: u16,
+    repo: &str,
+    name: &str,
+    notes: Option<String>,
+) -> Result<()> {
+    let cleared = notes.is_none();
+    let resp = request(
+        port,
+        &ClientMessage::WorkstreamSetNotes {
+            repo: repo.to_string(),
+            name: name.to_string(),
+            notes,
+        },
+    )
+    .await?;
+    match resp {
+        ServerMessage::WorkstreamNotesSet { repo, name } => {
+            if cleared {
+                println!("cleared notes for workstream '{}' in repo '{}'", name, repo);
+            } else {
+                println!("updated notes for workstream '{}' in repo '{}'", name, repo);
+            }
+            Ok(())
+        }
+        ServerMessage::Error { message } => bail!("{}", message),
+        other => bail!("unexpected response: {:?}", other),
+    }
+}
+
+pub async fn workstream_prune(port: u16, repo: Option<&str>, dry_run: bool) -> Result<()> {
+    let resp = request(
+        port,
+        &ClientMessage::WorkstreamPrune {
+            repo: repo.map(String::from),
+            dry_run,
+        },
+    )
+    .await?;
+    match resp {
+        ServerMessage::WorkstreamsPruned {
+            removed,
+            kept_dirty,
+            kept_branches,
+            in_use,
+            dry_run,
+        } => {
+            let verb = if dry_run { "would remove" } else { "removed" };
+            for ws in &removed {
+                println!("{} workstream '{}' from repo '{}'", verb, ws.name, ws.repo);
+            }
+            for ws in &kept_dirty {
+                println!(
+                    "kept workstream '{}' in repo '{}': it has uncommitted changes",
+                    ws.name, ws.repo
+                );
+            }
+            let verb = if dry_run { "would keep" } else { "kept" };
+            for ws in &kept_branches {
+                println!(
+                    "{} branch '{}' of workstream '{}': it has commits that aren't merged or pushed",
+                    verb, ws.branch, ws.name
+                );
+            }
+            if removed.is_empty() && kept_dirty.is_empty() {
+                if in_use == 0 {
+                    println!("no workstreams to prune");
+                } else {
+                    println!("nothing to prune; every workstream is in use");
+                }
+            }
             Ok(())
         }
         ServerMessage::Error { message } => bail!("{}", message),
@@ -22,6 +22,10 @@ pub enum ClientMessage {
         id: Uuid,
         cols: u16,
         rows: u16,
+        /// Output offset the client already has (from a previous
+        /// `Attached`); only bytes after it are replayed.
+        #[serde(default)]
+        since: Option<u64>,
     },
     DetachSession,
     ResizeSession {
@@ -32,7 +36,18 @@ pub enum ClientMessage {
     KillSession {
         id: Uuid,
     },
-    AgentList,
+    /// Forcibly end shell sessions, all of them or only those started in
+    /// `repo`. Sessions running an agent are left alone.
+    ResetShells {
+        #[serde(default)]
+        repo: Option<String>,
+    },
+    /// Live and exited agents. Last prompts come as a short preview unless
+    /// `full_prompt` is set.
+    AgentList {
+        #[serde(default)]
+        full_prompt: bool,
+    },
     AgentNotifications,
     AgentWatch {
         session_id: Uuid,
@@ -41,32 +56,122 @@ pub enum ClientMessage {
         session_id: Uuid,
         text: String,
     },
+    /// Recent output of an agent's session, for clients that can't attach.
+    AgentLogs {
+        session_id: Uuid,
+        /// Only the last this many lines; everything retained when unset.
+        #[serde(default)]
+        lines: Option<u32>,
+    },
+    /// Start an ended agent again with its original command, environment
+    /// and directory. Answered with `SessionCreated`.
+    AgentRestart {
+        session_id: Uuid,
+    },
     AgentSpawn {
         repo: String,
         workstream: Option<String>,
+        /// Extra environment variables for the agent process.
+        #[serde(default)]
+        env: Vec<(String, String)>,
+        /// Subdirectory of the repo or worktree to start in.
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+        /// Named entry in the daemon's `agent_profiles` to run instead of
+        /// the repo's agent command.
+        #[serde(default)]
+        profile: Option<String>,
     },
     WorkstreamCreate {
         repo: String,
         name: String,
     },
+    /// One workstream per existing branch, each named after its branch.
+    /// Branches that fail don't affect the others.
+    WorkstreamCreateBatch {
+        repo: String,
+        branches: Vec<String>,
+        /// Fetch each branch from origin first, so a branch pushed since the
+        /// last prefetch is found and checked out at its latest commit.
+        #[serde(default)]
+        fetch_latest: bool,
+    },
     WorkstreamList {
         repo: Option<String>,
+        #[serde(default)]
+        sort: Option<WorkstreamSort>,
+        /// Also report each worktree's git state. Runs git per workstream.
+        #[serde(default)]
+        git_status: bool,
     },
     WorkstreamRemove {
         repo: String,
         name: String,
     },
+    /// Remove every workstream that no session or agent is working in.
+    /// Worktrees with uncommitted changes are kept. With `dry_run`, only
+    /// report what would be removed.
+    WorkstreamPrune {
+        #[serde(default)]
+        repo: Option<String>,
+        #[serde(default)]
+        dry_run: bool,
+    },
+    WorkstreamSetNotes {
+        repo: String,
+        name: String,
+        notes: Option<String>,
+    },
+    /// Change the name vex shows for a workstream. The branch and worktree
+    /// keep their names.
+    WorkstreamRename {
+        repo: String,
+        name: String,
+        new_name: String,
+    },
+    /// Git state of the workstream's worktree.
+    WorkstreamStatus {
+        repo: String,
+        name: String,
+    },
+    /// Where the workstream's worktree is on the daemon's host.
+    WorkstreamPath {
+        repo: String,
+        name: String,
+    },
+    /// Run `command` with `sh -c` in the workstream's worktree and return
+    /// its output. Defaults to a 60 second timeout.
+    WorkstreamExec {
+        repo: String,
+        name: String,
+        command: String,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
     RepoAdd {
         name: String,
         path: PathBuf,
     },
     RepoRemove {
         name: String,
+        /// Remove the repo's workstreams first instead of refusing.
+        #[serde(default)]
+        delete_workstreams: bool,
     },
     RepoList,
     RepoIntrospectPath {
         path: PathBuf,
     },
+    /// Liveness probe; answered with `Pong`.
+    Ping,
+    /// Any idle-mode request, tagged with a client-chosen id so several can
+    /// be in flight on one connection. Replies come back as
+    /// `ServerMessage::Tagged` with the same id, in completion order.
+    /// Untagged requests are still answered one at a time.
+    Tagged {
+        request_id: u64,
+        message: Box<ClientMessage>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -80,6 +185,15 @@ pub enum ServerMessage {
     },
     Attached {
         id: Uuid,
+        /// Output offset of the first replayed byte. Every data frame that
+        /// follows, replay and live output alike, continues from here.
+        #[serde(default)]
+        offset: u64,
+        /// Bytes after the requested `since` that are no longer retained,
+        /// so the replay starts this much later than asked; 0 when nothing
+        /// was lost.
+        #[serde(default)]
+        skipped: u64,
     },
     Detached,
     SessionEnded {
@@ -99,10 +213,17 @@ pub enum ServerMessage {
     },
     AgentListResponse {
         agents: Vec<AgentEntry>,
+        /// Recently ended `AgentSpawn` sessions, newest first.
+        #[serde(default)]
+        exited: Vec<ExitedAgent>,
     },
     AgentPromptSent {
         session_id: Uuid,
     },
+    AgentLogs {
+        session_id: Uuid,
+        content: String,
+    },
     AgentConversationLine {
         session_id: Uuid,
         line: String,
@@ -131,13 +252,65 @@ pub enum ServerMessage {
         name: String,
         worktree_path: PathBuf,
     },
+    WorkstreamsCreated {
+        created: Vec<WorkstreamInfo>,
+        failed: Vec<BranchFailure>,
+    },
     WorkstreamRemoved {
         repo: String,
         name: String,
     },
+    WorkstreamsPruned {
+        /// Removed, or with `dry_run` the ones that would be.
+        removed: Vec<WorkstreamInfo>,
+        /// Unused, but kept because of uncommitted changes.
+        kept_dirty: Vec<WorkstreamInfo>,
+        /// Among `removed`, those whose branch is kept because it has
+        /// commits neither merged into the repo's HEAD nor pushed.
+        #[serde(default)]
+        kept_branches: Vec<WorkstreamInfo>,
+        /// How many were left alone because a session or agent is in them.
+        #[serde(default)]
+        in_use: usize,
+        dry_run: bool,
+    },
     Workstreams {
         workstreams: Vec<WorkstreamInfo>,
     },
+    WorkstreamNotesSet {
+        repo: String,
+        name: String,
+    },
+    WorkstreamRenamed {
+        workstream: WorkstreamInfo,
+    },
+    WorkstreamGitStatus {
+        repo: String,
+        name: String,
+        status: GitState,
+    },
+    WorkstreamPath {
+        path: PathBuf,
+    },
+    ExecResult {
+        stdout: String,
+        stderr: String,
+        /// `None` if the command was killed by a signal.
+        code: Option<i32>,
+        /// Output over the daemon's limit was dropped.
+        #[serde(default)]
+        truncated: bool,
+    },
+    Pong,
+    ShellsReset {
+        ids: Vec<Uuid>,
+    },
+    /// A reply to `ClientMessage::Tagged`. Streaming requests produce
+    /// several of these with the same id.
+    Tagged {
+        request_id: u64,
+        message: Box<ServerMessage>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -157,12 +330,33 @@ pub struct AgentEntry {
     pub cwd: PathBuf,
     pub detected_at: DateTime<Utc>,
     pub needs_intervention: bool,
+    /// Prompts sent through vex since the agent was detected.
+    #[serde(default)]
+    pub prompt_count: u32,
+    #[serde(default)]
+    pub last_prompt_at: Option<DateTime<Utc>>,
+    /// Text of the most recent of those prompts.
+    #[serde(default)]
+    pub last_prompt: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExitedAgent {
+    pub vex_session_id: Uuid,
+    pub command: String,
+    pub cwd: Option<PathBuf>,
+    /// `None` if the agent was killed by a signal (including `session kill`).
+    pub exit_code: Option<i32>,
+    pub ended_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct RepoEntry {
     pub name: String,
     pub path: PathBuf,
+    /// URL of the repo's `origin` remote.
+    #[serde(default)]
+    pub remote_url: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -172,6 +366,65 @@ pub struct WorkstreamInfo {
     pub worktree_path: PathBuf,
     pub branch: String,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Only filled in when `WorkstreamList` asks for `git_status`, and left
+    /// out for worktrees whose status couldn't be read in time.
+    #[serde(default)]
+    pub git: Option<GitState>,
+    /// The worktree directory is gone, e.g. deleted outside vex. Such a
+    /// workstream can only be removed.
+    #[serde(default)]
+    pub missing: bool,
+}
+
+/// A branch `WorkstreamCreateBatch` couldn't make a workstream for.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BranchFailure {
+    pub branch: String,
+    pub message: String,
+}
+
+/// Git state of a worktree.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GitState {
+    /// `None` when HEAD is detached.
+    pub branch: Option<String>,
+    /// Changed and untracked files.
+    pub dirty_files: u32,
+    /// Commits relative to the upstream; `None` without one.
+    pub ahead: Option<u32>,
+    pub behind: Option<u32>,
+}
+
+/// Presentation order for `WorkstreamList`. Applied server-side; the stored
+/// data is never reordered.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkstreamSort {
+    /// Most recently created first.
+    #[default]
+    Created,
+    /// Alphabetical by workstream name.
+    Name,
+    /// Alphabetical by repo, then workstream name.
+    Repo,
+}
+
+impl std::str::FromStr for WorkstreamSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(Self::Created),
+            "name" => Ok(Self::Name),
+            "repo" => Ok(Self::Repo),
+            other => Err(format!(
+                "unknown sort key '{}' (expected created, name or repo)",
+                other
+            )),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -263,6 +516,13 @@ mod tests {
                 id: Uuid::nil(),
                 cols: 120,
                 rows: 40,
+                since: None,
+            },
+            ClientMessage::AttachSession {
+                id: Uuid::nil(),
+                cols: 120,
+                rows: 40,
+                since: Some(4096),
             },
             ClientMessage::DetachSession,
             ClientMessage::ResizeSession {
@@ -271,44 +531,113 @@ mod tests {
                 rows: 24,
             },
             ClientMessage::KillSession { id: Uuid::nil() },
-            ClientMessage::AgentList,
+            ClientMessage::AgentList { full_prompt: true },
             ClientMessage::AgentNotifications,
             ClientMessage::AgentWatch {
                 session_id: Uuid::nil(),
             },
+            ClientMessage::AgentRestart {
+                session_id: Uuid::nil(),
+            },
             ClientMessage::AgentPrompt {
                 session_id: Uuid::nil(),
                 text: "hello".into(),
             },
+            ClientMessage::AgentLogs {
+                session_id: Uuid::nil(),
+                lines: Some(50),
+            },
             ClientMessage::AgentSpawn {
                 repo: "vex".into(),
                 workstream: None,
+                env: vec![],
+                cwd: None,
+                profile: None,
             },
             ClientMessage::AgentSpawn {
                 repo: "vex".into(),
                 workstream: Some("feature-x".into()),
+                env: vec![("OPENAI_BASE_URL".into(), "http://localhost:8080".into())],
+                cwd: Some(PathBuf::from("services/worker")),
+                profile: Some("opus".into()),
             },
             ClientMessage::WorkstreamCreate {
                 repo: "vex".into(),
                 name: "feature-x".into(),
             },
-            ClientMessage::WorkstreamList { repo: None },
+            ClientMessage::WorkstreamCreateBatch {
+                repo: "vex".into(),
+                branches: vec!["fix/login".into(), "gone".into()],
+                fetch_latest: true,
+            },
+            ClientMessage::WorkstreamList {
+                repo: None,
+                sort: None,
+                git_status: false,
+            },
             ClientMessage::WorkstreamList {
                 repo: Some("vex".into()),
+                sort: Some(WorkstreamSort::Name),
+                git_status: true,
             },
             ClientMessage::WorkstreamRemove {
                 repo: "vex".into(),
                 name: "feature-x".into(),
             },
+            ClientMessage::WorkstreamPrune {
+                repo: Some("vex".into()),
+                dry_run: true,
+            },
+            ClientMessage::WorkstreamSetNotes {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+                notes: Some("reviewing auth refactor".into()),
+            },
+            ClientMessage::WorkstreamSetNotes {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+                notes: None,
+            },
+            ClientMessage::WorkstreamRename {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+                new_name: "feature-y".into(),
+            },
+            ClientMessage::WorkstreamStatus {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+            },
+            ClientMessage::WorkstreamPath {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+            },
+            ClientMessage::WorkstreamExec {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+                command: "cargo test".into(),
+                timeout_secs: Some(300),
+            },
             ClientMessage::RepoAdd {
                 name: "vex".into(),
                 path: PathBuf::from("/tmp/vex"),
             },
-            ClientMessage::RepoRemove { name: "vex".into() },
+            ClientMessage::RepoRemove {
+                name: "vex".into(),
+                delete_workstreams: true,
+            },
             ClientMessage::RepoList,
             ClientMessage::RepoIntrospectPath {
                 path: PathBuf::from("/tmp"),
             },
+            ClientMessage::Ping,
+            ClientMessage::ResetShells { repo: None },
+            ClientMessage::ResetShells {
+                repo: Some("vex".into()),
+            },
+            ClientMessage::Tagged {
+                request_id: 7,
+                message: Box::new(ClientMessage::RepoList),
+            },
         ];
         for msg in msgs {
             let json = serde_json::to_string(&msg).unwrap();
@@ -330,7 +659,11 @@ mod tests {
                     client_count: 2,
                 }],
             },
-            ServerMessage::Attached { id: Uuid::nil() },
+            ServerMessage::Attached {
+                id: Uuid::nil(),
+                offset: 4096,
+                skipped: 128,
+            },
             ServerMessage::Detached,
             ServerMessage::SessionEnded {
                 id: Uuid::nil(),
@@ -355,11 +688,25 @@ mod tests {
                     cwd: PathBuf::from("/tmp"),
                     detected_at: Utc::now(),
                     needs_intervention: true,
+                    prompt_count: 2,
+                    last_prompt_at: Some(Utc::now()),
+                    last_prompt: Some("fix the login bug".into()),
+                }],
+                exited: vec![ExitedAgent {
+                    vex_session_id: Uuid::nil(),
+                    command: "claude --model opus".into(),
+                    cwd: Some(PathBuf::from("/tmp")),
+                    exit_code: Some(1),
+                    ended_at: Utc::now(),
                 }],
             },
             ServerMessage::AgentPromptSent {
                 session_id: Uuid::nil(),
             },
+            ServerMessage::AgentLogs {
+                session_id: Uuid::nil(),
+                content: "\x1b[1mdone\x1b[0m\r\n".into(),
+            },
             ServerMessage::AgentConversationLine {
                 session_id: Uuid::nil(),
                 line: "test line".into(),
@@ -376,6 +723,7 @@ mod tests {
                 repos: vec![RepoEntry {
                     name: "vex".into(),
                     path: PathBuf::from("/tmp/vex"),
+                    remote_url: Some("git@github.com:sandipndev/vex.git".into()),
                 }],
             },
             ServerMessage::RepoIntrospected {
@@ -393,6 +741,29 @@ mod tests {
                 repo: "vex".into(),
                 name: "feature-x".into(),
             },
+            ServerMessage::WorkstreamsCreated {
+                created: vec![],
+                failed: vec![BranchFailure {
+                    branch: "gone".into(),
+                    message: "branch 'gone' not found locally or on origin".into(),
+                }],
+            },
+            ServerMessage::WorkstreamsPruned {
+                removed: vec![WorkstreamInfo {
+                    repo: "vex".into(),
+                    name: "old".into(),
+                    worktree_path: PathBuf::from("/tmp/workstreams/vex/old"),
+                    branch: "old".into(),
+                    created_at: Utc::now(),
+                    notes: None,
+                    git: None,
+                    missing: true,
+                }],
+                kept_dirty: vec![],
+                kept_branches: vec![],
+                in_use: 2,
+                dry_run: false,
+            },
             ServerMessage::Workstreams {
                 workstreams: vec![WorkstreamInfo {
                     repo: "vex".into(),
@@ -400,8 +771,59 @@ mod tests {
                     worktree_path: PathBuf::from("/tmp/workstreams/vex/feature-x"),
                     branch: "feature-x".into(),
                     created_at: Utc::now(),
+                    notes: Some("reviewing auth refactor".into()),
+                    git: Some(GitState {
+                        branch: Some("feature-x".into()),
+                        dirty_files: 0,
+                        ahead: None,
+                        behind: None,
+                    }),
+                    missing: false,
                 }],
             },
+            ServerMessage::WorkstreamNotesSet {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+            },
+            ServerMessage::WorkstreamRenamed {
+                workstream: WorkstreamInfo {
+                    repo: "vex".into(),
+                    name: "feature-y".into(),
+                    worktree_path: PathBuf::from("/tmp/workstreams/vex/feature-x"),
+                    branch: "feature-x".into(),
+                    created_at: Utc::now(),
+                    notes: None,
+                    git: None,
+                    missing: false,
+                },
+            },
+            ServerMessage::WorkstreamGitStatus {
+                repo: "vex".into(),
+                name: "feature-x".into(),
+                status: GitState {
+                    branch: None,
+                    dirty_files: 3,
+                    ahead: Some(2),
+                    behind: Some(0),
+                },
+            },
+            ServerMessage::WorkstreamPath {
+                path: PathBuf::from("/home/me/.vex/workstreams/vex/feature-x"),
+            },
+            ServerMessage::ExecResult {
+                stdout: "ok\n".into(),
+                stderr: String::new(),
+                code: Some(0),
+                truncated: false,
+            },
+            ServerMessage::Pong,
+            ServerMessage::ShellsReset {
+                ids: vec![Uuid::nil()],
+            },
+            ServerMessage::Tagged {
+                request_id: 7,
+                message: Box::new(ServerMessage::Pong),
+            },
         ];
         for msg in msgs {
             let json = serde_json::to_string(&msg).unwrap();
@@ -410,6 +832,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn workstream_list_without_sort_deserializes() {
+        let msg: ClientMessage =
+            serde_json::from_str(r#"{"type":"WorkstreamList","repo":null}"#).unwrap();
+        assert_eq!(
+            msg,
+            ClientMessage::WorkstreamList {
+                repo: None,
+                sort: None,
+                git_status: false,
+            }
+        );
+    }
+
+    #[test]
+    fn agent_list_without_full_prompt_deserializes() {
+        let msg: ClientMessage = serde_json::from_str(r#"{"type":"AgentList"}"#).unwrap();
+        assert_eq!(msg, ClientMessage::AgentList { full_prompt: false });
+    }
+
+    #[test]
+    fn timestamps_serialize_as_rfc3339() {
+        let now = Utc::now();
+        let session = serde_json::to_value(SessionInfo {
+            id: Uuid::new_v4(),
+            cols: 80,
+            rows: 24,
+            created_at: now,
+            client_count: 0,
+        })
+        .unwrap();
+        let agent = serde_json::to_value(AgentEntry {
+            vex_session_id: Uuid::new_v4(),
+            claude_session_id: "abc".into(),
+            claude_pid: 1,
+            cwd: PathBuf::from("/tmp"),
+            detected_at: now,
+            needs_intervention: false,
+            prompt_count: 1,
+            last_prompt_at: Some(now),
+            last_prompt: None,
+        })
+        .unwrap();
+        let workstream = serde_json::to_value(WorkstreamInfo {
+            repo: "r".into(),
+            name: "w".into(),
+            worktree_path: PathBuf::from("/tmp/w"),
+            branch: "w".into(),
+            created_at: now,
+            notes: None,
+            git: None,
+            missing: false,
+        })
+        .unwrap();
+
+        for ts in [
+            &session["created_at"],
+            &agent["detected_at"],
+            &agent["last_prompt_at"],
+            &workstream["created_at"],
+        ] {
+            let s = ts.as_str().expect("timestamp should be a string");
+            let parsed = DateTime::parse_from_rfc3339(s).unwrap();
+            assert_eq!(parsed, now);
+        }
+    }
+
+    #[test]
+    fn workstream_sort_from_str() {
+        assert_eq!("created".parse(), Ok(WorkstreamSort::Created));
+        assert_eq!("name".parse(), Ok(WorkstreamSort::Name));
+        assert_eq!("repo".parse(), Ok(WorkstreamSort::Repo));
+        assert!("size".parse::<WorkstreamSort>().is_err());
+    }
+
     #[tokio::test]
     async fn frame_round_trip_control() {
         let (mut client, mut server) = tokio::io::duplex(1024);
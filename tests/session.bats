@@ -15,6 +15,8 @@ setup() {
     TEST_TMPDIR="$(mktemp -d)"
     export VEX_DIR="$TEST_TMPDIR/.vex"
     export VEX_PORT=$((20000 + RANDOM % 10000))
+    # CI containers often run as root
+    export VEX_ALLOW_ROOT=1
 
     "$VEX" daemon start 2>/dev/null
 
@@ -143,10 +145,35 @@ attach_via_pty() {
     [[ "$output" == *"listening"* ]]
 }
 
+@test "daemon logs filters by text and level" {
+    "$VEX" session create >/dev/null
+    run "$VEX" daemon logs --grep "created session"
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"created session"* ]]
+    [[ "$output" != *"listening"* ]]
+
+    run "$VEX" daemon logs --level warn
+    [ "$status" -eq 0 ]
+    [[ "$output" != *"listening"* ]]
+}
+
 @test "daemon status shows running" {
     run vex daemon status
     [ "$status" -eq 0 ]
     [[ "$output" == *"daemon running"* ]]
+    [[ "$output" == *"rtt: "*"ms"* ]]
+    [[ "$output" == *"sessions: 0, agents: 0"* ]]
+}
+
+@test "daemon check passes on clean state and flags corrupt files" {
+    run vex daemon check
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"looks good"* ]]
+
+    echo "{not json" > "$VEX_DIR/repos.json"
+    run vex daemon check
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"repos.json is invalid"* ]]
 }
 
 @test "daemon status shows not running when stopped" {
@@ -462,6 +489,22 @@ attach_via_pty() {
     rm "$VEX_DIR/connect.json"
 }
 
+@test "remote list: VEX_CONFIG overrides connection file" {
+    echo '{"host":"user@workhost","tunnel_port":12345}' > "$TEST_TMPDIR/work.json"
+
+    run env VEX_CONFIG="$TEST_TMPDIR/work.json" "$VEX" remote list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"user@workhost"* ]]
+
+    # Default location is untouched
+    run "$VEX" remote list
+    [[ "$output" == *"not connected"* ]]
+
+    run vex --config "$TEST_TMPDIR/work.json" remote disconnect
+    [ "$status" -eq 0 ]
+    [ ! -f "$TEST_TMPDIR/work.json" ]
+}
+
 @test "commands use tunnel port when connected" {
     # Create a fake connection pointing to our actual daemon port
     mkdir -p "$VEX_DIR"
@@ -587,6 +630,12 @@ attach_via_pty() {
     [[ "$output" == *"compdef"* ]]
 }
 
+@test "piping into a closed reader exits without panicking" {
+    run bash -c '"$VEX" completions bash | head -c0'
+    [[ "$output" != *"panicked"* ]]
+    [[ "$output" != *"Broken pipe"* ]]
+}
+
 # ═══════════════════════════════════════════════════════════════════
 #  Repo management
 # ═══════════════════════════════════════════════════════════════════
@@ -634,6 +683,19 @@ attach_via_pty() {
     [[ "$output" == *"no repos"* ]]
 }
 
+@test "repo list --fast is invalidated by repo add" {
+    run "$VEX" repo list --fast
+    [[ "$output" == *"no repos"* ]]
+    [ -f "$VEX_DIR/cache.json" ]
+
+    mkdir -p "$TEST_TMPDIR/myrepo"
+    "$VEX" repo add myrepo "$TEST_TMPDIR/myrepo"
+    [ ! -f "$VEX_DIR/cache.json" ]
+
+    run "$VEX" repo list --fast
+    [[ "$output" == *"myrepo"* ]]
+}
+
 @test "repo remove: nonexistent fails" {
     run vex repo remove nope
     [ "$status" -ne 0 ]
@@ -761,3 +823,473 @@ setup_git_repo() {
     [ "$status" -eq 0 ]
     [[ "$output" == *"no workstreams"* ]]
 }
+
+@test "workstream note: set, list, and clear" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+
+    run "$VEX" workstream note -r myrepo feat-1 "reviewing auth refactor"
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"updated notes"* ]]
+
+    run "$VEX" workstream list
+    [[ "$output" == *"NOTES"* ]]
+    [[ "$output" == *"reviewing auth refactor"* ]]
+
+    run "$VEX" workstream note -r myrepo feat-1
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"cleared notes"* ]]
+
+    run "$VEX" workstream list
+    [[ "$output" != *"reviewing auth refactor"* ]]
+}
+
+@test "workstream note: nonexistent workstream fails" {
+    setup_git_repo
+    run vex workstream note -r myrepo nope "hello"
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"not found"* ]]
+}
+
+@test "workstream list --sort name orders alphabetically" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo zeta
+    "$VEX" workstream create -r myrepo alpha
+
+    run "$VEX" workstream list --sort name
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"alpha"*"zeta"* ]]
+
+    run "$VEX" workstream list --sort created
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"alpha"*"zeta"* ]]
+}
+
+@test "workstream list --sort rejects unknown keys" {
+    run vex workstream list --sort size
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"unknown sort key"* ]]
+}
+
+@test "agent spawn --cwd rejects paths outside the repo" {
+    setup_git_repo
+    run vex agent spawn -r myrepo --cwd ../elsewhere
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"relative path inside"* ]]
+
+    run vex agent spawn -r myrepo --cwd missing-dir
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"does not exist"* ]]
+}
+
+@test "repo remove refuses while workstreams exist" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+
+    run vex repo remove myrepo
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"still has workstreams: feat-1"* ]]
+
+    run "$VEX" repo list
+    [[ "$output" == *"myrepo"* ]]
+}
+
+@test "repo remove --delete-workstreams cascades" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+    local wt_path="$VEX_DIR/workstreams/myrepo/feat-1"
+    [ -d "$wt_path" ]
+
+    run "$VEX" repo remove myrepo --delete-workstreams
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"removed repo"* ]]
+
+    [ ! -d "$wt_path" ]
+    run git -C "$TEST_TMPDIR/myrepo" branch --list feat-1
+    [ -z "$output" ]
+    run "$VEX" workstream list
+    [[ "$output" == *"no workstreams"* ]]
+}
+
+@test "workstream create rolls back when a hook fails" {
+    setup_git_repo
+    "$VEX" daemon stop
+    cat > "$VEX_DIR/config.yml" <<'YAML'
+hooks:
+  timeout_secs: 2
+  on_workstream_create:
+    do:
+      - "echo 'registry unreachable' >&2; exit 1"
+YAML
+    "$VEX" daemon start 2>/dev/null
+
+    run vex workstream create -r myrepo feat-1
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"failed (exit code 1)"* ]]
+    [[ "$output" == *"registry unreachable"* ]]
+
+    [ ! -d "$VEX_DIR/workstreams/myrepo/feat-1" ]
+    run git -C "$TEST_TMPDIR/myrepo" branch --list feat-1
+    [ -z "$output" ]
+}
+
+@test "workstream create kills hooks that exceed the timeout" {
+    setup_git_repo
+    "$VEX" daemon stop
+    cat > "$VEX_DIR/config.yml" <<'YAML'
+hooks:
+  timeout_secs: 1
+  on_workstream_create:
+    do:
+      - "sleep 30"
+YAML
+    "$VEX" daemon start 2>/dev/null
+
+    run vex workstream create -r myrepo feat-1
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"timed out after 1s"* ]]
+    [ ! -d "$VEX_DIR/workstreams/myrepo/feat-1" ]
+}
+
+@test "workstream remove runs on_workstream_remove hooks inside the worktree" {
+    setup_git_repo
+    "$VEX" daemon stop
+    cat > "$VEX_DIR/config.yml" <<'YAML'
+hooks:
+  on_workstream_remove:
+    do:
+      - 'pwd -P > "$VEX_DIR/removed-from"'
+      - "exit 1"
+YAML
+    "$VEX" daemon start 2>/dev/null
+    "$VEX" workstream create -r myrepo feat-1
+    local wt_path="$VEX_DIR/workstreams/myrepo/feat-1"
+
+    # A failing hook is logged but does not block the removal
+    run "$VEX" workstream remove -r myrepo feat-1
+    [ "$status" -eq 0 ]
+    [ ! -d "$wt_path" ]
+    [ "$(cat "$VEX_DIR/removed-from")" = "$(cd "$VEX_DIR" && pwd -P)/workstreams/myrepo/feat-1" ]
+}
+
+@test "workstream create rejects names with shell metacharacters" {
+    setup_git_repo
+    run vex workstream create -r myrepo 'x; touch pwned'
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"invalid workstream name"* ]]
+    [ ! -e "$TEST_TMPDIR/myrepo/pwned" ]
+    [ ! -e pwned ]
+
+    run vex workstream create -r myrepo ../escape
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"invalid workstream name"* ]]
+}
+
+@test "agent prompt refuses sessions without a detected agent" {
+    run "$VEX" session create
+    local id="$output"
+    run vex agent prompt "$id" "hello"
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"no agent running in session $id"* ]]
+}
+
+@test "workstream exec runs in the worktree and relays the exit code" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+
+    run "$VEX" workstream exec -r myrepo feat-1 -- echo hello
+    [ "$status" -eq 0 ]
+    [ "$output" = "hello" ]
+
+    run "$VEX" workstream exec -r myrepo feat-1 -- git rev-parse --abbrev-ref HEAD
+    [ "$output" = "feat-1" ]
+
+    run "$VEX" workstream exec -r myrepo feat-1 -- 'echo failing >&2; exit 3'
+    [ "$status" -eq 3 ]
+    [[ "$output" == *"failing"* ]]
+}
+
+@test "agent spawn rejects unknown profiles" {
+    setup_git_repo
+    run vex agent spawn -r myrepo --profile nope
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"unknown agent profile 'nope'"* ]]
+}
+
+@test "workstream rename keeps the branch and worktree" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+    local wt_path="$VEX_DIR/workstreams/myrepo/feat-1"
+
+    run "$VEX" workstream rename -r myrepo feat-1 auth-rework
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"renamed workstream 'feat-1' to 'auth-rework'"* ]]
+    [ -d "$wt_path" ]
+
+    run "$VEX" workstream list -r myrepo
+    [[ "$output" == *"auth-rework"* ]]
+    [[ "$output" != *"feat-1 "* ]]
+
+    run "$VEX" workstream exec -r myrepo auth-rework -- git rev-parse --abbrev-ref HEAD
+    [ "$output" = "feat-1" ]
+}
+
+@test "daemon reset-shells ends shell sessions" {
+    "$VEX" session create
+    "$VEX" session create
+    run "$VEX" daemon reset-shells
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"reset 2 shell session(s)"* ]]
+
+    run "$VEX" session list
+    [[ "$output" == *"no active sessions"* ]]
+}
+
+@test "workstream create without --repo uses the repo containing cwd" {
+    setup_git_repo
+    mkdir -p "$TEST_TMPDIR/myrepo/src"
+    cd "$TEST_TMPDIR/myrepo/src"
+    run "$VEX" workstream create feat-1
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"for repo 'myrepo'"* ]]
+}
+
+@test "workstream create without --repo refuses an unregistered repo" {
+    mkdir -p "$TEST_TMPDIR/loose"
+    git -C "$TEST_TMPDIR/loose" init --quiet
+    cd "$TEST_TMPDIR/loose"
+    run "$VEX" workstream create feat-1 </dev/null
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"is not a registered repo"* ]]
+}
+
+@test "agent list shows exit codes of ended agents" {
+    setup_git_repo
+    cat > "$VEX_DIR/config.yml" <<'YAML'
+agent_profiles:
+  crash:
+    command: sh -c 'exit 4'
+YAML
+    "$VEX" daemon stop 2>/dev/null
+    "$VEX" daemon start 2>/dev/null
+    "$VEX" agent spawn -r myrepo --profile crash
+    sleep 0.5
+
+    run "$VEX" agent list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"failed (4)"* ]]
+}
+
+@test "workstream list --format renders a template per row" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+    run "$VEX" workstream list --format '{repo}/{name} on {branch}'
+    [ "$status" -eq 0 ]
+    [ "$output" = "myrepo/feat-1 on feat-1" ]
+
+    run "$VEX" workstream list --format '{status}'
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"unknown field '{status}'"*"available: repo, name"* ]]
+}
+
+@test "agent logs prints the tail of an agent's output" {
+    setup_git_repo
+    cat > "$VEX_DIR/config.yml" <<'YAML'
+agent_profiles:
+  chatty:
+    command: sh -c 'echo first; echo second; read _'
+YAML
+    "$VEX" daemon stop 2>/dev/null
+    "$VEX" daemon start 2>/dev/null
+    id=$("$VEX" agent spawn -r myrepo --profile chatty)
+    sleep 0.5
+
+    run "$VEX" agent logs "$id" -n 1
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"second"* ]]
+    [[ "$output" != *"first"* ]]
+}
+
+@test "attach without a terminal passes piped input through" {
+    SID=$("$VEX" session create --shell /bin/sh)
+    run bash -c "printf 'echo VEXPIPED\$((6*7))\nexit\n' | timeout 5 '$VEX' session attach '$SID'"
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"VEXPIPED42"* ]]
+    [[ "$output" == *"ended"* ]]
+}
+
+@test "attach --cols/--rows sets a fixed size" {
+    SID=$("$VEX" session create --shell /bin/sh)
+    run bash -c "printf 'stty size\nexit\n' | timeout 5 '$VEX' session attach '$SID' --cols 132 --rows 50"
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"50 132"* ]]
+}
+
+@test "workstream list --status shows uncommitted changes" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+    touch "$VEX_DIR/workstreams/myrepo/feat-1/new-file"
+
+    run "$VEX" workstream list --status
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"GIT"* ]]
+    [[ "$output" == *"1 changed"* ]]
+
+    run "$VEX" workstream list --format '{name}:{dirty}'
+    [ "$status" -eq 0 ]
+    [ "$output" = "feat-1:1" ]
+}
+
+@test "workstream path prints only the worktree path" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+
+    run "$VEX" workstream path -r myrepo feat-1
+    [ "$status" -eq 0 ]
+    [ "$output" = "$VEX_DIR/workstreams/myrepo/feat-1" ]
+
+    run "$VEX" workstream path -r myrepo nope
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"not found"* ]]
+
+    rm -rf "$VEX_DIR/workstreams/myrepo/feat-1"
+    run "$VEX" workstream path -r myrepo feat-1
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"no longer exists"* ]]
+}
+
+@test "--json prints lists as JSON" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+
+    run "$VEX" workstream list --json
+    [ "$status" -eq 0 ]
+    [[ "${lines[0]}" == "[" ]]
+    [[ "$output" == *'"name": "feat-1"'* ]]
+
+    run "$VEX" --json repo list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *'"name": "myrepo"'* ]]
+
+    run "$VEX" workstream list --json --format '{name}'
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"cannot be used together"* ]]
+}
+
+@test "workstream list flags worktrees deleted outside vex" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo feat-1
+    rm -rf "$VEX_DIR/workstreams/myrepo/feat-1"
+
+    run "$VEX" workstream list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"(missing)"* ]]
+    [[ "$output" == *"vex workstream remove"* ]]
+
+    run "$VEX" workstream remove -r myrepo feat-1
+    [ "$status" -eq 0 ]
+    run "$VEX" workstream list
+    [[ "$output" == *"no workstreams"* ]]
+}
+
+@test "workstream prune keeps worktrees with uncommitted changes" {
+    setup_git_repo
+    "$VEX" workstream create -r myrepo idle
+    "$VEX" workstream create -r myrepo wip
+    touch "$VEX_DIR/workstreams/myrepo/wip/notes.txt"
+
+    run "$VEX" workstream prune --dry-run
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"would remove workstream 'idle'"* ]]
+    [[ "$output" == *"kept workstream 'wip'"* ]]
+    [ -d "$VEX_DIR/workstreams/myrepo/idle" ]
+
+    run "$VEX" workstream prune
+    [ "$status" -eq 0 ]
+    [ ! -d "$VEX_DIR/workstreams/myrepo/idle" ]
+    [ -d "$VEX_DIR/workstreams/myrepo/wip" ]
+}
+
+@test "workstream create --branch checks out existing branches" {
+    setup_git_repo
+    git -C "$TEST_TMPDIR/myrepo" branch fix/login
+
+    run "$VEX" workstream create -r myrepo -b fix/login -b nope
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"created workstream 'fix-login'"* ]]
+    [[ "$output" == *"failed 'nope': branch 'nope' not found"* ]]
+    [ -d "$VEX_DIR/workstreams/myrepo/fix-login" ]
+    [ ! -d "$VEX_DIR/workstreams/myrepo/nope" ]
+
+    "$VEX" workstream remove -r myrepo fix-login
+    git -C "$TEST_TMPDIR/myrepo" rev-parse --verify fix/login
+}
+
+@test "workstream create --branch --fetch finds branches pushed since cloning" {
+    mkdir -p "$TEST_TMPDIR/origin"
+    git -C "$TEST_TMPDIR/origin" init --quiet
+    git -C "$TEST_TMPDIR/origin" -c user.name=test -c user.email=test@test commit --allow-empty -m "init" --quiet
+    git clone --quiet "$TEST_TMPDIR/origin" "$TEST_TMPDIR/myrepo"
+    "$VEX" repo add myrepo "$TEST_TMPDIR/myrepo"
+    git -C "$TEST_TMPDIR/origin" branch review/late
+
+    run "$VEX" workstream create -r myrepo -b review/late
+    [ "$status" -ne 0 ]
+
+    run "$VEX" workstream create -r myrepo -b review/late --fetch
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"created workstream 'review-late'"* ]]
+    [ -d "$VEX_DIR/workstreams/myrepo/review-late" ]
+}
+
+@test "agent spawn names a missing agent binary" {
+    setup_git_repo
+    echo 'default_agent_command: nosuch-agent --flag' > "$VEX_DIR/config.yml"
+    "$VEX" daemon stop 2>/dev/null
+    "$VEX" daemon start 2>/dev/null
+
+    run "$VEX" agent spawn -r myrepo
+    [ "$status" -ne 0 ]
+    [[ "$output" == *"agent binary 'nosuch-agent' not found in PATH"* ]]
+    run "$VEX" session list
+    [[ "$output" == *"no active sessions"* ]]
+}
+
+@test "agent restart reruns an ended agent" {
+    setup_git_repo
+    cat > "$VEX_DIR/config.yml" <<'YAML'
+agent_profiles:
+  crash:
+    command: sh -c 'exit 4'
+YAML
+    "$VEX" daemon stop 2>/dev/null
+    "$VEX" daemon start 2>/dev/null
+    id=$("$VEX" agent spawn -r myrepo --profile crash)
+    sleep 0.5
+
+    run "$VEX" agent restart "${id:0:8}"
+    [ "$status" -eq 0 ]
+    [ "$output" != "$id" ]
+    sleep 0.5
+
+    run "$VEX" agent list
+    [ "$(grep -c 'failed (4)' <<< "$output")" -eq 2 ]
+}
+
+@test "workstream prune keeps branches with unmerged work" {
+    run "$VEX" workstream prune
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"no workstreams to prune"* ]]
+
+    setup_git_repo
+    "$VEX" workstream create -r myrepo spike
+    git -C "$VEX_DIR/workstreams/myrepo/spike" -c user.name=test -c user.email=test@test \
+        commit --allow-empty -m "unmerged" --quiet
+
+    run "$VEX" workstream prune
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"removed workstream 'spike'"* ]]
+    [[ "$output" == *"kept branch 'spike'"* ]]
+    [ ! -d "$VEX_DIR/workstreams/myrepo/spike" ]
+    git -C "$TEST_TMPDIR/myrepo" rev-parse --verify spike
+}